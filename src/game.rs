@@ -7,10 +7,9 @@ extern crate serde_json;
 
 use self::parking_lot::Mutex;
 use std::sync::Arc;
-use std::sync::atomic::Ordering;
 use std::thread;
 use std::time::{Instant, Duration};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::result::Result;
 use std::error;
 use std::ops::Neg;
@@ -29,13 +28,15 @@ use winit::{Window, WindowBuilder};
 use buffer::CpuAccessibleBufferAutoPool;
 use geometry::VertexPositionColorAlpha;
 use geometry::Mesh;
-use geometry::Material;
 use renderer::Renderer;
 use input::InputState;
 use world::Dimension;
+use world::dimension::DimensionID;
+use world::generators::{GeneratorRegistry, sub_seed};
+use world::storage::{WorldStorage, WorldInfo, DimensionInfo};
 use registry::DimensionRegistry;
 use player::PlayerController;
-use world::dimension::{CHUNK_STATE_DIRTY, CHUNK_STATE_WRITING, CHUNK_STATE_CLEAN};
+use world::dimension::{ChunkEntry, ChunkEvent};
 
 use mesh_simplifier::*;
 use voxel::voxelmath::*;
@@ -46,7 +47,9 @@ use util::logger::*;
 use util::event::*;
 
 use world::block::Chunk;
-use world::block::BlockID;
+use world::block::BlockStateID;
+use world::block::{MASTER_BLOCK_REGISTRY, BLOCK_DEFINITIONS_DIR, AIR};
+use world::blockmodel::BLOCK_MODELS_DIR;
 
 use network;
 
@@ -78,6 +81,15 @@ pub type Port = u16;
 
 pub type PlayerPosition = (f32, f32, f32);
 
+/// A voxel event, and the dimension it happened in.
+pub type DimensionVoxelEvent = (DimensionID, VoxelEvent<BlockStateID, i32>);
+
+/// The dimensions every world starts out with: ID, name, generator spec (see GeneratorRegistry), chunk size.
+fn default_dimensions() -> Vec<(DimensionID, &'static str, &'static str, VoxelSize<u32>)> {
+    vec![(0, "Overworld", "terrain", vpos!(16, 16, 16)),
+        (1, "Lowlands", "perlin:0.003", vpos!(32, 16, 32))]
+}
+
 #[derive(PartialEq, Eq)]
 pub enum GameMode {
     Singleplayer,
@@ -92,19 +104,30 @@ pub struct GameClient {
     prev_time: Instant,
     input_state: InputState,
     player: PlayerController,
-    pending_meshes : Vec<(VoxelPos<i32>, PendingMesh, Instant)>,
-    chunk_meshes: HashMap<VoxelPos<i32>, Mesh>,
-    voxel_event_sender : Sender<VoxelEvent<BlockID, i32>>,
-    voxel_event_receiver : Receiver<VoxelEvent<BlockID, i32>>,
+    /// Meshes being built on other threads, along with the chunk and the chunk revision they're being built from.
+    pending_meshes : Vec<((DimensionID, VoxelPos<i32>), Arc<ChunkEntry>, u64, PendingMesh, Instant)>,
+    chunk_meshes: HashMap<(DimensionID, VoxelPos<i32>), Mesh>,
+    /// Chunks which might need a new mesh, because of a ChunkEvent about them or one of their neighbours.
+    remesh_queue: HashSet<(DimensionID, VoxelPos<i32>)>,
+    /// The dimension we were in, and the block the camera was in, when the translucent faces of chunk_meshes were last sorted.
+    translucent_sorted_for: Option<(DimensionID, VoxelPos<i32>)>,
+    voxel_event_sender : Sender<DimensionVoxelEvent>,
+    voxel_event_receiver : Receiver<DimensionVoxelEvent>,
     net: network::Client,
+    chunk_event_receivers : HashMap<DimensionID, Receiver<ChunkEvent>>,
+    /// When we last told the server where we are, and where that was. None if we never have.
+    last_position_update: Option<(Instant, Point3<f32>)>,
 }
 
+/// How often a client connected to a server tells it where its player is, if the player has moved.
+const POSITION_UPDATE_INTERVAL : Duration = Duration::from_millis(100);
+
 /// Main type for the game. `Game::new().run()` runs the game.
 pub struct Game {
     dimension_registry: DimensionRegistry,
-    event_bus: SimpleEventBus<VoxelEvent<BlockID, i32>>,
-    voxel_event_sender : Sender<VoxelEvent<BlockID, i32>>,
-    voxel_event_receiver : Receiver<VoxelEvent<BlockID, i32>>,
+    event_bus: SimpleEventBus<DimensionVoxelEvent>,
+    voxel_event_sender : Sender<DimensionVoxelEvent>,
+    voxel_event_receiver : Receiver<DimensionVoxelEvent>,
     current_server_tick : u64,
     last_tick: Instant,
     since_tick: Duration,
@@ -114,8 +137,9 @@ pub struct Game {
 }
 
 impl Game {
-    /// Creates a new `Game`.
-    pub fn new(mode : GameMode) -> Game {
+    /// Creates a new `Game`. Unless we're joining a server, the world is saved to and loaded from world_path.
+    /// seed and generator are only used if we're creating a new world. generator picks the first dimension's generator.
+    pub fn new(mode : GameMode, world_path : &str, seed : Option<u64>, generator : Option<&str>) -> Game {
 
         let is_server = match mode {
             GameMode::Server(_) => true,
@@ -124,10 +148,80 @@ impl Game {
         let since_tick = Duration::new(0,0);
         let last_tick = Instant::now();
 
+        // Generators and meshing both need to know about our blocks, so this comes first. Blocks can use models, so those come before them.
+        let model_count = MASTER_BLOCK_REGISTRY.write().load_models(BLOCK_MODELS_DIR).map_err( |err|
+                                 {error!("Could not load block models from {}: {}", BLOCK_MODELS_DIR, err); panic!();}).unwrap();
+        info!("Loaded {} block models", model_count);
+        let block_count = MASTER_BLOCK_REGISTRY.write().load_definitions(BLOCK_DEFINITIONS_DIR).map_err( |err|
+                                 {error!("Could not load block definitions from {}: {}", BLOCK_DEFINITIONS_DIR, err); panic!();}).unwrap();
+        info!("Loaded {} block definitions", block_count);
+
+        // If we're joining a server, the server owns the world and we don't save anything.
+        // It tells us its seed and dimensions when we join, so we generate the same terrain it does.
+        let (net, joined) = match mode {
+            GameMode::JoinServer(addr) => {
+                let mut net = network::Client::new();
+                let description = net.connect(addr).map_err( |err|
+                                 {error!("Could not join server at {}: {}", addr, err); panic!();}).unwrap();
+                (Some(net), Some(description))
+            },
+            _ => (None, None),
+        };
+        let world = match mode {
+            GameMode::JoinServer(_) => None,
+            _ => Some(WorldStorage::open_or_create(world_path, WorldInfo { seed : seed.unwrap_or_else(::rand::random) },
+                                                             &MASTER_BLOCK_REGISTRY.read()).map_err( |err|
+                                 {error!("Could not open world at {}: {}", world_path, err); panic!();}).unwrap()),
+        };
+        let mut stores = HashMap::new();
+        let description = match joined {
+            Some(description) => {
+                if seed.is_some() || generator.is_some() {
+                    warn!("Joining a server, so its seed and generators are used instead of ours.");
+                }
+                description
+            },
+            None => {
+                let world = world.as_ref().unwrap();
+                if let Some(seed) = seed {
+                    if seed != world.info.seed {
+                        warn!("The world at {} already has seed {}, ignoring seed {}.", world_path, world.info.seed, seed);
+                    }
+                }
+                let mut dimensions = Vec::new();
+                for (id, name, default_generator, chunk_size) in default_dimensions() {
+                    let requested_generator = match generator {
+                        Some(generator) if id == 0 => generator,
+                        _ => default_generator,
+                    };
+                    let new_info = DimensionInfo { name : name.to_owned(), chunk_size : chunk_size, generator : requested_generator.to_owned() };
+                    let (store, mut info) = world.open_dimension(id, new_info).map_err( |err|
+                                 {error!("Could not open dimension {} of world at {}: {}", id, world_path, err); panic!();}).unwrap();
+                    if info.generator.is_empty() {
+                        info.generator = default_generator.to_owned();
+                    }
+                    if id == 0 && generator.is_some() && generator != Some(info.generator.as_str()) {
+                        warn!("Dimension {} of the world at {} already uses generator {}, ignoring generator {}.", id, world_path, info.generator, generator.unwrap());
+                    }
+                    stores.insert(id, store);
+                    dimensions.push((id, info));
+                }
+                network::WorldDescription { seed : world.info.seed, dimensions : dimensions }
+            },
+        };
+        let generators = GeneratorRegistry::default();
         let mut dimension_registry = DimensionRegistry::new();
-        let dimension = Dimension::new();
-        dimension_registry.dimensions.insert(0, dimension);
-        let mut bus : SimpleEventBus<VoxelEvent<BlockID, i32>> = SimpleEventBus::new();
+        for &(id, ref info) in description.dimensions.iter() {
+            // Each dimension gets its own seed, so no two dimensions share terrain.
+            let dimension_generator = generators.create(&info.generator, sub_seed(description.seed, id as u64)).map_err( |err|
+                                 {error!("Could not create generator {} for dimension {}: {}", info.generator, id, err); panic!();}).unwrap();
+            let mut dimension = Dimension::new(id, &info.name, dimension_generator, info.chunk_size);
+            if let Some(store) = stores.remove(&id) {
+                dimension.set_storage(store);
+            }
+            dimension_registry.add(dimension);
+        }
+        let mut bus : SimpleEventBus<DimensionVoxelEvent> = SimpleEventBus::new();
         
         let sender = bus.get_sender();
         let (receiver, _) = bus.subscribe(); // We don't need the ID since we're never going to remove this channel until the game terminates. 
@@ -153,10 +247,9 @@ impl Game {
             let voxel_event_sender = sender.clone();
             let (voxel_event_receiver, _) = bus.subscribe(); // We don't need the ID since we're never going to remove this channel until the game terminates.
             surface.window().hide_cursor(true);
-            let mut net = network::Client::new();
-            if let GameMode::JoinServer(addr) = mode {
-                net.connect(addr).unwrap();
-            }
+            let chunk_event_receivers : HashMap<DimensionID, Receiver<ChunkEvent>> = dimension_registry.dimensions.iter_mut()
+                .map(|(id, dimension)| (*id, dimension.chunk_events.subscribe().0)).collect();
+            let net = net.unwrap_or_else(network::Client::new);
 
             return Game {
                 dimension_registry: dimension_registry,
@@ -175,9 +268,13 @@ impl Game {
                     player,
                     pending_meshes,
                     chunk_meshes,
+                    remesh_queue: HashSet::new(),
+                    translucent_sorted_for: None,
                     voxel_event_sender,
                     voxel_event_receiver,
                     net,
+                    chunk_event_receivers,
+                    last_position_update: None,
                 }),
                 net_srv : None,
                 mode : mode,
@@ -195,7 +292,7 @@ impl Game {
                     last_tick : last_tick, 
                     since_tick : since_tick,
                     c : None,
-                    net_srv : Some(network::Server::new(addr).map_err( |err|
+                    net_srv : Some(network::Server::new(addr, description).map_err( |err|
                                  {error!("{}", err); panic!();}).unwrap()),
                     mode : mode,
                 };
@@ -216,8 +313,16 @@ impl Game {
 
             //Serverside chunk stuff.
             if let GameMode::Server(_ip) = self.mode {
-                //let player_positions = self.players.iter().map(|player| { player.pos.into() }).collect();
-                self.dimension_registry.get_mut(0).unwrap().load_unload_chunks_clientside(Point3{x:0.0,y:0.0,z:0.0});
+                let mut player_positions : HashMap<DimensionID, Vec<Point3<f32>>> = HashMap::new();
+                if let Some(ref srv) = self.net_srv {
+                    for (dimension_id, pos) in srv.player_positions() {
+                        player_positions.entry(dimension_id).or_insert(Vec::new()).push(Point3::new(pos[0], pos[1], pos[2]));
+                    }
+                }
+                for dimension in self.dimension_registry.dimensions.values_mut() {
+                    let positions = player_positions.remove(&dimension.id).unwrap_or(Vec::new());
+                    dimension.load_unload_chunks_serverside(positions);
+                }
             }
 
             //Handle networking if we're a server.
@@ -240,16 +345,16 @@ impl Game {
             self.last_tick = Instant::now();
             self.since_tick += elapsed;
 
-            let mut events_from_clients : Vec<(network::Identity, VoxelEvent<BlockID, i32>)> = Vec::new();
+            let mut events_from_clients : Vec<(network::Identity, DimensionVoxelEvent)> = Vec::new();
             // Handle voxel events we got from these clients.
             if self.net_srv.is_some() {
                 let mut srv = self.net_srv.take().unwrap();
                 for pak in srv.poll() {
-                    if let network::ToServerPacketData::VoxEv(event) = pak.pak.data {
+                    if let network::ToServerPacketData::VoxEv(dimension_id, event) = pak.pak.data {
                         //Route voxel events through our own instance of the engine.
-                        self.voxel_event_sender.send(event.clone()).unwrap();
+                        self.voxel_event_sender.send((dimension_id, event.clone())).unwrap();
                         // Queue this event to see if it's valid.
-                        events_from_clients.push((pak.client_id, event.clone()));
+                        events_from_clients.push((pak.client_id, (dimension_id, event.clone())));
                     }
                 }
                 //Put it back.
@@ -267,9 +372,13 @@ impl Game {
             }
             // Move our Voxel Events along.
             self.event_bus.process();
-            for event in self.voxel_event_receiver.try_iter().collect::<Vec<VoxelEvent<BlockID, i32>>>(){
-                trace!("Got event: {:?}", event); 
-                match self.dimension_registry.get_mut(0).unwrap().apply_event(event.clone()) {
+            for (dimension_id, event) in self.voxel_event_receiver.try_iter().collect::<Vec<DimensionVoxelEvent>>(){
+                trace!("Got event in dimension {}: {:?}", dimension_id, event); 
+                let dimension = match self.dimension_registry.get_mut(dimension_id) {
+                    Some(dimension) => dimension,
+                    None => { warn!("Got a voxel event for dimension {}, which does not exist.", dimension_id); continue; },
+                };
+                match dimension.apply_event(event.clone()) {
                     Ok(_) => {
                         // We have succeeded in applying this event to our world, so it's valid. Record it, tell the players about it.
                         //self.event_history.push(event.clone());
@@ -277,11 +386,11 @@ impl Game {
                         if self.net_srv.is_some() {
                             let mut srv = self.net_srv.take().unwrap();
                             for pak in srv.poll() {
-                                if let network::ToServerPacketData::VoxEv(event) = pak.pak.data {
+                                if let network::ToServerPacketData::VoxEv(dimension_id, event) = pak.pak.data {
                                     srv.queue_broadcast(
                                         network::QualifiedToClientPacket{client_id:pak.client_id, 
                                             pak: network::ToClientPacket {
-                                                data: network::ToClientPacketData::VoxEv(event),
+                                                data: network::ToClientPacketData::VoxEv(dimension_id, event),
                                     },});
                                 }
                             }
@@ -298,11 +407,22 @@ impl Game {
                 }
             }
 
+            for dimension in self.dimension_registry.dimensions.values_mut() {
+                dimension.process_chunk_events();
+            }
+
             // Do clientsided things.
             if self.c.is_some() {
                 let mut client = self.c.take().unwrap();
-                #[allow(unused_mut)] //This will probably need to be mutable in the future.
-                self.dimension_registry.get_mut(0).unwrap().load_unload_chunks_clientside(client.player.position.clone());
+                for dimension in self.dimension_registry.dimensions.values_mut() {
+                    if dimension.id == client.player.dimension_id {
+                        dimension.load_unload_chunks_clientside(client.player.position.clone());
+                    } else {
+                        // Nobody is here, so this unloads everything.
+                        dimension.load_unload_chunks(&[]);
+                    }
+                    dimension.process_chunk_events();
+                }
                 match client.update(&self.dimension_registry) {
                     Ok(keep_running) => running = keep_running,
                     Err(error) => error!("Encountered an error in tick {} in client mainloop: {}", self.current_server_tick, error),
@@ -310,6 +430,9 @@ impl Game {
                 self.c = Some(client); // Take ownership again
             }
         }
+        for dimension in self.dimension_registry.dimensions.values() {
+            dimension.save_all();
+        }
     }
 }
 
@@ -379,17 +502,21 @@ impl GameClient {
                                 let mut raycast = VoxelRaycast::new(self.player.position, forward);
                                 let mut continue_raycast = true;
                                 while continue_raycast {
-                                    match dimension_registry.get(0).unwrap().get(raycast.pos) {
+                                    match dimension_registry.get(self.player.dimension_id).unwrap().get(raycast.pos) {
                                         Ok(voxel) => {
-                                            // Is it not air?
-                                            if voxel != 0 {
-                                                let event = VoxelEvent::SetOne(OneVoxelChange{ new_value : 0, pos : raycast.pos});
-                                                self.voxel_event_sender.try_send(event.clone())?;
+                                            // Did we hit something?
+                                            if MASTER_BLOCK_REGISTRY.read().is_hit_by_ray(voxel, raycast.pos, self.player.position, forward) {
                                                 continue_raycast = false;
+                                                // Some blocks can't be broken.
+                                                if !MASTER_BLOCK_REGISTRY.read().def_for_state(voxel).unwrap().is_breakable() {
+                                                    break;
+                                                }
+                                                let event = VoxelEvent::SetOne(OneVoxelChange{ new_value : AIR, pos : raycast.pos});
+                                                self.voxel_event_sender.try_send((self.player.dimension_id, event.clone()))?;
 
                                                 //Let the server know (if we're connected to one).
                                                 self.net.send_packet(network::ToServerPacket{
-                                                    data: network::ToServerPacketData::VoxEv(event.clone())})?;
+                                                    data: network::ToServerPacketData::VoxEv(self.player.dimension_id, event.clone())})?;
                                             }
                                         },
                                         Err(_) => continue_raycast = false, //We've left the currently-loaded chunks.
@@ -405,10 +532,10 @@ impl GameClient {
                                 let mut continue_raycast = true;
                                 //let mut counter = 0;
                                 while continue_raycast {
-                                    match dimension_registry.get(0).unwrap().get(raycast.pos) {
+                                    match dimension_registry.get(self.player.dimension_id).unwrap().get(raycast.pos) {
                                         Ok(voxel) => {
-                                            // Is it not air?
-                                            if voxel != 0 {
+                                            // Did we hit something?
+                                            if MASTER_BLOCK_REGISTRY.read().is_hit_by_ray(voxel, raycast.pos, self.player.position, forward) {
                                                 self.player.selected_block = voxel;
                                                 continue_raycast = false;
                                             }
@@ -434,17 +561,22 @@ impl GameClient {
                                 let mut continue_raycast = true;
                                 //let mut counter = 0;
                                 while continue_raycast {
-                                    match dimension_registry.get(0).unwrap().get(raycast.pos) {
+                                    match dimension_registry.get(self.player.dimension_id).unwrap().get(raycast.pos) {
                                         Ok(voxel) => {
-                                            // Is it not air?
-                                            if voxel != 0 {
+                                            // Did we hit something?
+                                            if MASTER_BLOCK_REGISTRY.read().is_hit_by_ray(voxel, raycast.pos, self.player.position, forward) {
                                                 let adjacent_pos = raycast.pos.get_neighbor(raycast.get_last_direction().opposite());
-                                                let event = VoxelEvent::SetOne(OneVoxelChange{ new_value : self.player.selected_block, pos : adjacent_pos});
-                                                self.voxel_event_sender.try_send(event.clone())?;
+                                                // Blocks which face some way (like stairs) face the way we're looking.
+                                                let facing = if forward.x.abs() > forward.z.abs() { if forward.x > 0.0 { "east" } else { "west" } }
+                                                             else if forward.z > 0.0 { "south" } else { "north" };
+                                                let new_value = MASTER_BLOCK_REGISTRY.read().with_property(self.player.selected_block, "facing", facing)
+                                                    .unwrap_or(self.player.selected_block);
+                                                let event = VoxelEvent::SetOne(OneVoxelChange{ new_value : new_value, pos : adjacent_pos});
+                                                self.voxel_event_sender.try_send((self.player.dimension_id, event.clone()))?;
                                                 continue_raycast = false;
                                                 //Let the server know (if we're connected to one).
                                                 self.net.send_packet(network::ToServerPacket{
-                                                    data: network::ToServerPacketData::VoxEv(event.clone())})?;
+                                                    data: network::ToServerPacketData::VoxEv(self.player.dimension_id, event.clone())})?;
                                             }
                                         },
                                         Err(_) => continue_raycast = false, //We've left the currently-loaded chunks.
@@ -463,6 +595,20 @@ impl GameClient {
                     } 
                     if inp.virtual_keycode == Some(VirtualKeyCode::E) && inp.state == ::winit::ElementState::Pressed {
                         println!("{:?}", self.player.position);
+                        let dimension = dimension_registry.get(self.player.dimension_id).unwrap();
+                        info!("In dimension {} ({}). {} chunks loaded, {} of them uniform", dimension.id, dimension.name,
+                                 dimension.chunks.len(), dimension.uniform_chunk_count());
+                    }
+                    if inp.virtual_keycode == Some(VirtualKeyCode::N) && inp.state == ::winit::ElementState::Pressed {
+                        // Move to the next dimension.
+                        let mut ids : Vec<DimensionID> = dimension_registry.dimensions.keys().cloned().collect();
+                        ids.sort();
+                        let current = ids.iter().position(|id| *id == self.player.dimension_id).unwrap_or(0);
+                        self.player.dimension_id = ids[(current + 1) % ids.len()];
+                        info!("Moved to dimension {} ({})", self.player.dimension_id, dimension_registry.get(self.player.dimension_id).unwrap().name);
+                        self.renderer.render_queue.lines.chunks_changed = true;
+                        // Let the server know right away.
+                        self.last_position_update = None;
                     }
                 },
                 _ => ()
@@ -471,14 +617,54 @@ impl GameClient {
 
         self.player.update(dt, &self.input_state);
 
+        for (dimension_id, receiver) in self.chunk_event_receivers.iter() {
+            let is_current = *dimension_id == self.player.dimension_id;
+            for event in receiver.try_iter() {
+                // Any of these can change which faces the chunk's neighbours hide, so they're checked too.
+                // Neighbours whose meshes are still good are skipped when we get to them.
+                match event {
+                    ChunkEvent::Loaded(pos) => {
+                        if is_current { self.renderer.render_queue.lines.chunks_changed = true; }
+                        self.remesh_queue.insert((*dimension_id, pos));
+                        self.remesh_queue.extend(VoxelAxis::iter_all().map(|side| (*dimension_id, pos.get_neighbor(side))));
+                    },
+                    ChunkEvent::Unloaded(pos) => {
+                        if is_current { self.renderer.render_queue.lines.chunks_changed = true; }
+                        self.chunk_meshes.remove(&(*dimension_id, pos));
+                        self.remesh_queue.remove(&(*dimension_id, pos));
+                        self.remesh_queue.extend(VoxelAxis::iter_all().map(|side| (*dimension_id, pos.get_neighbor(side))));
+                    },
+                    ChunkEvent::Modified(pos, _) => {
+                        self.remesh_queue.insert((*dimension_id, pos));
+                        self.remesh_queue.extend(VoxelAxis::iter_all().map(|side| (*dimension_id, pos.get_neighbor(side))));
+                    },
+                }
+            }
+        }
+
+        // Let the server know where we are, so it loads chunks around us.
+        let should_update_position = match self.last_position_update {
+            Some((time, pos)) => (time.elapsed() >= POSITION_UPDATE_INTERVAL) && (pos != self.player.position),
+            None => true,
+        };
+        if self.net.is_connected() && should_update_position {
+            let pos = self.player.position;
+            self.net.send_packet(network::ToServerPacket{
+                data: network::ToServerPacketData::UpdateMyPosition(self.player.dimension_id, [pos.x, pos.y, pos.z])})?;
+            self.last_position_update = Some((Instant::now(), pos));
+        }
+
         {
             let line_queue = &mut self.renderer.render_queue.lines;
             if line_queue.chunks_changed {
                 let mut verts = Vec::new();
                 let mut idxs = Vec::new();
                 let mut index_offset = 0;
-                for (pos, _) in dimension_registry.get(0).unwrap().chunks.iter() {
-                    verts.append(&mut ::util::cube::generate_chunk_debug_line_vertices(pos.x, pos.y, pos.z, 0.25f32).to_vec());
+                let dimension = dimension_registry.get(self.player.dimension_id).unwrap();
+                let size = dimension.chunk_size;
+                for (pos, _) in dimension.chunks.iter() {
+                    verts.append(&mut ::util::cube::generate_chunk_debug_line_vertices(pos.x, pos.y, pos.z,
+                                                                                        size.x as f32, size.y as f32, size.z as f32, 0.25f32).to_vec());
                     idxs.append(&mut ::util::cube::generate_chunk_debug_line_indices(index_offset).to_vec());
                     index_offset += 1;
                 }
@@ -498,58 +684,82 @@ impl GameClient {
             }
         }
 
-        let loaded_chunk_list = dimension_registry.get(0).unwrap().loaded_chunk_list();
-
         self.renderer.render_queue.chunk_meshes.clear();
-        for (pos, ref mut entry) in dimension_registry.get(0).unwrap().chunks.iter() {
-            let is_dirty = entry.state.load(Ordering::Relaxed) == CHUNK_STATE_DIRTY;
-            if is_dirty {
-                entry.state.store(CHUNK_STATE_WRITING, Ordering::Relaxed);
+        let dimension_id = self.player.dimension_id;
+        let dimension = dimension_registry.get(dimension_id).unwrap();
+        // Chunks in other dimensions wait in the queue until we go back there.
+        let queued : Vec<VoxelPos<i32>> = self.remesh_queue.iter().filter(|(queued_dimension, _)| *queued_dimension == dimension_id)
+            .map(|(_, pos)| *pos).collect();
+        for pos in queued.iter() {
+            self.remesh_queue.remove(&(dimension_id, *pos));
+            let entry = match dimension.chunks.get(pos) {
+                Some(entry) => entry,
+                None => continue,
+            };
+            // Chunks which are already meshed, or being meshed, don't need another one.
+            if let Some(revision) = entry.pipeline.begin_meshing() {
                 let entry_arc = entry.clone();
 
                 let device_arc = self.renderer.device.clone();
                 let memory_pool_arc = self.renderer.memory_pool.clone();
 
                 let mesh_pend = new_pending_mesh();
-                self.pending_meshes.push((*pos, mesh_pend.clone(), Instant::now()));
+                self.pending_meshes.push(((dimension_id, *pos), entry_arc.clone(), revision, mesh_pend.clone(), Instant::now()));
 
                 let bounds = entry_arc.bounds.clone();
-                
+                let neighbors = dimension.chunk_neighbors(*pos);
+
                 thread::spawn(move || {
                     let chunk_lock = entry_arc.data.read();
-                    let mut mesh = MeshSimplifier::generate_mesh(&*chunk_lock as &Chunk, bounds, device_arc, memory_pool_arc).unwrap();
-
-                    mesh.materials.push(Material { albedo_map_name: String::from(""), specular_exponent: 0.0, specular_strength: 0.6 });
-                    mesh.materials.push(Material { albedo_map_name: String::from("stone"), specular_exponent: 128.0, specular_strength: 1.0 });
-                    mesh.materials.push(Material { albedo_map_name: String::from("dirt"), specular_exponent: 16.0, specular_strength: 0.5 });
-                    mesh.materials.push(Material { albedo_map_name: String::from("grass"), specular_exponent: 64.0, specular_strength: 0.7 });
+                    let mesh_data = MeshSimplifier::generate_mesh(&*chunk_lock as &Chunk, bounds, &MASTER_BLOCK_REGISTRY.read(), &neighbors).unwrap();
+                    let mesh = mesh_data.upload(device_arc, memory_pool_arc);
 
                     complete_pending_mesh(mesh_pend.clone(), mesh);
-                    entry_arc.state.store(CHUNK_STATE_CLEAN, Ordering::Relaxed);
                 });
             }
         }
-        let mut new_meshes: Vec<(VoxelPos<i32>, Mesh)> = Vec::new();
+        let mut new_meshes: Vec<((DimensionID, VoxelPos<i32>), Mesh)> = Vec::new();
         // Add any mesh from a task that just finished.
-        self.pending_meshes.retain(|(pos, pending_mesh, _time)| {
+        let remesh_queue = &mut self.remesh_queue;
+        self.pending_meshes.retain(|(pos, entry, revision, pending_mesh, _time)| {
             match poll_pending_mesh(pending_mesh.clone()) {
                 Some(mesh) => { //Mesh is done! Remove it from this list.
-                    new_meshes.push((*pos, mesh));
+                    // Only keep it if nobody changed the chunk while we were meshing it. Otherwise it gets remeshed.
+                    if entry.pipeline.finish_meshing(*revision) {
+                        new_meshes.push((*pos, mesh));
+                    } else {
+                        remesh_queue.insert(*pos);
+                    }
                     //trace!("Chunk mesh at ({}, {}, {}) took {} milliseconds to generate.", pos.x, pos.y, pos.z, time.elapsed().as_millis());
                     false
                 }
                 None => true, //Not done yet, keep this around to poll again next time.
             }
         });
-        for elem in new_meshes.drain(..) {
-            self.chunk_meshes.insert(elem.0, elem.1);
+        // Translucent faces are drawn back to front, so they're sorted again whenever we move into another block
+        // (or dimension), and new meshes are sorted as they come in.
+        let eye = self.player.position;
+        let eye_block = vpos!(eye.x.floor() as i32, eye.y.floor() as i32, eye.z.floor() as i32);
+        if self.translucent_sorted_for != Some((dimension_id, eye_block)) {
+            for ((mesh_dimension, _), mesh) in self.chunk_meshes.iter_mut() {
+                if *mesh_dimension != dimension_id { continue; }
+                MeshSimplifier::sort_translucent(mesh, eye, self.renderer.device.clone(), self.renderer.memory_pool.clone());
+            }
+            self.translucent_sorted_for = Some((dimension_id, eye_block));
+        }
+        for ((mesh_dimension, pos), mut mesh) in new_meshes.drain(..) {
+            // Don't keep meshes for chunks which were unloaded while they were being meshed.
+            if dimension_registry.get(mesh_dimension).unwrap().is_chunk_loaded(pos) {
+                if mesh_dimension == dimension_id {
+                    MeshSimplifier::sort_translucent(&mut mesh, eye, self.renderer.device.clone(), self.renderer.memory_pool.clone());
+                }
+                self.chunk_meshes.insert((mesh_dimension, pos), mesh);
+            }
         }
-
-        // Clean up meshes for chunks that are no longer loaded.
-        self.chunk_meshes.retain(|pos, _ | { loaded_chunk_list.contains(pos) } );
 
         // Actually add the mesh to our render queue.
-        for mesh in self.chunk_meshes.values_mut() {
+        for ((mesh_dimension, _), mesh) in self.chunk_meshes.iter_mut() {
+            if *mesh_dimension != dimension_id { continue; }
             self.renderer.render_queue.chunk_meshes.append(&mut mesh.queue());
         }
 
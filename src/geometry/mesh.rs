@@ -5,6 +5,9 @@
 
 use std::sync::Arc;
 
+use cgmath::Point3;
+use cgmath::Transform as CgmathTransform;
+
 use geometry::{VertexGroup, Material};
 use renderer::ChunkRenderQueueEntry;
 use util::Transform;
@@ -16,7 +19,9 @@ use util::Transform;
 pub struct Mesh {
     pub transform: Transform,
     pub vertex_groups: Vec<Arc<VertexGroup>>,
-    pub materials: Vec<Material>
+    pub materials: Vec<Material>,
+    /// Middle of the mesh, before it's transformed. Used to sort meshes by how far away they are.
+    pub center: Point3<f32>,
 }
 
 
@@ -27,6 +32,7 @@ impl Mesh {
             transform: Transform::new(),
             vertex_groups: Vec::new(),
             materials: Vec::new(),
+            center: Point3::new(0.0, 0.0, 0.0),
         }
     }
 
@@ -37,11 +43,14 @@ impl Mesh {
     /// [ChunkRenderPipeline](::pipeline::chunk_pipeline::ChunkRenderPipeline).
     pub fn queue(&self) -> Vec<ChunkRenderQueueEntry> {
         let mut result = Vec::new();
+        let transform = self.transform.to_matrix();
+        let center = transform.transform_point(self.center);
         for vg in self.vertex_groups.iter() {
             result.push(ChunkRenderQueueEntry {
                 vertex_group: vg.clone(),
                 material: self.materials[vg.material_id as usize].clone(),
-                transform: self.transform.to_matrix()
+                transform: transform,
+                center: center,
             });
         }
         result
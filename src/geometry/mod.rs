@@ -21,5 +21,7 @@ pub struct Material {
     /// Exponent used in specular lighting calculation. Higher values have sharper highlights.
     pub specular_exponent: f32,
     /// Intensity of specular highlights.
-    pub specular_strength: f32
+    pub specular_strength: f32,
+    /// If true, this is blended with whatever is behind it, so it's drawn after everything opaque.
+    pub translucent: bool
 }
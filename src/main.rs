@@ -54,6 +54,25 @@ fn main() {
                                 .value_name("IP")
                                 .help("Joins a server at the selected IP address and socket.")
                                 .takes_value(true))
+                                .arg(Arg::with_name("world")
+                                .short("w")
+                                .long("world")
+                                .value_name("DIRECTORY")
+                                .help("Directory to save the world to and load it from. Ignored when joining a server.")
+                                .default_value("world")
+                                .takes_value(true))
+                                .arg(Arg::with_name("seed")
+                                .long("seed")
+                                .value_name("SEED")
+                                .help("Seed for generating a new world. Existing worlds keep the seed they were created with. Ignored when joining a server.")
+                                .takes_value(true))
+                                .arg(Arg::with_name("generator")
+                                .short("g")
+                                .long("generator")
+                                .value_name("GENERATOR")
+                                .help("World generator for a new world's first dimension: terrain, perlin[:SCALE], flat[:LAYERS], void or heightmap:IMAGE[,HEIGHT]. \
+                                       Existing worlds keep the generator they were created with. Ignored when joining a server.")
+                                .takes_value(true))
                                 .get_matches();
 
     let server_mode : bool = matches.is_present("server");
@@ -66,6 +85,12 @@ fn main() {
         return;
     }
 
+    let seed = match matches.value_of("seed").map(|seed| seed.parse::<u64>()) {
+        Some(Ok(seed)) => Some(seed),
+        Some(Err(_)) => { println!("The seed must be a whole number between 0 and {}.", u64::max_value()); return; },
+        None => None,
+    };
+
     let mut mode = game::GameMode::Singleplayer;
     if let Some(ip) = server_ip {
         mode = game::GameMode::Server(ip.parse().unwrap());
@@ -78,5 +103,5 @@ fn main() {
         Ok(_) => {},
         Err(error) => { println!("Unable to initialize logger. Reason: {}. Closing application.", error); return; }
     }
-    game::Game::new(mode).run();
+    game::Game::new(mode, matches.value_of("world").unwrap(), seed, matches.value_of("generator")).run();
 }
\ No newline at end of file
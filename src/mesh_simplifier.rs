@@ -1,32 +1,82 @@
 //! Simplified mesh generator.
 
 use std::sync::Arc;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::cmp::Ordering;
 
 use cgmath::Point3;
 use vulkano::device::Device;
 
-use geometry::{Mesh, VertexPositionNormalUVColor, VertexGroup};
+use geometry::{Mesh, Material, VertexPositionNormalUVColor, VertexGroup};
 use util::Transform;
 use memory::pool::AutoMemoryPool;
 
 use voxel::voxelstorage::*;
 use voxel::voxelmath::*;
 use world::block;
+use world::block::BlockRegistry;
+use world::blockmodel::{ModelBox, ModelFace, axis_index};
+use world::dimension::ChunkNeighbors;
 
 
-type VoxelTy = block::BlockID;
+type VoxelTy = block::BlockStateID;
 type Chunk = block::Chunk;
 type ChunkBounds = VoxelRange<i32>;
 
-const AIR : VoxelTy = 0;
-
-/// Struct used internally to represent unoptimized quads.
-#[derive(Clone)]
-pub struct InputQuad { x: usize, y: usize, exists: bool, done: bool, pub block_id: VoxelTy }
 /// Struct returned as output from the generator; represents quads in an optimized mesh.
+/// x and y are the quad's corner within its slice of the chunk, and w and h its size. See MeshSimplifier::slice_axes().
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutputQuad { pub x: usize, pub y: usize, pub w: usize, pub h: usize, pub block_id: VoxelTy }
+
+/// Vertices and indices which are all drawn with the same material.
 #[derive(Debug, Clone)]
-pub struct OutputQuad { pub x: usize, pub y: usize, pub w: usize, pub h: usize, width_done: bool, pub block_id: VoxelTy }
+pub struct MeshGroupData {
+    pub vertices: Vec<VertexPositionNormalUVColor>,
+    pub indices: Vec<u32>,
+    /// Index of this group's material in the owning ChunkMeshData.
+    pub material_id: u8,
+}
+
+/// A chunk's mesh, built entirely on the CPU. Nothing is sent to the GPU until it's uploaded with upload().
+#[derive(Clone)]
+pub struct ChunkMeshData {
+    /// Where the chunk's lower corner is in the world. Vertex positions are relative to this.
+    pub origin: VoxelPos<i32>,
+    /// How big the chunk is, in blocks.
+    pub size: VoxelSize<i32>,
+    pub groups: Vec<MeshGroupData>,
+    pub materials: Vec<Material>,
+}
+
+impl ChunkMeshData {
+    /// Creates an empty mesh for the chunk with its lower corner at origin.
+    pub fn new(origin: VoxelPos<i32>, size: VoxelSize<i32>) -> ChunkMeshData {
+        ChunkMeshData {
+            origin: origin,
+            size: size,
+            groups: Vec::new(),
+            materials: Vec::new(),
+        }
+    }
+
+    /// How many quads there are in all of the groups put together.
+    pub fn quad_count(&self) -> usize {
+        self.groups.iter().map(|group| group.indices.len() / 6).sum()
+    }
+
+    /// Makes a renderable Mesh out of this, creating the vertex and index buffers for each group.
+    pub fn upload(self, device: Arc<Device>, memory_pool: AutoMemoryPool) -> Mesh {
+        let mut mesh = Mesh::new();
+        for group in self.groups.into_iter() {
+            mesh.vertex_groups.push(Arc::new(VertexGroup::new(group.vertices, group.indices, group.material_id, device.clone(), memory_pool.clone())));
+        }
+        mesh.materials = self.materials;
+        //Our origin in worldspace (1 block = 1 unit) is the chunk's lower corner, so we can just use it directly as the transform for this mesh.
+        mesh.transform = Transform::from_position(Point3::new(self.origin.x as f32, self.origin.y as f32, self.origin.z as f32));
+        mesh.center = Point3::new(self.size.x as f32 / 2.0, self.size.y as f32 / 2.0, self.size.z as f32 / 2.0);
+        mesh
+    }
+}
 
 
 /// Simplified mesh generator.
@@ -37,241 +87,624 @@ pub struct MeshSimplifier;
 pub struct ChunkMeshError; // TODO
 
 impl MeshSimplifier {
-    // The bug here is negative X faces don't get generated. SPECIFICALLY negative X-facing faces.
+    /// The indices of a group of quads (6 indices each), reordered so the quads furthest away from eye come first.
+    /// Blended faces have to be drawn back to front to look right. eye is in the same space as the vertices.
+    pub fn sort_back_to_front(vertices: &[VertexPositionNormalUVColor], indices: &[u32], eye: [f32; 3]) -> Vec<u32> {
+        // Squared distance from eye to the middle of the quad.
+        let distance = |quad: &[u32]| -> f32 {
+            (0..3).map(|axis| {
+                let middle = [quad[0], quad[1], quad[2], quad[4]].iter().map(|i| vertices[*i as usize].position[axis]).sum::<f32>() / 4.0;
+                (middle - eye[axis]) * (middle - eye[axis])
+            }).sum()
+        };
+        let mut quads : Vec<(f32, &[u32])> = indices.chunks(6).map(|quad| (distance(quad), quad)).collect();
+        quads.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+        quads.iter().flat_map(|(_, quad)| quad.iter().cloned()).collect()
+    }
+
+    /// Sorts the translucent groups of an uploaded chunk mesh back to front, as seen from eye (in world space), and
+    /// replaces their index buffers. Which order is right depends on where the camera is, so this needs doing again
+    /// whenever the camera moves into another block, which is when it can pass through the plane of a face.
+    pub fn sort_translucent(mesh: &mut Mesh, eye: Point3<f32>, device: Arc<Device>, memory_pool: AutoMemoryPool) {
+        let origin = mesh.transform.position;
+        let eye = [eye.x - origin.x, eye.y - origin.y, eye.z - origin.z];
+        let materials = &mesh.materials;
+        for group in mesh.vertex_groups.iter_mut().filter(|group| materials[group.material_id as usize].translucent) {
+            let indices = MeshSimplifier::sort_back_to_front(&group.vertices, &group.indices, eye);
+            if let Some(group) = Arc::get_mut(group) {
+                group.indices = indices;
+                group.update_index_buffer(device.clone(), memory_pool.clone());
+                continue;
+            }
+            // Something else (like last frame's render queue) still has this group, so replace it instead.
+            *group = Arc::new(VertexGroup::new(group.vertices.clone(), indices, group.material_id, device.clone(), memory_pool.clone()));
+        }
+    }
+
+    /// Which axes a slice of faces pointing towards facing is laid out along: (across, up), which are x and y in OutputQuads.
+    pub fn slice_axes(facing: VoxelAxis) -> (VoxelAxisUnsigned, VoxelAxisUnsigned) {
+        match facing.into() {
+            VoxelAxisUnsigned::X => (VoxelAxisUnsigned::Z, VoxelAxisUnsigned::Y),
+            VoxelAxisUnsigned::Y => (VoxelAxisUnsigned::X, VoxelAxisUnsigned::Z),
+            VoxelAxisUnsigned::Z => (VoxelAxisUnsigned::X, VoxelAxisUnsigned::Y),
+        }
+    }
+
     /// Generates a simplified mesh from the given chunk. Returns side, layer of this side (stacked), quads.
-    pub fn generate_quads(chunk: &Chunk, range: ChunkBounds) -> Vec<(VoxelAxis, usize, Vec<OutputQuad>)> {
+    /// Layers without any quads are left out.
+    /// Which blocks get drawn, and which ones hide their neighbours' faces, is looked up in blocks.
+    /// Faces on the edges of the chunk are hidden by the blocks in neighbors, or drawn if that neighbour isn't loaded.
+    pub fn generate_quads(chunk: &Chunk, range: ChunkBounds, blocks: &BlockRegistry, neighbors: &ChunkNeighbors) -> Vec<(VoxelAxis, usize, Vec<OutputQuad>)> {
         let mut output = Vec::new();
-        // Look in each direction.
-        voxel_sides_unroll!(facing, {
-            // Which directions AREN'T the ones we're stacking?
-            let up = match facing.into() {
-                VoxelAxisUnsigned::X => VoxelAxis::PosiY,
-                VoxelAxisUnsigned::Y => VoxelAxis::PosiZ,
-                VoxelAxisUnsigned::Z => VoxelAxis::PosiY,
-            };
-            let across = match facing.into() {
-                VoxelAxisUnsigned::X => VoxelAxis::PosiZ,
-                VoxelAxisUnsigned::Y => VoxelAxis::PosiX,
-                VoxelAxisUnsigned::Z => VoxelAxis::PosiX,
-            };
-            let chunk_size : VoxelPos<u8> = vpos!(range.get_size().x as u8, range.get_size().y as u8, range.get_size().z as u8);
-            let bounds_local : VoxelRange<u8> = VoxelRange{ lower: vpos!(0,0,0), upper: chunk_size };
-            // Iterate by "layers" of each block-side from one end of the chunk to another.
-            let max_layer = chunk_size.coord_for_axis(facing.into());
-
-            for layer_l in 0 .. max_layer {
-                let layer = match facing.get_sign() {
-                            VoxelAxisSign::POSI => layer_l,
-                            VoxelAxisSign::NEGA => max_layer - layer_l,};
-                let mut input_quads = Vec::new();
-                let max_y = chunk_size.coord_for_axis(up.into());
-                let max_x = chunk_size.coord_for_axis(across.into());
-                for y in 0..max_y {
-                    for x in 0..max_x {
+        // Nothing to draw in a chunk that's nothing but air (or anything else we can't see).
+        if let Some(id) = chunk.uniform_value() {
+            if !blocks.is_visible(*id) {
+                return output;
+            }
+        }
+        let chunk_size : VoxelPos<u8> = vpos!(range.get_size().x as u8, range.get_size().y as u8, range.get_size().z as u8);
+        let bounds_local : VoxelRange<u8> = VoxelRange{ lower: vpos!(0,0,0), upper: chunk_size };
+        for facing in VoxelAxis::iter_all() {
+            let (across, up) = MeshSimplifier::slice_axes(facing);
+            let slice_width = chunk_size.coord_for_axis(across);
+            let slice_height = chunk_size.coord_for_axis(up);
+            // Go through the chunk one slice at a time, finding every face in the slice we can see.
+            let mut faces = Vec::with_capacity(slice_width as usize * slice_height as usize);
+            for layer in 0..chunk_size.coord_for_axis(facing.into()) {
+                faces.clear();
+                for y in 0..slice_height {
+                    for x in 0..slice_width {
                         let mut point : VoxelPos<u8> = vpos!(0,0,0);
                         point.set_coord_for_axis(facing.into(), layer);
-                        point.set_coord_for_axis(up.into(), y);
-                        point.set_coord_for_axis(across.into(), x);
-                        let adjacent_point = point.get_neighbor_unsigned(facing);
-                        let voxel_maybe = chunk.get(point);
-                        let exists = match voxel_maybe {
-                            Err(_) => false,
-                            Ok(AIR) => false,
-                            //Check neighbor point
-                            Ok(_) => match adjacent_point {
-                                Ok(adj) => {
-                                    //We have found our way to the end of the chunk, and the next block is past our range. Make this side solid.
-                                    if !(bounds_local.contains(adj)) {
-                                        true
-                                    } else {
-                                        // Is the neighboring block solid?
-                                        match chunk.get(adj) {
-                                            Err(_) => true, //End of the underlying voxel storage - should match end of chunk.
-                                            Ok(AIR) => true, //Air block, nothing in our way.
-                                            Ok(_) => false, //Solid block in the way, do not process this quad.
-                                        }
-                                    }
-                                },
-                                Err(_) => true, //Underflow. Our neighbor would have been at a negative point, but this is unsigned.
-                            },
-                        };
-                        input_quads.push(InputQuad { x: (x as usize), y: (y as usize), exists: exists, 
-                        done: false, block_id: voxel_maybe.unwrap_or(AIR), });
+                        point.set_coord_for_axis(up, y);
+                        point.set_coord_for_axis(across, x);
+                        faces.push(MeshSimplifier::visible_face(chunk, bounds_local, blocks, neighbors, point, facing));
                     }
                 }
-                // Done with this slice, now process it.
-                output.push((facing, layer as usize, MeshSimplifier::process_slice(input_quads, max_x as usize, max_y as usize)));
+                let quads = MeshSimplifier::process_slice(&faces, slice_width as usize, slice_height as usize);
+                if !quads.is_empty() {
+                    output.push((facing, layer as usize, quads));
+                }
             }
-        });
+        }
         output
     }
 
-    /// Generates one 2d slice of the mesh.
-    pub fn process_slice(mut input_quads: Vec<InputQuad>, slice_width : usize, slice_height : usize) -> Vec<OutputQuad> {
+    /// If the face of the block at point which points towards facing should be drawn, the block. Otherwise None.
+    fn visible_face(chunk: &Chunk, bounds: VoxelRange<u8>, blocks: &BlockRegistry, neighbors: &ChunkNeighbors,
+                    point: VoxelPos<u8>, facing: VoxelAxis) -> Option<VoxelTy> {
+        let id = chunk.get(point).ok()?;
+        // Blocks with models are drawn one at a time, not merged into quads. See generate_mesh().
+        if !blocks.is_visible(id) || blocks.model(id).is_some() {
+            return None;
+        }
+        // Can we see through the neighboring block? Nothing there at all counts as yes.
+        // Faces between two of the same translucent block (like a wall of glass) are hidden too. Cutout blocks
+        // (like leaves) keep theirs, since they can be seen through the holes.
+        match MeshSimplifier::neighbor(chunk, bounds, neighbors, point, facing) {
+            Some(neighbor) if blocks.is_opaque(neighbor) || (blocks.is_translucent(id) && blocks.block_of(neighbor) == blocks.block_of(id)) => None,
+            _ => Some(id),
+        }
+    }
+
+    /// The block next to point in the direction of facing, or None if it's in a chunk which isn't loaded.
+    fn neighbor(chunk: &Chunk, bounds: VoxelRange<u8>, neighbors: &ChunkNeighbors, point: VoxelPos<u8>, facing: VoxelAxis) -> Option<VoxelTy> {
+        match point.get_neighbor_unsigned(facing) {
+            Ok(adj) if bounds.contains(adj) => chunk.get(adj).ok(),
+            //The next block is past the end of our chunk (or before the start of it, which underflows), so it's in the next chunk over.
+            _ => neighbors.get(facing, point),
+        }
+    }
+
+    /// Greedily merges one 2d slice of faces into as few quads as it can manage. faces is slice_width * slice_height
+    /// entries, row by row, each the block whose face is there (or None if there isn't one). Every face ends up in
+    /// exactly one quad, and each quad only covers faces of a single block.
+    pub fn process_slice(faces: &[Option<VoxelTy>], slice_width : usize, slice_height : usize) -> Vec<OutputQuad> {
         let mut output_quads = Vec::new();
-        let mut current_quad: Option<OutputQuad> = None;
-        let mut i : usize= 0;
-        while i < (slice_width*slice_height) as usize {
-            let mut q = input_quads.get_mut(i).unwrap().clone();
-            if current_quad.is_none() {
-                if q.exists && !q.done {
-                    current_quad = Some(OutputQuad { x: q.x, y: q.y, w: 1, h: 1, width_done: false, block_id: q.block_id });
-                    q.done = true;
+        let mut done = vec![false; faces.len()];
+        for y in 0..slice_height {
+            let mut x = 0;
+            while x < slice_width {
+                let block_id = match faces[y*slice_width + x] {
+                    Some(block_id) if !done[y*slice_width + x] => block_id,
+                    _ => {
+                        x += 1;
+                        continue;
+                    },
+                };
+                let fits = |i: usize| !done[i] && faces[i] == Some(block_id);
+                // Grow right as far as we can, and then down for as many whole rows as we can.
+                let mut w = 1;
+                while x + w < slice_width && fits(y*slice_width + x + w) {
+                    w += 1;
                 }
-                i += 1;
-                continue;
-            }
-            let mut current = current_quad.unwrap();
-            if !current.width_done {
-                // is quad on the same row?
-                if q.x > current.x {
-                    // moving right, check for quad
-                    if q.exists && !q.done && q.block_id == current.block_id {
-                        q.done = true;
-                        current.w += 1;
-                    }
-                    else {
-                        // found a gap, done with right expansion
-                        current.width_done = true;
-                    }
+                let mut h = 1;
+                while y + h < slice_height && (x..x + w).all(|i| fits((y + h)*slice_width + i)) {
+                    h += 1;
                 }
-                else {
-                    // quad below start, meaning next row, done with right expansion
-                    current.width_done = true;
+                for row in y..y + h {
+                    for i in x..x + w {
+                        done[row*slice_width + i] = true;
+                    }
                 }
+                output_quads.push(OutputQuad { x: x, y: y, w: w, h: h, block_id: block_id });
+                x += w;
             }
-            if current.width_done {
-                let mut y = current.y + 1;
-                if y < slice_height {
-                    loop {
-                        let x_min = current.x;
-                        let x_max = current.x + current.w;
-                        let mut ok = true;
-                        for x in x_min..x_max {
-                            if !input_quads[y*slice_width+x].exists 
-                                    || input_quads[y*slice_width+x].done 
-                                    || input_quads[y*slice_width+x].block_id != current.block_id {
-                                ok = false;
-                                break;
-                            }
-                        }
-                        if ok {
-                            for x in x_min..x_max {
-                                input_quads[y*slice_width+x].done = true;
-                            }
-                            current.h += 1;
-                            y += 1;
-                            if y >= slice_width { break; }
+        }
+        output_quads
+    }
+
+    /// Which corners of a face pointing towards facing its vertices go at, in the order they're drawn in (which decides
+    /// which side of it is the front). Each is (across, up), with false for the lower edge and true for the upper one. See slice_axes().
+    fn face_corners(facing: VoxelAxis) -> [(bool, bool); 4] {
+        match facing {
+            VoxelAxis::NegaX => [(false, false), (true, false), (true, true), (false, true)],
+            VoxelAxis::PosiX | VoxelAxis::PosiY | VoxelAxis::NegaZ => [(false, true), (true, true), (true, false), (false, false)],
+            VoxelAxis::NegaY | VoxelAxis::PosiZ => [(true, true), (false, true), (false, false), (true, false)],
+        }
+    }
+
+    /// The vertices of a rectangle pointing towards facing, lying at plane along facing's axis and covering across
+    /// and up along the other two (see slice_axes()). uv is the part of the texture it shows: [left, top, right, bottom].
+    fn face_vertices(facing: VoxelAxis, plane: f32, across: [f32; 2], up: [f32; 2], uv: [f32; 4]) -> [VertexPositionNormalUVColor; 4] {
+        let (across_axis, up_axis) = MeshSimplifier::slice_axes(facing);
+        let mut normal = [0.0; 3];
+        normal[axis_index(facing.into())] = match facing.get_sign() {
+            VoxelAxisSign::POSI => 1.0,
+            VoxelAxisSign::NEGA => -1.0,
+        };
+        let corners = MeshSimplifier::face_corners(facing);
+        let vertex = |i: usize| {
+            let (is_right, is_top) = corners[i];
+            let mut position = [0.0; 3];
+            position[axis_index(facing.into())] = plane;
+            position[axis_index(across_axis)] = if is_right { across[1] } else { across[0] };
+            position[axis_index(up_axis)] = if is_top { up[1] } else { up[0] };
+            VertexPositionNormalUVColor { position: position, normal: normal,
+                                          uv: [if is_right { uv[2] } else { uv[0] }, if is_top { uv[1] } else { uv[3] }],
+                                          color: [ 1.0, 1.0, 1.0 ] }
+        };
+        [vertex(0), vertex(1), vertex(2), vertex(3)]
+    }
+
+    /// The vertices of one face of a box in a block model, for the block at point.
+    fn model_face_vertices(model_box: &ModelBox, face: &ModelFace, facing: VoxelAxis, point: VoxelPos<u8>) -> [VertexPositionNormalUVColor; 4] {
+        let (across_axis, up_axis) = MeshSimplifier::slice_axes(facing);
+        let (axis, across_axis, up_axis) = (axis_index(facing.into()), axis_index(across_axis), axis_index(up_axis));
+        let plane = match facing.get_sign() {
+            VoxelAxisSign::POSI => model_box.to[axis],
+            VoxelAxisSign::NEGA => model_box.from[axis],
+        };
+        let across = [model_box.from[across_axis], model_box.to[across_axis]];
+        let up = [model_box.from[up_axis], model_box.to[up_axis]];
+        // Unless we're told otherwise, show the part of the texture which lines up with where the face is in the block.
+        let uv = face.uv.unwrap_or([across[0], 1.0 - up[1], across[1], 1.0 - up[0]]);
+        let mut vertices = MeshSimplifier::face_vertices(facing, plane, across, up, uv);
+        for vertex in vertices.iter_mut() {
+            let position = model_box.rotate(vertex.position, false);
+            vertex.position = [position[0] + point.x as f32, position[1] + point.y as f32, position[2] + point.z as f32];
+            vertex.normal = model_box.rotate(vertex.normal, true);
+        }
+        vertices
+    }
+
+    /// Generates a mesh for a chunk, using [MeshSimplifier]. The mesh gets a group of vertices and a material
+    /// for each block and texture in the chunk, with textures and shininess from the blocks' definitions.
+    /// Full cubes are merged together into as few quads as possible, and blocks with models are drawn box by box,
+    /// leaving out faces which are up against a full cube.
+    /// This doesn't touch the GPU; see ChunkMeshData::upload() for that.
+    pub fn generate_mesh(chunk: &Chunk, range: ChunkBounds, blocks: &BlockRegistry, neighbors: &ChunkNeighbors) -> Result<ChunkMeshData, ChunkMeshError> {
+        let quad_lists = MeshSimplifier::generate_quads(chunk, range, blocks, neighbors);
+
+        let mut mesh = ChunkMeshData::new(range.lower, range.get_size());
+        // One group for each block and texture, in the same order as mesh.materials.
+        // TODO: material IDs are u8s, so a chunk can only use 256 different block textures.
+        let mut group_indices : HashMap<(block::BlockID, String), usize> = HashMap::new();
+        let mut add_face = |mesh: &mut ChunkMeshData, id: VoxelTy, texture: &str, vertices: [VertexPositionNormalUVColor; 4]| {
+            let block = blocks.block_of(id);
+            let def = match blocks.get(block) {
+                Some(def) => def,
+                None => return,
+            };
+            let materials = &mut mesh.materials;
+            let groups = &mut mesh.groups;
+            let group = *group_indices.entry((block, texture.to_owned())).or_insert_with(|| {
+                materials.push(Material { albedo_map_name: texture.to_owned(), specular_exponent: def.specular_exponent,
+                                          specular_strength: def.specular_strength, translucent: def.translucent });
+                groups.push(MeshGroupData { vertices: Vec::new(), indices: Vec::new(), material_id: (materials.len() - 1) as u8 });
+                groups.len() - 1
+            });
+            let MeshGroupData { vertices: ref mut group_vertices, ref mut indices, .. } = mesh.groups[group];
+            let o = group_vertices.len() as u32;
+            group_vertices.extend(vertices.iter().cloned());
+            indices.push(0+o); indices.push(1+o); indices.push(2+o);
+            indices.push(2+o); indices.push(3+o); indices.push(0+o);
+        };
+
+        for (facing, layer, list) in quad_lists.iter() {
+            // Positive faces are on the far side of their layer.
+            let plane = match facing.get_sign() {
+                VoxelAxisSign::POSI => *layer as f32 + 1.0,
+                VoxelAxisSign::NEGA => *layer as f32,
+            };
+            for quad in list {
+                let texture = match blocks.def_for_state(quad.block_id).and_then(|def| def.textures.for_face(*facing)) {
+                    Some(texture) => texture,
+                    None => continue, // Nothing to draw on this face.
+                };
+                let vertices = MeshSimplifier::face_vertices(*facing, plane, [quad.x as f32, (quad.x + quad.w) as f32],
+                                                             [quad.y as f32, (quad.y + quad.h) as f32], [0.0, 0.0, quad.w as f32, quad.h as f32]);
+                add_face(&mut mesh, quad.block_id, texture, vertices);
+            }
+        }
+
+        // Blocks with models.
+        if chunk.uniform_value().map_or(true, |id| blocks.model(*id).is_some()) {
+            let chunk_size : VoxelPos<u8> = vpos!(range.get_size().x as u8, range.get_size().y as u8, range.get_size().z as u8);
+            let bounds_local : VoxelRange<u8> = VoxelRange{ lower: vpos!(0,0,0), upper: chunk_size };
+            for point in chunk.get_bounds() {
+                let id = chunk.get(point).unwrap();
+                let (model, def) = match (blocks.model(id), blocks.def_for_state(id)) {
+                    (Some(model), Some(def)) => (model, def),
+                    _ => continue,
+                };
+                for model_box in model.boxes.iter() {
+                    for facing in VoxelAxis::iter_all() {
+                        let face = match model_box.faces.for_face(facing) {
+                            Some(face) => face,
+                            None => continue,
+                        };
+                        if model_box.touches_side(facing)
+                                && MeshSimplifier::neighbor(chunk, bounds_local, neighbors, point, facing).map_or(false, |neighbor| blocks.is_opaque(neighbor)) {
+                            continue;
                         }
-                        else { break; }
+                        let texture = match face.texture.as_ref().map(|texture| texture.as_str()).or(def.textures.for_face(facing)) {
+                            Some(texture) => texture,
+                            None => continue,
+                        };
+                        add_face(&mut mesh, id, texture, MeshSimplifier::model_face_vertices(model_box, face, facing, point));
                     }
                 }
-                output_quads.push(current);
-                current_quad = None;
-                continue;
             }
-            i += 1;
-            // when i == 16*16, loop would end without adding quad
-            if i >= slice_width*slice_height {
-                output_quads.push(current.clone());
-                break;
+        }
+
+        return Ok(mesh);
+    }
+}
+
+#[cfg(test)]
+use world::block::AIR;
+
+#[cfg(test)]
+fn test_blocks() -> BlockRegistry {
+    use world::block::BlockDef;
+    let mut blocks = BlockRegistry::new();
+    let mut stone = BlockDef::new("stone");
+    stone.textures.all = Some("stone".to_owned());
+    blocks.define(stone);
+    let mut grass = BlockDef::new("grass");
+    grass.textures.all = Some("dirt".to_owned());
+    grass.textures.side = Some("grass_side".to_owned());
+    grass.textures.top = Some("grass".to_owned());
+    blocks.define(grass);
+    blocks
+}
+
+/// Checks that every quad in mesh is flat, faces the way its normal says, and lies inside [lower, upper].
+#[cfg(test)]
+fn check_quads(mesh: &ChunkMeshData, lower: [f32; 3], upper: [f32; 3]) {
+    for group in mesh.groups.iter() {
+        assert_eq!(group.vertices.len(), group.indices.len() / 6 * 4);
+        for quad in group.vertices.chunks(4) {
+            let normal = quad[0].normal;
+            let axis = normal.iter().position(|n| *n != 0.0).unwrap();
+            for vertex in quad.iter() {
+                assert_eq!(vertex.normal, normal);
+                assert_eq!(vertex.position[axis], quad[0].position[axis]);
+                for i in 0..3 {
+                    assert!(vertex.position[i] >= lower[i] && vertex.position[i] <= upper[i], "{:?} is outside the mesh", vertex.position);
+                }
             }
-            current_quad = Some(current);
+            // Faces pointing away from the middle of the mesh are on its outside.
+            let expected = if normal[axis] > 0.0 { upper[axis] } else { lower[axis] };
+            assert_eq!(quad[0].position[axis], expected, "{:?} face is on the inside", normal);
         }
+    }
+}
 
-        output_quads
+#[test]
+fn test_mesh_one_block() {
+    use world::block::STONE;
+    let blocks = test_blocks();
+    let mut chunk = Chunk::new_solid(2, 3, 4, AIR);
+    chunk.set(vpos!(0, 1, 2), STONE).unwrap();
+    let range = VoxelRange::new_origin_size(vpos!(32, -16, 0), vpos!(2, 3, 4));
+    let mesh = MeshSimplifier::generate_mesh(&chunk, range, &blocks, &ChunkNeighbors::new(vpos!(2, 3, 4))).unwrap();
+    assert_eq!(mesh.origin, vpos!(32, -16, 0));
+    assert_eq!(mesh.size, vpos!(2, 3, 4));
+    assert_eq!(mesh.quad_count(), 6);
+    assert_eq!(mesh.groups.len(), 1);
+    assert_eq!(mesh.materials[mesh.groups[0].material_id as usize].albedo_map_name, "stone");
+    check_quads(&mesh, [0.0, 1.0, 2.0], [1.0, 2.0, 3.0]);
+
+    // Nothing to see.
+    let empty = Chunk::new_solid(2, 3, 4, AIR);
+    assert_eq!(MeshSimplifier::generate_mesh(&empty, range, &blocks, &ChunkNeighbors::new(vpos!(2, 3, 4))).unwrap().quad_count(), 0);
+}
+
+#[test]
+fn test_mesh_solid_chunk() {
+    use world::block::STONE;
+    let blocks = test_blocks();
+    // Greedy meshing turns each side of a solid chunk into one quad, and no inside faces are drawn.
+    let chunk = Chunk::new_solid(2, 3, 4, STONE);
+    let mesh = MeshSimplifier::generate_mesh(&chunk, VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(2, 3, 4)), &blocks, &ChunkNeighbors::new(vpos!(2, 3, 4))).unwrap();
+    assert_eq!(mesh.quad_count(), 6);
+    check_quads(&mesh, [0.0, 0.0, 0.0], [2.0, 3.0, 4.0]);
+}
+
+#[test]
+fn test_mesh_textures() {
+    use world::block::{STONE, GRASS};
+    let blocks = test_blocks();
+    // A grass block sitting on a stone one.
+    let mut chunk = Chunk::new_solid(1, 2, 1, STONE);
+    chunk.set(vpos!(0, 1, 0), GRASS).unwrap();
+    let mesh = MeshSimplifier::generate_mesh(&chunk, VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(1, 2, 1)), &blocks, &ChunkNeighbors::new(vpos!(1, 2, 1))).unwrap();
+    // Stone's top and grass's bottom are hidden.
+    assert_eq!(mesh.quad_count(), 10);
+    check_quads(&mesh, [0.0, 0.0, 0.0], [1.0, 2.0, 1.0]);
+    let mut textures : Vec<(String, usize)> = mesh.groups.iter()
+        .map(|group| (mesh.materials[group.material_id as usize].albedo_map_name.clone(), group.indices.len() / 6)).collect();
+    textures.sort();
+    assert_eq!(textures, vec![("grass".to_owned(), 1), ("grass_side".to_owned(), 4), ("stone".to_owned(), 5)]);
+    for group in mesh.groups.iter() {
+        let name = &mesh.materials[group.material_id as usize].albedo_map_name;
+        let expected_y = if name == "stone" { [0.0, 1.0] } else { [1.0, 2.0] };
+        assert!(group.vertices.iter().all(|vertex| vertex.position[1] >= expected_y[0] && vertex.position[1] <= expected_y[1]));
+    }
+}
+
+#[test]
+fn test_mesh_neighbors() {
+    use world::block::{STONE, GRASS};
+    let blocks = test_blocks();
+    let size = vpos!(2, 3, 4);
+    let range = VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(2, 3, 4));
+    let chunk = Chunk::new_solid(2, 3, 4, STONE);
+
+    // Solid neighbours on every side hide everything.
+    let mut neighbors = ChunkNeighbors::new(size);
+    for side in VoxelAxis::iter_all() {
+        neighbors.set_side(side, &Chunk::new_solid(2, 3, 4, STONE));
     }
+    assert_eq!(MeshSimplifier::generate_mesh(&chunk, range, &blocks, &neighbors).unwrap().quad_count(), 0);
 
-    /// Generates a mesh for a chunk, using [MeshSimplifier].
-    pub fn generate_mesh(chunk: &Chunk, range: ChunkBounds, device: Arc<Device>, 
-                                memory_pool: AutoMemoryPool) -> Result<Mesh, ChunkMeshError> {
-        let quad_lists = MeshSimplifier::generate_quads(chunk, range);
+    // Only the faces touching the air block in the chunk below us get drawn.
+    let mut below = Chunk::new_solid(2, 3, 4, GRASS);
+    below.set(vpos!(1, 2, 3), AIR).unwrap();
+    neighbors.set_side(VoxelAxis::NegaY, &below);
+    let mesh = MeshSimplifier::generate_mesh(&chunk, range, &blocks, &neighbors).unwrap();
+    assert_eq!(mesh.quad_count(), 1);
+    check_quads(&mesh, [1.0, 0.0, 3.0], [2.0, 0.0, 4.0]);
+    assert_eq!(neighbors.get(VoxelAxis::NegaY, vpos!(1, 0, 3)), Some(AIR));
+    assert_eq!(neighbors.get(VoxelAxis::NegaY, vpos!(0, 0, 3)), Some(GRASS));
 
-        // Get all unique block ids and seperate
-        let mut unique_ids = HashSet::new();
-        for (_, _, list) in quad_lists.iter() {
-            for quad in list.iter() {
-                unique_ids.insert(quad.block_id);
+    // Neighbours which aren't loaded don't hide anything.
+    let mut neighbors = ChunkNeighbors::new(size);
+    neighbors.set_side(VoxelAxis::PosiX, &Chunk::new_solid(2, 3, 4, STONE));
+    assert_eq!(neighbors.get(VoxelAxis::NegaX, vpos!(0, 0, 0)), None);
+    assert_eq!(MeshSimplifier::generate_mesh(&chunk, range, &blocks, &neighbors).unwrap().quad_count(), 5);
+}
+
+/// Every face the greedy mesher should draw, worked out one face at a time: (facing, block, block's position).
+#[cfg(test)]
+fn naive_faces(chunk: &Chunk, blocks: &BlockRegistry, neighbors: &ChunkNeighbors) -> ::std::collections::HashSet<(VoxelAxis, VoxelPos<u8>, VoxelTy)> {
+    let mut faces = ::std::collections::HashSet::new();
+    for point in chunk.get_bounds() {
+        let id = chunk.get(point).unwrap();
+        if !blocks.is_visible(id) {
+            continue;
+        }
+        for facing in VoxelAxis::iter_all() {
+            let next = vpos!(point.x as i32, point.y as i32, point.z as i32).get_neighbor(facing);
+            let size = chunk.get_bounds().upper;
+            let inside = next.x >= 0 && next.y >= 0 && next.z >= 0
+                && next.x < size.x as i32 && next.y < size.y as i32 && next.z < size.z as i32;
+            let neighbor = if inside { Some(chunk.get(vpos!(next.x as u8, next.y as u8, next.z as u8)).unwrap()) }
+                           else { neighbors.get(facing, point) };
+            if !neighbor.map_or(false, |neighbor| blocks.is_opaque(neighbor) || (blocks.is_translucent(id) && blocks.block_of(neighbor) == blocks.block_of(id))) {
+                faces.insert((facing, point, id));
             }
         }
-        unique_ids.remove(&AIR); // don't generate anything for air
+    }
+    faces
+}
 
-        let mut mesh = Mesh::new();
-        /*let mut count_p_x = 0;
-        let mut count_n_x = 0;
-        let mut count_p_y = 0;
-        let mut count_n_y = 0;
-        let mut count_p_z = 0;
-        let mut count_n_z = 0;
-        */
-        // TODO: currently iterates over the whole quad list [# of unique ids] times. for diverse
-        // chunks this will get expensive. needs optimization.
-        for id in unique_ids.iter() {
-            let mut vertices = Vec::new() as Vec<VertexPositionNormalUVColor>;
-            let mut indices = Vec::new() as Vec<u32>;
-            let mut o = 0;
-            for (facing, layer, list) in quad_lists.iter() {
-                /*match facing {
-                    VoxelAxis::PosiX => count_p_x += list.len(),
-                    VoxelAxis::NegaX => count_n_x += list.len(),
-                    VoxelAxis::PosiY => count_p_y += list.len(),
-                    VoxelAxis::NegaY => count_n_y += list.len(),
-                    VoxelAxis::PosiZ => count_p_z += list.len(),
-                    VoxelAxis::NegaZ => count_n_z += list.len(),
-                }*/
-                for quad in list {
-                    if quad.block_id != *id { continue; }
-                    match facing {
-                        //Positive X face gets added, negative X face goes nowhere.
-                        VoxelAxis::NegaX => {
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32, quad.y as f32,          quad.x as f32,], normal: [ -1.0, 0.0, 0.0 ], uv: [ 0.0,           quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32, quad.y as f32,          (quad.x+quad.w) as f32], normal: [ -1.0, 0.0, 0.0 ], uv: [ quad.w as f32, quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32, (quad.y+quad.h) as f32, (quad.x+quad.w) as f32], normal: [ -1.0, 0.0, 0.0 ], uv: [ quad.w as f32, 0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32, (quad.y+quad.h) as f32, quad.x as f32], normal: [ -1.0, 0.0, 0.0 ], uv: [ 0.0,           0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                        },
-                        VoxelAxis::PosiX => {
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32 + 1.0, (quad.y+quad.h) as f32, quad.x as f32 ], normal: [ 1.0, 0.0, 0.0 ], uv: [ 0.0,           0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32 + 1.0, (quad.y+quad.h) as f32, (quad.x+quad.w) as f32 ], normal: [ 1.0, 0.0, 0.0 ], uv: [ quad.w as f32, 0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32 + 1.0, quad.y as f32,          (quad.x+quad.w) as f32], normal: [ 1.0, 0.0, 0.0 ], uv: [ quad.w as f32, quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ *layer as f32 + 1.0, quad.y as f32,          quad.x as f32], normal: [ 1.0, 0.0, 0.0 ], uv: [ 0.0,           quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                        },
-                        VoxelAxis::NegaY => {
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, *layer as f32, (quad.y+quad.h) as f32 ], normal: [ 0.0, -1.0, 0.0 ], uv: [ quad.w as f32, 0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          *layer as f32, (quad.y+quad.h) as f32 ], normal: [ 0.0, -1.0, 0.0 ], uv: [ 0.0,           0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          *layer as f32, quad.y as f32          ], normal: [ 0.0, -1.0, 0.0 ], uv: [ 0.0,           quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, *layer as f32, quad.y as f32          ], normal: [ 0.0, -1.0, 0.0 ], uv: [ quad.w as f32, quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                        },
-                        VoxelAxis::PosiY => {
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          *layer as f32 + 1.0, (quad.y+quad.h) as f32 ], normal: [ 0.0, 1.0, 0.0 ], uv: [ 0.0,           0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, *layer as f32 + 1.0, (quad.y+quad.h) as f32 ], normal: [ 0.0, 1.0, 0.0 ], uv: [ quad.w as f32, 0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, *layer as f32 + 1.0, quad.y as f32          ], normal: [ 0.0, 1.0, 0.0 ], uv: [ quad.w as f32, quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          *layer as f32 + 1.0, quad.y as f32          ], normal: [ 0.0, 1.0, 0.0 ], uv: [ 0.0,           quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                        },
-                        VoxelAxis::NegaZ => {
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          (quad.y+quad.h) as f32, *layer as f32 ], normal: [ 0.0, 0.0, -1.0 ], uv: [ 0.0,           0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, (quad.y+quad.h) as f32, *layer as f32 ], normal: [ 0.0, 0.0, -1.0 ], uv: [ quad.w as f32, 0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, quad.y as f32,          *layer as f32 ], normal: [ 0.0, 0.0, -1.0 ], uv: [ quad.w as f32, quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          quad.y as f32,          *layer as f32 ], normal: [ 0.0, 0.0, -1.0 ], uv: [ 0.0,           quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                        },
-                        VoxelAxis::PosiZ => {
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, (quad.y+quad.h) as f32, *layer as f32 + 1.0 ], normal: [ 0.0, 0.0, 1.0 ], uv: [ quad.w as f32, 0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          (quad.y+quad.h) as f32, *layer as f32 + 1.0 ], normal: [ 0.0, 0.0, 1.0 ], uv: [ 0.0,           0.0 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ quad.x as f32,          quad.y as f32,          *layer as f32 + 1.0 ], normal: [ 0.0, 0.0, 1.0 ], uv: [ 0.0,           quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                            vertices.push(VertexPositionNormalUVColor { position: [ (quad.x+quad.w) as f32, quad.y as f32,          *layer as f32 + 1.0 ], normal: [ 0.0, 0.0, 1.0 ], uv: [ quad.w as f32, quad.h as f32 ], color: [ 1.0, 1.0, 1.0 ] });
-                        },
+#[cfg(test)]
+fn random_chunk<R: ::rand::Rng>(rng: &mut R, size: VoxelPos<u8>, palette: &[VoxelTy]) -> Chunk {
+    // Sometimes leave it as one block all the way through.
+    if rng.gen_range(0, 8) == 0 {
+        return Chunk::new_solid(size.x, size.y, size.z, palette[rng.gen_range(0, palette.len())]);
+    }
+    let mut chunk = Chunk::new_solid(size.x, size.y, size.z, AIR);
+    for point in chunk.get_bounds() {
+        chunk.set(point, palette[rng.gen_range(0, palette.len())]).unwrap();
+    }
+    chunk
+}
+
+#[test]
+fn test_greedy_matches_naive() {
+    use rand::{SeedableRng, Rng};
+    use rand::rngs::StdRng;
+    use world::block::{BlockDef, STONE, GRASS};
+    let mut blocks = test_blocks();
+    let mut glass = BlockDef::new("glass");
+    glass.opaque = false;
+    glass.textures.all = Some("glass".to_owned());
+    let glass = blocks.define(glass);
+    let glass = blocks.default_state(glass);
+    let mut water = BlockDef::new("water");
+    water.opaque = false;
+    water.translucent = true;
+    water.textures.all = Some("water".to_owned());
+    let water = blocks.define(water);
+    let water = blocks.default_state(water);
+    let palette = [AIR, AIR, STONE, GRASS, glass, water];
+
+    let mut rng = StdRng::seed_from_u64(23);
+    for _ in 0..200 {
+        let size : VoxelPos<u8> = vpos!(rng.gen_range(1, 9), rng.gen_range(1, 9), rng.gen_range(1, 9));
+        let chunk = random_chunk(&mut rng, size, &palette);
+        let mut neighbors = ChunkNeighbors::new(vpos!(size.x as u32, size.y as u32, size.z as u32));
+        for side in VoxelAxis::iter_all() {
+            if rng.gen() {
+                neighbors.set_side(side, &random_chunk(&mut rng, size, &palette));
+            }
+        }
+        let range = VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(size.x as i32, size.y as i32, size.z as i32));
+        let expected = naive_faces(&chunk, &blocks, &neighbors);
+
+        // Turn the quads back into single faces.
+        let mut faces = ::std::collections::HashSet::new();
+        let mut face_count = 0;
+        for (facing, layer, quads) in MeshSimplifier::generate_quads(&chunk, range, &blocks, &neighbors) {
+            let (across, up) = MeshSimplifier::slice_axes(facing);
+            for quad in quads {
+                assert!(quad.w > 0 && quad.h > 0);
+                for y in quad.y..quad.y + quad.h {
+                    for x in quad.x..quad.x + quad.w {
+                        let mut point : VoxelPos<u8> = vpos!(0, 0, 0);
+                        point.set_coord_for_axis(facing.into(), layer as u8);
+                        point.set_coord_for_axis(across, x as u8);
+                        point.set_coord_for_axis(up, y as u8);
+                        faces.insert((facing, point, quad.block_id));
+                        face_count += 1;
                     }
-                    indices.push(0+o); indices.push(1+o); indices.push(2+o);
-                    indices.push(2+o); indices.push(3+o); indices.push(0+o);
-                    o += 4;
                 }
             }
-            mesh.vertex_groups.push(Arc::new(VertexGroup::new(vertices, indices, (*id as VoxelTy) as u8, device.clone(), memory_pool.clone())));
         }
+        // No face is covered twice, and the quads cover exactly the faces we can see.
+        assert_eq!(face_count, faces.len());
+        assert_eq!(faces, expected);
+
+        // Greedy or not, the mesh has the same area.
+        let mesh = MeshSimplifier::generate_mesh(&chunk, range, &blocks, &neighbors).unwrap();
+        let area : f32 = mesh.groups.iter().flat_map(|group| group.vertices.chunks(4)).map(|quad| {
+            let d = |a: usize, b: usize| (0..3).map(|i| (quad[a].position[i] - quad[b].position[i]).abs()).sum::<f32>();
+            d(0, 1) * d(1, 2)
+        }).sum();
+        assert_eq!(area as usize, expected.len());
+    }
+}
 
-        //println!("+x: {}, -x: {}, +y: {}, -y: {}, +z: {}, -z: {}", count_p_x, count_n_x, count_p_y, count_n_y, count_p_z, count_n_z);
-        //Range.lower is currently our origin in worldspace (1 block = 1 unit), so we can just use it directly as the transform for this mesh.
-        mesh.transform = Transform::from_position(Point3::new(range.lower.x as f32, range.lower.y as f32, range.lower.z as f32));
+#[test]
+fn test_mesh_translucent() {
+    use world::block::{BlockDef, STONE};
+    let mut blocks = test_blocks();
+    let mut glass = BlockDef::new("glass");
+    glass.opaque = false;
+    glass.translucent = true;
+    glass.textures.all = Some("glass".to_owned());
+    let glass = blocks.define(glass);
+    let glass = blocks.default_state(glass);
 
-        return Ok(mesh);
+    // Two glass blocks next to a stone one.
+    let mut chunk = Chunk::new_solid(3, 1, 1, glass);
+    chunk.set(vpos!(2, 0, 0), STONE).unwrap();
+    let range = VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(3, 1, 1));
+    let mesh = MeshSimplifier::generate_mesh(&chunk, range, &blocks, &ChunkNeighbors::new(vpos!(3, 1, 1))).unwrap();
+    let group_for = |mesh: &ChunkMeshData, texture: &str| mesh.groups.iter()
+        .position(|group| mesh.materials[group.material_id as usize].albedo_map_name == texture).unwrap();
+    let glass_group = group_for(&mesh, "glass");
+    let stone_group = group_for(&mesh, "stone");
+    assert!(mesh.materials[mesh.groups[glass_group].material_id as usize].translucent);
+    assert!(!mesh.materials[mesh.groups[stone_group].material_id as usize].translucent);
+    // The glass between the two glass blocks and behind the stone is hidden, but the stone behind the glass isn't.
+    assert_eq!(mesh.groups[glass_group].indices.len() / 6, 5);
+    assert_eq!(mesh.groups[stone_group].indices.len() / 6, 6);
+
+    // Looking from the far side of the glass, its -X face is the furthest away, then the four long sides.
+    let group = &mesh.groups[glass_group];
+    let sorted = MeshSimplifier::sort_back_to_front(&group.vertices, &group.indices, [10.0, 0.5, 0.5]);
+    assert_eq!(group.vertices[sorted[0] as usize].normal, [-1.0, 0.0, 0.0]);
+    assert_eq!(sorted.len(), 30);
+    let mut quads : Vec<&[u32]> = sorted.chunks(6).collect();
+    quads.sort();
+    let mut unsorted : Vec<&[u32]> = group.indices.chunks(6).collect();
+    unsorted.sort();
+    assert_eq!(quads, unsorted);
+    let sorted = MeshSimplifier::sort_back_to_front(&group.vertices, &group.indices, [-10.0, 0.5, 0.5]);
+    assert_eq!(group.vertices[sorted[24] as usize].normal, [-1.0, 0.0, 0.0]);
+
+    // Leaves aren't opaque, but they aren't translucent either, so the faces between them are still drawn.
+    let mut leaves = BlockDef::new("leaves");
+    leaves.opaque = false;
+    leaves.textures.all = Some("leaves".to_owned());
+    let leaves = blocks.define(leaves);
+    let leaves = blocks.default_state(leaves);
+    let chunk = Chunk::new_solid(3, 1, 1, leaves);
+    let mesh = MeshSimplifier::generate_mesh(&chunk, range, &blocks, &ChunkNeighbors::new(vpos!(3, 1, 1))).unwrap();
+    assert_eq!(mesh.quad_count(), 10);
+    assert!(mesh.materials.iter().all(|material| !material.translucent));
+}
+
+#[test]
+fn test_mesh_models() {
+    use world::block::{BlockDef, STONE};
+    use world::blockmodel::BlockModel;
+    let mut blocks = test_blocks();
+    blocks.add_model("slab", BlockModel::from_json(r#"{ "boxes": [{ "from": [0, 0, 0], "to": [1, 0.5, 1] }] }"#).unwrap());
+    blocks.add_model("cross", BlockModel::from_json(r#"{ "boxes": [
+        { "from": [0, 0, 0.5], "to": [1, 1, 0.5], "rotation": 45, "faces": { "north": {}, "south": {} } },
+        { "from": [0, 0, 0.5], "to": [1, 1, 0.5], "rotation": -45, "faces": { "north": {}, "south": { "texture": "flower" } } }
+    ] }"#).unwrap());
+    let mut slab = BlockDef::new("slab");
+    slab.model = Some("slab".to_owned());
+    slab.textures.all = Some("slab".to_owned());
+    let slab = blocks.define(slab);
+    let slab = blocks.default_state(slab);
+    let mut plant = BlockDef::new("plant");
+    plant.model = Some("cross".to_owned());
+    plant.textures.all = Some("plant".to_owned());
+    let plant = blocks.define(plant);
+    let plant = blocks.default_state(plant);
+
+    // A slab on top of stone. The slab's bottom is hidden, but the stone's top isn't.
+    let mut chunk = Chunk::new_solid(1, 2, 1, STONE);
+    chunk.set(vpos!(0, 1, 0), slab).unwrap();
+    let range = VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(1, 2, 1));
+    let mesh = MeshSimplifier::generate_mesh(&chunk, range, &blocks, &ChunkNeighbors::new(vpos!(1, 2, 1))).unwrap();
+    assert_eq!(mesh.quad_count(), 11);
+    let group = mesh.groups.iter().find(|group| mesh.materials[group.material_id as usize].albedo_map_name == "slab").unwrap();
+    assert_eq!(group.indices.len() / 6, 5);
+    for vertex in group.vertices.iter() {
+        assert!(vertex.position[1] >= 1.0 && vertex.position[1] <= 1.5);
+        // The sides show the bottom half of the texture.
+        if vertex.normal[1] == 0.0 {
+            assert_eq!(vertex.uv[1], if vertex.position[1] == 1.0 { 1.0 } else { 0.5 });
+        }
+    }
+
+    // Surrounded by stone, only the top of the slab can be seen.
+    let mut neighbors = ChunkNeighbors::new(vpos!(1, 1, 1));
+    for side in VoxelAxis::iter_all() {
+        neighbors.set_side(side, &Chunk::new_solid(1, 1, 1, STONE));
+    }
+    let range = VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(1, 1, 1));
+    let mesh = MeshSimplifier::generate_mesh(&Chunk::new_solid(1, 1, 1, slab), range, &blocks, &neighbors).unwrap();
+    assert_eq!(mesh.quad_count(), 1);
+    assert!(mesh.groups[0].vertices.iter().all(|vertex| vertex.position[1] == 0.5 && vertex.normal == [0.0, 1.0, 0.0]));
+
+    // Turned faces are never hidden, and can have their own textures.
+    let mesh = MeshSimplifier::generate_mesh(&Chunk::new_solid(1, 1, 1, plant), range, &blocks, &neighbors).unwrap();
+    assert_eq!(mesh.quad_count(), 4);
+    let mut textures : Vec<&str> = mesh.groups.iter().map(|group| mesh.materials[group.material_id as usize].albedo_map_name.as_str()).collect();
+    textures.sort();
+    assert_eq!(textures, vec!["flower", "plant"]);
+    for vertex in mesh.groups.iter().flat_map(|group| group.vertices.iter()) {
+        assert!(vertex.normal[0].abs() > 0.7 && vertex.normal[2].abs() > 0.7);
+        assert!(vertex.position.iter().all(|coord| *coord >= -0.01 && *coord <= 1.01));
     }
-}
\ No newline at end of file
+}
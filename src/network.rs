@@ -26,10 +26,13 @@ use serde::{Serialize, Deserialize};
 
 use entity::EntityID;
 use voxel::voxelevent::*;
-use world::BlockID;
+use world::{BlockStateID, BlockName};
+use world::block::MASTER_BLOCK_REGISTRY;
+use world::dimension::DimensionID;
+use world::storage::DimensionInfo;
 
 //Latest major version / breaking change revision number of our network protocol.
-pub const PROTOCOL_VERSION: u32 = 0;
+pub const PROTOCOL_VERSION: u32 = 3;
 
 /// A unique identifier for a player or a server. Currently this is just a dummy - eventually this will be a public key.
 #[derive(Clone, PartialEq, Eq, PartialOrd, Hash, Serialize, Deserialize, Debug)] 
@@ -80,6 +83,30 @@ impl Error for DidNotIdentifyError {
     }
 }
 
+/// An error reported when the server we're joining kicks us.
+#[derive(Debug, Clone)]
+pub struct KickedError {
+    reason : String,
+}
+impl Display for KickedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Kicked by the server: {}", self.reason)
+    }
+}
+impl Error for KickedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// What a joining client needs to generate the same terrain as the server.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldDescription {
+    pub seed : u64,
+    /// Every dimension's ID and settings. Generators are always spelled out, never left empty for the default.
+    pub dimensions : Vec<(DimensionID, DimensionInfo)>,
+}
+
 // Sometimes we need to know which client created this packet,
 // so we can avoid broadcasting a client's own event back to it.
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -96,12 +123,14 @@ pub struct ToClientPacket {
 pub enum ToClientPacketData {
     Ping,
     Pong,
-    Kick,
+    Kick(String), //Reason
     ChatMsg(Identity, String),
     Ready, //Is this server fully started?
     NotReady, //Wait a minute, server is still starting.
-    VoxEv(VoxelEvent<BlockID, i32>),
+    VoxEv(DimensionID, VoxelEvent<BlockStateID, i32>),
     UpdateEntity(EntityID, [f32; 3]),
+    BlockMappings(HashMap<BlockName, BlockStateID>), //The server's block state IDs, which the client has to switch to.
+    World(WorldDescription), //Sent right after BlockMappings, so the client can build the same dimensions we have.
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -121,21 +150,30 @@ pub enum ToServerPacketData {
     Disconnect,
     ChatMsg(String),
     Join(Identity), //Tell the server we're here and who we are.
+    BlockMappings(HashMap<BlockName, BlockStateID>), //Sent right after Join, so the server can check we have the same block states it does.
     SetName(String),
-    VoxEv(VoxelEvent<BlockID, i32>),
-    UpdateMyPosition([f32; 3]),
+    VoxEv(DimensionID, VoxelEvent<BlockStateID, i32>),
+    UpdateMyPosition(DimensionID, [f32; 3]),
 }
 
 /// A ClientInfo is the server's way of keeping track of a client.
-#[derive(Clone,PartialEq,Eq)]
+#[derive(Clone)]
 pub struct ClientInfo {
     pub player_id : Identity,
     pub client_ip : SocketAddr,
     pub bound_entity : EntityID,
     pub name : String,
+    /// Which dimension this client's player was in, and where, when they last sent us an UpdateMyPosition. None until they do.
+    pub last_position : Option<(DimensionID, [f32; 3])>,
 }
 
 //Compare on ID since this must be unique by definition. 
+impl PartialEq for ClientInfo {
+    fn eq(&self, other: &Self) -> bool {
+        self.player_id == other.player_id
+    }
+}
+impl Eq for ClientInfo {}
 impl PartialOrd for ClientInfo {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         self.player_id.partial_cmp(&other.player_id)
@@ -155,10 +193,11 @@ pub struct Server {
     broadcast_list : Vec<QualifiedToClientPacket>,
     to_drop : Vec<Identity>,
     messages_received : Vec<QualifiedToServerPacket>,
+    world : WorldDescription, // Sent to every client that joins.
 }
 
 impl Server {
-    pub fn new(addr: SocketAddr) -> Result<Server, Box<dyn Error>> {
+    pub fn new(addr: SocketAddr, world: WorldDescription) -> Result<Server, Box<dyn Error>> {
         //Bind our listener.
         let listener = TcpListener::bind(addr)?; //.map_err(|err| {error!("{}", err)})
         info!("Server listening on port {}", addr.port());
@@ -172,6 +211,7 @@ impl Server {
             broadcast_list : Vec::new(),
             to_drop : Vec::new(),
             messages_received : Vec::new(),
+            world : world,
             }
         )
     }
@@ -243,10 +283,29 @@ impl Server {
                 //Now let's get a join packet.
                 let packet = Self::read_incoming_packet(&mut stream)?;
                 if let ToServerPacketData::Join(id) = packet.data {
+                    //Block IDs go over the wire in voxel events, so they have to mean the same thing on both ends.
+                    let mappings = MASTER_BLOCK_REGISTRY.read().all_mappings();
+                    let kick_reason = match Self::read_incoming_packet(&mut stream)?.data {
+                        ToServerPacketData::BlockMappings(theirs) => match MASTER_BLOCK_REGISTRY.read().compare_mappings(&theirs) {
+                            Ok(_) => None,
+                            Err(error) => Some(error.describe_to_client()),
+                        },
+                        _ => Some("Expected a block mappings packet after joining.".to_owned()),
+                    };
+                    if let Some(reason) = kick_reason {
+                        info!("Kicking client {} from {}: {}", id, ip, reason);
+                        Self::send_packet(&mut stream, ToClientPacket { data: ToClientPacketData::Kick(reason) })?;
+                        stream.shutdown(Shutdown::Both)?;
+                        return Ok(());
+                    }
+                    Self::send_packet(&mut stream, ToClientPacket { data: ToClientPacketData::BlockMappings(mappings) })?;
+                    Self::send_packet(&mut stream, ToClientPacket { data: ToClientPacketData::World(self.world.clone()) })?;
+
                     let player = ClientInfo{ player_id : id.clone(),
                                                 client_ip : ip,
                                                 bound_entity : 0,
                                                 name : "Player".to_owned(),
+                                                last_position : None,
                                                 };
                     stream.set_nonblocking(true)?;
                     self.clients.insert(id, (player, stream));
@@ -279,6 +338,9 @@ impl Server {
                         ToServerPacketData::SetName(ref name) => { client.name = name.clone();
                             self.messages_received.push(QualifiedToServerPacket{client_id: id.clone(), pak:pak});
                         },
+                        ToServerPacketData::UpdateMyPosition(dimension_id, pos) => { client.last_position = Some((dimension_id, pos));
+                            self.messages_received.push(QualifiedToServerPacket{client_id: id.clone(), pak:pak});
+                        },
                         _ => self.messages_received.push(QualifiedToServerPacket{client_id: id.clone(), pak:pak}),
                     }
                 },
@@ -314,6 +376,10 @@ impl Server {
         Ok(())
     }
     pub fn poll (&mut self) -> Vec<QualifiedToServerPacket> { self.messages_received.drain(..).collect() }
+    /// Last known position of every connected client which has told us where it is.
+    pub fn player_positions(&self) -> Vec<(DimensionID, [f32; 3])> {
+        self.clients.values().filter_map(|(client, _)| client.last_position).collect()
+    }
 }
 
 //We only get one of these upon connecting
@@ -342,7 +408,25 @@ impl Client {
         }
         Ok(())
     }
-    pub fn connect(&mut self, addr: SocketAddr) -> Result<(), std::io::Error> {
+    pub fn is_connected(&self) -> bool { self.inner.is_some() }
+
+    fn read_packet(stream: &mut TcpStream) -> Result<ToClientPacket, std::io::Error> {
+        let mut buf : [u8; 4] = [0; 4];
+        stream.read_exact(&mut buf)?;
+        let msg_len = u32::from_le_bytes(buf);
+        debug!("Receiving packet of size {} from the server", msg_len);
+
+        let mut buf : Vec<u8> = vec![0; msg_len as usize];
+        stream.read_exact(&mut buf)?;
+
+        let text = std::str::from_utf8(buf.as_slice()).unwrap();
+        Ok(serde_json::from_str::<ToClientPacket>(text)?)
+    }
+
+    /// Connects and joins. The server tells us which IDs it uses for our blocks, and MASTER_BLOCK_REGISTRY is remapped to match,
+    /// or it kicks us (and we return a KickedError) if we don't have the same blocks.
+    /// Returns the server's seed and dimensions, which we need to generate the same terrain it does.
+    pub fn connect(&mut self, addr: SocketAddr) -> Result<WorldDescription, Box<dyn Error>> {
         match TcpStream::connect(addr) {
             Ok(mut stream) => {
                 debug!("Successfully connected to server at {}.", addr);
@@ -356,16 +440,36 @@ impl Client {
                     data: ToServerPacketData::Join(self.ident.clone()),
                 };
                 self.send_packet(packet)?;
-                
+
+                //Then check that we agree with the server on what our blocks are.
+                let packet = ToServerPacket {
+                    data: ToServerPacketData::BlockMappings(MASTER_BLOCK_REGISTRY.read().all_mappings()),
+                };
+                self.send_packet(packet)?;
+                match Self::read_packet(&mut stream)?.data {
+                    ToClientPacketData::BlockMappings(mappings) => {
+                        MASTER_BLOCK_REGISTRY.write().remap(&mappings)?;
+                    },
+                    ToClientPacketData::Kick(reason) => {
+                        self.inner = None;
+                        return Err(Box::new(KickedError{ reason: reason }));
+                    },
+                    other => return Err(format!("Expected block mappings from the server, got {:?}", other).into()),
+                }
+                let world = match Self::read_packet(&mut stream)?.data {
+                    ToClientPacketData::World(world) => world,
+                    other => return Err(format!("Expected the world's seed and dimensions from the server, got {:?}", other).into()),
+                };
+
                 let packet = ToServerPacket { 
                     data: ToServerPacketData::SetName(self.name.clone()),
                 };
                 self.send_packet(packet)?;
 
                 stream.set_nonblocking(true)?;
-                Ok(())
+                Ok(world)
             },
-            Err(e) => Err(e),
+            Err(e) => Err(Box::new(e)),
         }
     }
     pub fn disconnect(&mut self) -> Result<(), Box<dyn Error>> { 
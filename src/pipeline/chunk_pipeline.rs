@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::cmp::Ordering;
 
 use vulkano::buffer::BufferUsage;
 use vulkano::buffer::cpu_pool::CpuBufferPool;
@@ -14,7 +15,7 @@ use winit::Window;
 
 use geometry::VertexPositionNormalUVColor;
 use renderpass::RenderPassUnclearedColorWithDepth;
-use renderer::RenderQueue;
+use renderer::{RenderQueue, ChunkRenderQueueEntry};
 use shader::chunks as ChunksShaders;
 use super::{RenderPipelineAbstract, PipelineCbCreateInfo};
 
@@ -22,6 +23,8 @@ use super::{RenderPipelineAbstract, PipelineCbCreateInfo};
 pub struct ChunkRenderPipeline {
     device: Arc<Device>,
     vulkan_pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
+    /// Same as vulkan_pipeline, but doesn't write to the depth buffer, so translucent faces don't hide each other.
+    translucent_pipeline: Arc<GraphicsPipelineAbstract + Send + Sync>,
     pub framebuffers: Option<Vec<Arc<FramebufferAbstract + Send + Sync>>>,
     renderpass: Arc<RenderPass<RenderPassUnclearedColorWithDepth>>,
     uniform_buffer_pool: CpuBufferPool<ChunksShaders::vertex::ty::Data>,
@@ -53,9 +56,24 @@ impl ChunkRenderPipeline {
             .build(device.clone())
             .unwrap());
 
+        let translucent_pipeline = Arc::new(GraphicsPipeline::start()
+            .cull_mode_back()
+            .vertex_input_single_buffer::<VertexPositionNormalUVColor>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .depth_stencil_simple_depth()
+            .depth_write(false)
+            .blend_alpha_blending()
+            .render_pass(Subpass::from(renderpass.clone(), 0).unwrap())
+            .build(device.clone())
+            .unwrap());
+
         ChunkRenderPipeline {
             device: device.clone(),
             vulkan_pipeline: pipeline,
+            translucent_pipeline,
             framebuffers: None,
             renderpass,
             uniform_buffer_pool: CpuBufferPool::<ChunksShaders::vertex::ty::Data>::new(device.clone(), BufferUsage::all()),
@@ -79,8 +97,25 @@ impl RenderPipelineAbstract for ChunkRenderPipeline {
 
 
     fn build_command_buffer(&self, info: PipelineCbCreateInfo, render_queue: &RenderQueue) -> AutoCommandBuffer {
+        // Opaque geometry first, then translucent geometry from the furthest chunk to the nearest, so it's blended
+        // with everything behind it. Each chunk's translucent faces are already sorted; see MeshSimplifier::sort_translucent().
+        let camera = info.camera_transform.position;
+        // Measured to the middle of each chunk, since its corner is closer to some of its neighbours than to others.
+        let distance = |entry: &ChunkRenderQueueEntry| {
+            let center = entry.center;
+            (center.x - camera.x).powi(2) + (center.y - camera.y).powi(2) + (center.z - camera.z).powi(2)
+        };
+        let entries = &render_queue.chunk_meshes;
+        let mut order : Vec<usize> = (0..entries.len()).filter(|i| !entries[*i].material.translucent).collect();
+        let mut translucent : Vec<usize> = (0..entries.len()).filter(|i| entries[*i].material.translucent).collect();
+        translucent.sort_by(|a, b| distance(&entries[*b]).partial_cmp(&distance(&entries[*a])).unwrap_or(Ordering::Equal));
+        order.append(&mut translucent);
+
+        let pipeline_for = |entry: &ChunkRenderQueueEntry| {
+            if entry.material.translucent { self.translucent_pipeline.clone() } else { self.vulkan_pipeline.clone() }
+        };
         let mut descriptor_sets = Vec::new();
-        for entry in render_queue.chunk_meshes.iter() {
+        for entry in order.iter().map(|i| &entries[*i]) {
             let uniform_data = ChunksShaders::vertex::ty::Data {
                 world: entry.transform.clone().into(),
                 view: info.view_mat.into(),
@@ -91,7 +126,7 @@ impl RenderPipelineAbstract for ChunkRenderPipeline {
             };
 
             let subbuffer = self.uniform_buffer_pool.next(uniform_data).unwrap();
-            descriptor_sets.push(Arc::new(PersistentDescriptorSet::start(self.vulkan_pipeline.clone(), 0)
+            descriptor_sets.push(Arc::new(PersistentDescriptorSet::start(pipeline_for(entry), 0)
                 .add_sampled_image(info.tex_registry.get(&entry.material.albedo_map_name).unwrap().clone(), self.sampler.clone()).unwrap()
                 .add_buffer(subbuffer).unwrap()
                 .build().unwrap()
@@ -103,8 +138,8 @@ impl RenderPipelineAbstract for ChunkRenderPipeline {
             .begin_render_pass(
                 self.framebuffers.as_ref().unwrap()[info.image_num].clone(), false,
                 vec![::vulkano::format::ClearValue::None, ::vulkano::format::ClearValue::None]).unwrap();
-        for (i, entry) in render_queue.chunk_meshes.iter().enumerate() {
-            cb = cb.draw_indexed(self.vulkan_pipeline.clone(), &DynamicState {
+        for (i, entry) in order.iter().map(|i| &entries[*i]).enumerate() {
+            cb = cb.draw_indexed(pipeline_for(entry), &DynamicState {
                 line_width: None,
                 viewports: Some(vec![Viewport {
                     origin: [0.0, 0.0],
@@ -3,6 +3,7 @@
 
 use ::util::{Camera, Transform};
 use ::input::InputState;
+use ::world::block::{BlockStateID, STONE};
 use winit::VirtualKeyCode;
 use cgmath::{Point3, Vector3, Quaternion, Matrix4, Euler, Deg, InnerSpace};
 use cgmath::Transform as CgmathTransform;
@@ -19,8 +20,8 @@ pub struct PlayerController {
     pub yaw: f64,
     /// ID of the dimension the player is currently in.
     pub dimension_id: u32,
-    /// Block currently selected for placement.
-    pub selected_block: u32,
+    /// Block state currently selected for placement.
+    pub selected_block: BlockStateID,
 }
 
 
@@ -33,7 +34,7 @@ impl PlayerController {
             pitch: 0.0,
             yaw: 0.0,
             dimension_id: 0,
-            selected_block: 1,
+            selected_block: STONE,
         }
     }
 
@@ -26,11 +26,9 @@ impl TextureRegistry {
     }
 
 
-    /// Loads the textures from disk, and onto the GPU.
-    pub fn load(&mut self, queue: Arc<Queue>) {
-        let tex_names = [ String::from("stone"), String::from("dirt"), String::from("grass") ];
-
-        for name in tex_names.iter().clone() {
+    /// Loads the textures with the given names from disk, and onto the GPU.
+    pub fn load(&mut self, queue: Arc<Queue>, tex_names: &[String]) {
+        for name in tex_names.iter() {
             let (texture, _future) = {
                 let mut path_str = String::from("textures/");
                 path_str.push_str(&name);
@@ -79,4 +77,8 @@ impl DimensionRegistry {
     pub fn get(&self, id: u32) -> Option<&Dimension> {
         self.dimensions.get(&id)
     }
+    /// Adds a dimension under its own ID, replacing any dimension which already had that ID.
+    pub fn add(&mut self, dimension: Dimension) {
+        self.dimensions.insert(dimension.id, dimension);
+    }
 }
\ No newline at end of file
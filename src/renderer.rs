@@ -3,7 +3,7 @@
 use std::sync::Arc;
 use std::collections::VecDeque;
 
-use cgmath::{EuclideanSpace, Matrix4, Vector4};
+use cgmath::{EuclideanSpace, Matrix4, Point3, Vector4};
 
 use vulkano::buffer::BufferUsage;
 use vulkano::device::{Device, DeviceExtensions, Queue};
@@ -18,6 +18,7 @@ use winit::Window;
 use util::{Camera, Transform};
 use geometry::{VertexGroup, Material};
 use registry::TextureRegistry;
+use world::block::MASTER_BLOCK_REGISTRY;
 use memory::pool::AutoMemoryPool;
 use pipeline::{RenderPipelineAbstract, SkyboxRenderPipeline, ChunkRenderPipeline, LinesRenderPipeline, PipelineCbCreateInfo};
 
@@ -46,7 +47,9 @@ pub struct RenderQueue {
 pub struct ChunkRenderQueueEntry {
     pub vertex_group: Arc<VertexGroup>,
     pub material: Material,
-    pub transform: Matrix4<f32>
+    pub transform: Matrix4<f32>,
+    /// Middle of the mesh in world space. See Mesh::center.
+    pub center: Point3<f32>,
 }
 
 
@@ -130,7 +133,7 @@ impl Renderer {
         let depth_buffer = ::vulkano::image::attachment::AttachmentImage::transient(device.clone(), dimensions, D32Sfloat).unwrap();
 
         let mut tex_registry = TextureRegistry::new();
-        tex_registry.load(queue.clone());
+        tex_registry.load(queue.clone(), &MASTER_BLOCK_REGISTRY.read().texture_names());
         let tex_registry = Arc::new(tex_registry);
 
         let memory_pool = AutoMemoryPool::new(device.clone());
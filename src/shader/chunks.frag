@@ -42,5 +42,11 @@ void main() {
 
     vec3 lighting = DirectionalLight(normal_world, light_dir, surface_pos);
 
-    f_color = vec4(lighting * texture(tex, uv).xyz * v_color, 1.0);
+    vec4 albedo = texture(tex, uv);
+    // Clear parts of see-through textures, like the gaps in leaves.
+    if (albedo.a < 0.01) {
+        discard;
+    }
+
+    f_color = vec4(lighting * albedo.rgb * v_color, albedo.a);
 }
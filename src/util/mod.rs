@@ -93,21 +93,22 @@ pub mod cube {
     use ::geometry::VertexPositionColorAlpha;
 
 
-    pub fn generate_chunk_debug_line_vertices(x: i32, y: i32, z: i32, a: f32) -> [VertexPositionColorAlpha; 8] {
-        let x = x as f32 * 16f32;
-        let y = y as f32 * 16f32;
-        let z = z as f32 * 16f32;
+    /// Outline of the chunk at (x, y, z), for a dimension whose chunks are sx * sy * sz voxels.
+    pub fn generate_chunk_debug_line_vertices(x: i32, y: i32, z: i32, sx: f32, sy: f32, sz: f32, a: f32) -> [VertexPositionColorAlpha; 8] {
+        let x = x as f32 * sx;
+        let y = y as f32 * sy;
+        let z = z as f32 * sz;
         [
             // top
-            VertexPositionColorAlpha { position: [ x,      y+16.0, z+16.0 ], color: [ 1.0, 1.0, 1.0, a ] },
-            VertexPositionColorAlpha { position: [ x+16.0, y+16.0, z+16.0 ], color: [ 1.0, 1.0, 1.0, a ] },
-            VertexPositionColorAlpha { position: [ x+16.0, y+16.0, z      ], color: [ 1.0, 1.0, 1.0, a ] },
-            VertexPositionColorAlpha { position: [ x,      y+16.0, z      ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x,    y+sy, z+sz ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x+sx, y+sy, z+sz ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x+sx, y+sy, z    ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x,    y+sy, z    ], color: [ 1.0, 1.0, 1.0, a ] },
             // bottom
-            VertexPositionColorAlpha { position: [ x,      y, z+16.0 ], color: [ 1.0, 1.0, 1.0, a ] },
-            VertexPositionColorAlpha { position: [ x+16.0, y, z+16.0 ], color: [ 1.0, 1.0, 1.0, a ] },
-            VertexPositionColorAlpha { position: [ x+16.0, y, z      ], color: [ 1.0, 1.0, 1.0, a ] },
-            VertexPositionColorAlpha { position: [ x,      y, z      ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x,    y, z+sz ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x+sx, y, z+sz ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x+sx, y, z    ], color: [ 1.0, 1.0, 1.0, a ] },
+            VertexPositionColorAlpha { position: [ x,    y, z    ], color: [ 1.0, 1.0, 1.0, a ] },
         ]
     }
 
@@ -0,0 +1,214 @@
+extern crate std;
+extern crate num;
+
+use voxel::voxelstorage::*;
+use voxel::voxelmath::*;
+use voxel::palettevoxelarray::PaletteVoxelArray;
+use voxel::voxelio::*;
+use std::default::Default;
+use std::io::{Read, Write};
+
+/// A 3D array of voxels which stores a region holding only one value as just
+/// that value, with no allocation at all. The first `set` of a differing value
+/// switches it over to a full PaletteVoxelArray.
+/// Should have a fixed, constant size after creation, just like VoxelArray.
+#[derive(Clone, Debug)]
+pub enum CompactVoxelArray<T: Voxel + PartialEq, P: 'static + VoxelCoord + USizeAble> {
+    /// Every position in this array holds the same value.
+    Uniform(VoxelSize<P>, T),
+    /// Mixed contents.
+    Palette(PaletteVoxelArray<T, P>),
+}
+
+impl <T:Voxel + PartialEq, P: 'static + VoxelCoord + USizeAble> CompactVoxelArray<T, P> {
+    /// Builds an array out of a flat buffer laid out the same way as VoxelArray's,
+    /// only allocating if the buffer holds more than one distinct value.
+    pub fn load_new(szx: P, szy: P, szz: P, dat: Vec<T>) -> CompactVoxelArray<T, P> {
+        let is_uniform = match dat.first() {
+            Some(first) => dat.iter().all(|v| *v == *first),
+            None => false,
+        };
+        if is_uniform {
+            let value = dat.into_iter().next().unwrap();
+            return CompactVoxelArray::Uniform(vpos!(szx, szy, szz), value);
+        }
+        CompactVoxelArray::Palette(PaletteVoxelArray::load_new(szx, szy, szz, dat))
+    }
+
+    /// Make a new CompactVoxelArray wherein every value is set to val
+    pub fn new_solid(szx: P, szy: P, szz: P, val:T) -> CompactVoxelArray<T, P> {
+        CompactVoxelArray::Uniform(vpos!(szx, szy, szz), val)
+    }
+
+    /// Is every voxel in this array the same value?
+    pub fn is_uniform(&self) -> bool {
+        match self {
+            CompactVoxelArray::Uniform(_, _) => true,
+            CompactVoxelArray::Palette(_) => false,
+        }
+    }
+
+    /// If every voxel in this array is the same value, returns that value.
+    pub fn uniform_value(&self) -> Option<&T> {
+        match self {
+            CompactVoxelArray::Uniform(_, value) => Some(value),
+            CompactVoxelArray::Palette(_) => None,
+        }
+    }
+
+    /// Approximate heap usage of this array in bytes, not counting anything T itself owns.
+    pub fn get_data_size(&self) -> usize {
+        match self {
+            CompactVoxelArray::Uniform(_, _) => 0,
+            CompactVoxelArray::Palette(inner) => inner.get_data_size(),
+        }
+    }
+
+    /// Replaces every value v in this array with f(v), without visiting each voxel. See PaletteVoxelArray::map_values().
+    pub fn map_values<F: Fn(&T) -> T>(&mut self, f: F) {
+        match self {
+            CompactVoxelArray::Uniform(_, value) => *value = f(value),
+            CompactVoxelArray::Palette(inner) => inner.map_values(f),
+        }
+    }
+}
+
+impl <T:Voxel + PartialEq + Default, P: 'static + VoxelCoord + USizeAble> CompactVoxelArray<T, P> {
+    /// Make a new CompactVoxelArray wherein every value is set to T::Default
+    pub fn new_empty(szx: P, szy: P, szz: P) -> CompactVoxelArray<T, P> { CompactVoxelArray::new_solid(szx, szy, szz,T::default()) }
+}
+
+impl <T: Voxel + PartialEq, P: 'static + VoxelCoord + USizeAble> VoxelStorage<T, P> for CompactVoxelArray<T, P> {
+    fn get(&self, coord: VoxelPos<P>) -> Result<T, VoxelError> {
+        match self {
+            CompactVoxelArray::Uniform(size, value) => {
+                //Bounds-check.
+                if (coord.x >= size.x) ||
+                    (coord.y >= size.y) ||
+                    (coord.z >= size.z)
+                {
+                    return Err(VoxelError::OutOfBounds(format!("{}", coord),
+                        format!("{}", VoxelRange::new(vpos!(P::zero(),P::zero(),P::zero()), *size))));
+                }
+                Ok(value.clone())
+            },
+            CompactVoxelArray::Palette(inner) => inner.get(coord),
+        }
+    }
+
+    fn set(&mut self, coord: VoxelPos<P>, value: T) -> Result<(), VoxelError> {
+        let promoted = match self {
+            CompactVoxelArray::Uniform(size, current) => {
+                if (coord.x >= size.x) ||
+                    (coord.y >= size.y) ||
+                    (coord.z >= size.z)
+                {
+                    return Err(VoxelError::OutOfBounds(format!("{}", coord),
+                        format!("{}", VoxelRange::new(vpos!(P::zero(),P::zero(),P::zero()), *size))));
+                }
+                // Writing the value we already hold everywhere changes nothing.
+                if *current == value {
+                    return Ok(());
+                }
+                PaletteVoxelArray::new_solid(size.x, size.y, size.z, current.clone())
+            },
+            CompactVoxelArray::Palette(inner) => return inner.set(coord, value),
+        };
+        *self = CompactVoxelArray::Palette(promoted);
+        match self {
+            CompactVoxelArray::Palette(inner) => inner.set(coord, value),
+            CompactVoxelArray::Uniform(_, _) => unreachable!(),
+        }
+    }
+}
+
+impl <T, P> VoxelStorageBounded<T, P> for CompactVoxelArray<T, P> where T : Voxel + PartialEq, P : 'static + VoxelCoord + USizeAble {
+    fn get_bounds(&self) -> VoxelRange<P> {
+        match self {
+            CompactVoxelArray::Uniform(size, _) => VoxelRange {lower: VoxelPos{x: P::zero(),y: P::zero(), z:P::zero()}, upper: *size },
+            CompactVoxelArray::Palette(inner) => inner.get_bounds(),
+        }
+    }
+}
+
+/// A uniform array is saved as a Uniform storage whose payload is its one value.
+/// Otherwise, this is saved exactly like the PaletteVoxelArray inside it.
+impl <T, P> VoxelStorageIOAble<T, P> for CompactVoxelArray<T, P> where T : VoxelBinary + PartialEq, P : 'static + VoxelCoord + USizeAble {
+    fn load<R: Read>(reader: &mut R) -> Result<Self, VoxelError> {
+        let (header, payload) = read_storage(reader)?;
+        match header.kind {
+            StorageKind::Uniform => {
+                let size : VoxelSize<P> = header.expect::<T, P>(StorageKind::Uniform)?;
+                let mut payload_reader = PayloadReader::new(&payload);
+                let value = payload_reader.read_voxel::<T>()?;
+                payload_reader.finish()?;
+                Ok(CompactVoxelArray::Uniform(size, value))
+            },
+            StorageKind::Palette => Ok(CompactVoxelArray::Palette(PaletteVoxelArray::load_payload(&header, &payload)?)),
+            kind => Err(VoxelError::CorruptData(format!("A CompactVoxelArray cannot be loaded from a storage of kind {:?}.", kind))),
+        }
+    }
+
+    fn save<W: Write>(&self, writer: &mut W) -> Result<usize, VoxelError> {
+        match self {
+            CompactVoxelArray::Uniform(size, value) => {
+                let mut payload = Vec::with_capacity(T::BYTE_LENGTH);
+                value.write_bytes(&mut payload);
+                write_storage(writer, StorageKind::Uniform, T::TYPE_TAG, *size, &payload)
+            },
+            CompactVoxelArray::Palette(inner) => inner.save(writer),
+        }
+    }
+}
+
+#[test]
+fn test_compact_array_promotes() {
+    let mut test_ca : CompactVoxelArray<u32, u8> = CompactVoxelArray::new_empty(16, 16, 16);
+    assert!(test_ca.is_uniform());
+    assert_eq!(test_ca.get_data_size(), 0);
+    // Setting the value it already holds should not allocate anything.
+    assert!(test_ca.set(vpos!(3, 4, 5), 0).is_ok());
+    assert!(test_ca.is_uniform());
+    assert!(test_ca.set(vpos!(16, 0, 0), 1).is_err());
+
+    assert!(test_ca.set(vpos!(3, 4, 5), 7).is_ok());
+    assert!(!test_ca.is_uniform());
+    assert_eq!(test_ca.get(vpos!(3, 4, 5)).unwrap(), 7);
+    assert_eq!(test_ca.get(vpos!(5, 4, 3)).unwrap(), 0);
+}
+
+#[test]
+fn test_compact_array_load_new() {
+    const OURSIZE : usize  = 16 * 16 * 16;
+    let solid : CompactVoxelArray<u32, u8> = CompactVoxelArray::load_new(16, 16, 16, vec![2; OURSIZE]);
+    assert_eq!(solid.uniform_value(), Some(&2));
+
+    let mut mixed_data = vec![2; OURSIZE];
+    mixed_data[OURSIZE - 1] = 3;
+    let mixed : CompactVoxelArray<u32, u8> = CompactVoxelArray::load_new(16, 16, 16, mixed_data);
+    assert!(!mixed.is_uniform());
+    assert_eq!(mixed.get(vpos!(15, 15, 15)).unwrap(), 3);
+    assert_eq!(mixed.get(vpos!(0, 0, 0)).unwrap(), 2);
+}
+
+#[test]
+fn test_compact_array_map_values() {
+    let mut solid : CompactVoxelArray<u32, u8> = CompactVoxelArray::new_solid(4, 4, 4, 2);
+    solid.map_values(|v| v * 10);
+    assert_eq!(solid.uniform_value(), Some(&20));
+
+    let mut mixed : CompactVoxelArray<u32, u8> = CompactVoxelArray::new_solid(4, 4, 4, 1);
+    mixed.set(vpos!(1, 2, 3), 2).unwrap();
+    mixed.set(vpos!(3, 2, 1), 3).unwrap();
+    // Two values can end up the same.
+    mixed.map_values(|v| if *v == 3 { 2 } else { v + 4 });
+    assert_eq!(mixed.get(vpos!(0, 0, 0)).unwrap(), 5);
+    assert_eq!(mixed.get(vpos!(1, 2, 3)).unwrap(), 6);
+    assert_eq!(mixed.get(vpos!(3, 2, 1)).unwrap(), 2);
+    // Still works when written to afterwards.
+    mixed.set(vpos!(0, 0, 0), 2).unwrap();
+    mixed.set(vpos!(1, 1, 1), 9).unwrap();
+    assert_eq!(mixed.get(vpos!(0, 0, 0)).unwrap(), 2);
+    assert_eq!(mixed.get(vpos!(1, 1, 1)).unwrap(), 9);
+    assert_eq!(mixed.get(vpos!(0, 0, 1)).unwrap(), 5);
+}
@@ -1,4 +1,7 @@
 #[macro_use] pub mod voxelmath;
 pub mod voxelstorage;
 pub mod voxelarray;
+pub mod palettevoxelarray;
+pub mod compactvoxelarray;
+pub mod voxelio;
 pub mod voxelevent;
\ No newline at end of file
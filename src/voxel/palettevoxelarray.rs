@@ -0,0 +1,299 @@
+extern crate std;
+extern crate num;
+
+use voxel::voxelstorage::*;
+use voxel::voxelmath::*;
+use voxel::voxelarray::xyz_to_i;
+use voxel::voxelio::*;
+use std::default::Default;
+use std::io::{Read, Write};
+
+/// Bits in one word of our packed index buffer.
+const WORD_BITS : usize = 64;
+
+/// A 3D array of voxels which keeps each distinct value only once, in a palette,
+/// and stores a bit-packed index into that palette for every position.
+/// Starts out with 1 bit per index, and grows the bit width whenever a
+/// value is written that would overflow the palette.
+/// Should have a fixed, constant size after creation, just like VoxelArray.
+#[derive(Clone, Debug)]
+pub struct PaletteVoxelArray<T: Voxel + PartialEq, P: 'static + VoxelCoord + USizeAble> {
+    size_x: P, size_y: P, size_z: P,
+    palette: Vec<T>,
+    bits_per_index: usize,
+    data: Vec<u64>,
+}
+
+/// How many u64s it takes to hold num_elements indices of bits_per_index bits.
+fn words_for(num_elements: usize, bits_per_index: usize) -> usize {
+    ((num_elements * bits_per_index) + (WORD_BITS - 1)) / WORD_BITS
+}
+
+/// Smallest bit width (minimum 1) which can index a palette of the given length.
+fn bits_for_palette_len(len: usize) -> usize {
+    let mut bits = 1;
+    while (1usize << bits) < len {
+        bits += 1;
+    }
+    bits
+}
+
+fn read_packed(data: &[u64], bits_per_index: usize, i: usize) -> usize {
+    let mask : u64 = (1u64 << bits_per_index) - 1;
+    let bit = i * bits_per_index;
+    let word = bit / WORD_BITS;
+    let offset = bit % WORD_BITS;
+    let mut value = data[word] >> offset;
+    // Does this index spill over into the next word?
+    if offset + bits_per_index > WORD_BITS {
+        value |= data[word + 1] << (WORD_BITS - offset);
+    }
+    (value & mask) as usize
+}
+
+fn write_packed(data: &mut [u64], bits_per_index: usize, i: usize, value: usize) {
+    let mask : u64 = (1u64 << bits_per_index) - 1;
+    let value = (value as u64) & mask;
+    let bit = i * bits_per_index;
+    let word = bit / WORD_BITS;
+    let offset = bit % WORD_BITS;
+    data[word] = (data[word] & !(mask << offset)) | (value << offset);
+    if offset + bits_per_index > WORD_BITS {
+        let spilled = WORD_BITS - offset;
+        data[word + 1] = (data[word + 1] & !(mask >> spilled)) | (value >> spilled);
+    }
+}
+
+impl <T:Voxel + PartialEq, P: 'static + VoxelCoord + USizeAble> PaletteVoxelArray<T, P> {
+    /// Builds a palette array out of a flat buffer laid out the same way as VoxelArray's.
+    pub fn load_new(szx: P, szy: P, szz: P, dat: Vec<T>) -> PaletteVoxelArray<T, P> {
+        let num_elements = szx.as_usize() * szy.as_usize() * szz.as_usize();
+        assert_eq!(dat.len(), num_elements);
+        let mut palette : Vec<T> = Vec::new();
+        let mut indices : Vec<usize> = Vec::with_capacity(num_elements);
+        for value in dat.into_iter() {
+            match palette.iter().position(|p| *p == value) {
+                Some(idx) => indices.push(idx),
+                None => {
+                    indices.push(palette.len());
+                    palette.push(value);
+                },
+            }
+        }
+        let bits_per_index = bits_for_palette_len(palette.len());
+        let mut data = vec![0u64; words_for(num_elements, bits_per_index)];
+        for (i, idx) in indices.iter().enumerate() {
+            write_packed(&mut data, bits_per_index, i, *idx);
+        }
+        PaletteVoxelArray{size_x: szx, size_y: szy, size_z: szz, palette: palette, bits_per_index: bits_per_index, data: data}
+    }
+
+    /// Make a new PaletteVoxelArray wherein every value is set to val
+    pub fn new_solid(szx: P, szy: P, szz: P, val:T) -> PaletteVoxelArray<T, P> {
+        let num_elements = szx.as_usize() * szy.as_usize() * szz.as_usize();
+        PaletteVoxelArray{size_x: szx, size_y: szy, size_z: szz,
+                        palette: vec![val],
+                        bits_per_index: 1,
+                        data: vec![0u64; words_for(num_elements, 1)] }
+    }
+
+    /// The distinct values this array has ever held. Never shrinks.
+    pub fn palette(&self) -> &Vec<T> { &self.palette }
+
+    /// Replaces every value v in this array with f(v). Only touches the palette, so it's cheap no matter how big the array is.
+    pub fn map_values<F: Fn(&T) -> T>(&mut self, f: F) {
+        for value in self.palette.iter_mut() {
+            *value = f(value);
+        }
+    }
+
+    /// Current width, in bits, of each index into our palette.
+    pub fn bits_per_index(&self) -> usize { self.bits_per_index }
+
+    /// Approximate heap usage of this array in bytes, not counting anything T itself owns.
+    pub fn get_data_size(&self) -> usize {
+        (self.data.len() * std::mem::size_of::<u64>()) + (self.palette.len() * std::mem::size_of::<T>())
+    }
+
+    /// Unpacks this array into a flat buffer, laid out the same way as VoxelArray's.
+    pub fn to_vec(&self) -> Vec<T> {
+        let num_elements = self.size_x.as_usize() * self.size_y.as_usize() * self.size_z.as_usize();
+        let mut result = Vec::with_capacity(num_elements);
+        for i in 0..num_elements {
+            result.push(self.palette[read_packed(&self.data, self.bits_per_index, i)].clone());
+        }
+        result
+    }
+
+    /// Finds value in our palette, adding it (and widening our indices if needed) if it isn't there yet.
+    fn palette_index_for(&mut self, value: T) -> usize {
+        if let Some(idx) = self.palette.iter().position(|p| *p == value) {
+            return idx;
+        }
+        let idx = self.palette.len();
+        self.palette.push(value);
+        let needed_bits = bits_for_palette_len(self.palette.len());
+        if needed_bits > self.bits_per_index {
+            self.repack(needed_bits);
+        }
+        idx
+    }
+
+    /// Copies every index into a new buffer with a different bit width.
+    fn repack(&mut self, new_bits: usize) {
+        let num_elements = self.size_x.as_usize() * self.size_y.as_usize() * self.size_z.as_usize();
+        let mut new_data = vec![0u64; words_for(num_elements, new_bits)];
+        for i in 0..num_elements {
+            write_packed(&mut new_data, new_bits, i, read_packed(&self.data, self.bits_per_index, i));
+        }
+        self.data = new_data;
+        self.bits_per_index = new_bits;
+    }
+
+    fn bounds_error(&self, coord: VoxelPos<P>) -> VoxelError {
+        VoxelError::OutOfBounds(format!("{}", coord),
+            format!("{}", VoxelRange::new(vpos!(P::zero(),P::zero(),P::zero()),
+                            vpos!(self.size_x, self.size_y, self.size_z))))
+    }
+}
+
+impl <T:Voxel + PartialEq + Default, P: 'static + VoxelCoord + USizeAble> PaletteVoxelArray<T, P> {
+    /// Make a new PaletteVoxelArray wherein every value is set to T::Default
+    pub fn new_empty(szx: P, szy: P, szz: P) -> PaletteVoxelArray<T, P> { PaletteVoxelArray::new_solid(szx, szy, szz,T::default()) }
+}
+
+impl <T: Voxel + PartialEq, P: 'static + VoxelCoord + USizeAble> VoxelStorage<T, P> for PaletteVoxelArray<T, P> {
+    fn get(&self, coord: VoxelPos<P>) -> Result<T, VoxelError> {
+        //Bounds-check.
+        if (coord.x >= self.size_x) ||
+            (coord.y >= self.size_y) ||
+            (coord.z >= self.size_z)
+        {
+            return Err(self.bounds_error(coord));
+        }
+        let i = xyz_to_i(coord.x, coord.y, coord.z, self.size_x, self.size_y, self.size_z);
+        match self.palette.get(read_packed(&self.data, self.bits_per_index, i)) {
+            Some(value) => Ok(value.clone()),
+            // An index pointing past the end of our palette means our data got mangled somehow.
+            None => Err(VoxelError::InvalidValueAt(format!("{}", coord))),
+        }
+    }
+
+    fn set(&mut self, coord: VoxelPos<P>, value: T) -> Result<(), VoxelError> {
+        if (coord.x >= self.size_x) ||
+            (coord.y >= self.size_y) ||
+            (coord.z >= self.size_z)
+        {
+            return Err(self.bounds_error(coord));
+        }
+        let i = xyz_to_i(coord.x, coord.y, coord.z, self.size_x, self.size_y, self.size_z);
+        let idx = self.palette_index_for(value);
+        write_packed(&mut self.data, self.bits_per_index, i, idx);
+        return Ok(());
+    }
+}
+
+impl <T, P> VoxelStorageBounded<T, P> for PaletteVoxelArray<T, P> where T : Voxel + PartialEq, P : 'static + VoxelCoord + USizeAble {
+    fn get_bounds(&self) -> VoxelRange<P> { VoxelRange {lower: VoxelPos{x: P::zero(),y: P::zero(), z:P::zero()},
+                                            upper: VoxelPos{x: self.size_x, y: self.size_y, z: self.size_z} } }
+}
+
+impl <T, P> PaletteVoxelArray<T, P> where T : VoxelBinary + PartialEq, P : 'static + VoxelCoord + USizeAble {
+    /// Rebuilds a palette array from an already-read header and payload, for storages which wrap this one.
+    pub fn load_payload(header: &StorageHeader, payload: &[u8]) -> Result<Self, VoxelError> {
+        let size : VoxelSize<P> = header.expect::<T, P>(StorageKind::Palette)?;
+        let num_elements = size.x.as_usize() * size.y.as_usize() * size.z.as_usize();
+        let mut payload_reader = PayloadReader::new(payload);
+
+        let palette_len = payload_reader.read_u32()? as usize;
+        if palette_len == 0 && num_elements != 0 {
+            return Err(VoxelError::CorruptData("Palette array has voxels but an empty palette.".to_owned()));
+        }
+        // Each entry takes at least one byte, so this can't be any bigger than the payload.
+        if palette_len > payload.len() {
+            return Err(VoxelError::CorruptData(format!("Palette length {} is longer than the whole payload.", palette_len)));
+        }
+        let mut palette = Vec::with_capacity(palette_len);
+        for _ in 0..palette_len {
+            palette.push(payload_reader.read_voxel::<T>()?);
+        }
+        let bits_per_index = payload_reader.read_u8()? as usize;
+        if bits_per_index < bits_for_palette_len(palette_len) || bits_per_index > 32 {
+            return Err(VoxelError::CorruptData(format!("{} bits per index is invalid for a palette of length {}.", bits_per_index, palette_len)));
+        }
+        let num_words = words_for(num_elements, bits_per_index);
+        if payload.len() - (4 + (palette_len * T::BYTE_LENGTH) + 1) != num_words * 8 {
+            return Err(VoxelError::CorruptData(format!("Expected {} words of packed indices.", num_words)));
+        }
+        let mut data = Vec::with_capacity(num_words);
+        for _ in 0..num_words {
+            data.push(payload_reader.read_u64()?);
+        }
+        payload_reader.finish()?;
+
+        for i in 0..num_elements {
+            if read_packed(&data, bits_per_index, i) >= palette_len {
+                return Err(VoxelError::CorruptData(format!("Voxel {} points past the end of its palette.", i)));
+            }
+        }
+        Ok(PaletteVoxelArray{size_x: size.x, size_y: size.y, size_z: size.z, palette: palette, bits_per_index: bits_per_index, data: data})
+    }
+}
+
+/// Payload is the palette length (u32), each palette entry, the bit width (u8), and then the packed words.
+impl <T, P> VoxelStorageIOAble<T, P> for PaletteVoxelArray<T, P> where T : VoxelBinary + PartialEq, P : 'static + VoxelCoord + USizeAble {
+    fn load<R: Read>(reader: &mut R) -> Result<Self, VoxelError> {
+        let (header, payload) = read_storage(reader)?;
+        PaletteVoxelArray::load_payload(&header, &payload)
+    }
+
+    fn save<W: Write>(&self, writer: &mut W) -> Result<usize, VoxelError> {
+        let mut payload = Vec::with_capacity(4 + (self.palette.len() * T::BYTE_LENGTH) + 1 + (self.data.len() * 8));
+        payload.extend_from_slice(&(self.palette.len() as u32).to_le_bytes());
+        for value in self.palette.iter() {
+            value.write_bytes(&mut payload);
+        }
+        payload.push(self.bits_per_index as u8);
+        for word in self.data.iter() {
+            payload.extend_from_slice(&word.to_le_bytes());
+        }
+        write_storage(writer, StorageKind::Palette, T::TYPE_TAG, vpos!(self.size_x, self.size_y, self.size_z), &payload)
+    }
+}
+
+#[test]
+fn test_palette_array_raccess() {
+    const OURSIZE : usize  = 16 * 16 * 16;
+    let mut test_chunk : Vec<u16> = Vec::with_capacity(OURSIZE);
+    for i in 0 .. OURSIZE {
+        test_chunk.push((i % 300) as u16);
+    }
+
+    let mut test_pa : PaletteVoxelArray<u16,u16> = PaletteVoxelArray::load_new(16, 16, 16, test_chunk.clone());
+    // 300 distinct values need 9 bits.
+    assert_eq!(test_pa.bits_per_index(), 9);
+    assert_eq!(test_pa.to_vec(), test_chunk);
+
+    let testpos = VoxelPos{x: 14, y: 14, z: 14};
+    assert!(test_pa.get(testpos).unwrap() == (3822 % 300) as u16);
+    assert!(test_pa.set(testpos, 9001).is_ok());
+    assert!(test_pa.get(testpos).unwrap() == 9001);
+    assert!(test_pa.get(VoxelPos{x: 16, y: 0, z: 0}).is_err());
+}
+
+#[test]
+fn test_palette_array_grows() {
+    let mut test_pa : PaletteVoxelArray<u32, u8> = PaletteVoxelArray::new_empty(16, 16, 16);
+    assert_eq!(test_pa.bits_per_index(), 1);
+    for pos in test_pa.get_bounds() {
+        assert!(test_pa.set(pos, (pos.x as u32 + pos.z as u32) % 5).is_ok());
+    }
+    // 0 through 4 need 3 bits.
+    assert_eq!(test_pa.palette().len(), 5);
+    assert_eq!(test_pa.bits_per_index(), 3);
+    for pos in test_pa.get_bounds() {
+        assert_eq!(test_pa.get(pos).unwrap(), (pos.x as u32 + pos.z as u32) % 5);
+    }
+    // Much smaller than the 16 KiB an equivalent VoxelArray<u32, u8> costs.
+    assert!(test_pa.get_data_size() < 2048);
+}
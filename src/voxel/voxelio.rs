@@ -0,0 +1,281 @@
+//! Binary format shared by every VoxelStorageIOAble implementation.
+//!
+//! Everything is little-endian regardless of platform. A saved storage is a fixed-size
+//! header followed by a payload whose layout depends on the kind of storage:
+//!
+//! | Field          | Type      |
+//! |----------------|-----------|
+//! | magic          | `b"VXST"` |
+//! | format version | `u16`     |
+//! | storage kind   | `u8`      |
+//! | element type   | `u8`      |
+//! | size x, y, z   | `u32` * 3 |
+//! | payload length | `u32`     |
+//! | checksum       | `u64`, FNV-1a of the payload |
+
+extern crate std;
+extern crate fnv;
+
+use std::hash::Hasher;
+use std::io::{Read, Write};
+
+use self::fnv::FnvHasher;
+
+use voxel::voxelmath::*;
+use voxel::voxelstorage::{Voxel, VoxelError};
+
+pub const STORAGE_MAGIC : [u8; 4] = *b"VXST";
+/// Bump this whenever the layout of the header or any payload changes.
+pub const STORAGE_FORMAT_VERSION : u16 = 1;
+pub const HEADER_LENGTH : usize = 4 + 2 + 1 + 1 + (4 * 3) + 4 + 8;
+
+/// Which VoxelStorage implementation wrote a payload.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StorageKind {
+    Array,
+    Palette,
+    Uniform,
+}
+
+impl StorageKind {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            StorageKind::Array => 0,
+            StorageKind::Palette => 1,
+            StorageKind::Uniform => 2,
+        }
+    }
+    pub fn from_byte(byte: u8) -> Option<StorageKind> {
+        match byte {
+            0 => Some(StorageKind::Array),
+            1 => Some(StorageKind::Palette),
+            2 => Some(StorageKind::Uniform),
+            _ => None,
+        }
+    }
+}
+
+/// A voxel type with a fixed-length, endian-stable binary representation.
+pub trait VoxelBinary : Voxel + Sized {
+    /// Written into the header so we never read u16s back as u32s.
+    const TYPE_TAG : u8;
+    const BYTE_LENGTH : usize;
+    fn write_bytes(&self, out: &mut Vec<u8>);
+    /// `bytes` is always exactly BYTE_LENGTH long.
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+macro_rules! impl_voxel_binary {
+    ($ty:ty, $tag:expr, $len:expr) => {
+        impl VoxelBinary for $ty {
+            const TYPE_TAG : u8 = $tag;
+            const BYTE_LENGTH : usize = $len;
+            fn write_bytes(&self, out: &mut Vec<u8>) { out.extend_from_slice(&self.to_le_bytes()); }
+            fn read_bytes(bytes: &[u8]) -> Self {
+                let mut buf = [0u8; $len];
+                buf.copy_from_slice(bytes);
+                <$ty>::from_le_bytes(buf)
+            }
+        }
+    };
+}
+
+impl_voxel_binary!(u8, 1, 1);
+impl_voxel_binary!(u16, 2, 2);
+impl_voxel_binary!(u32, 3, 4);
+impl_voxel_binary!(u64, 4, 8);
+
+/// Parsed and validated header of a saved storage.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StorageHeader {
+    pub kind : StorageKind,
+    pub element_type : u8,
+    pub size : VoxelSize<u32>,
+}
+
+impl StorageHeader {
+    /// Makes sure this header describes what the caller is expecting to load, and converts its size to P.
+    pub fn expect<T: VoxelBinary, P: VoxelCoord + USizeAble>(&self, kind: StorageKind) -> Result<VoxelSize<P>, VoxelError> {
+        if self.kind != kind {
+            return Err(VoxelError::CorruptData(format!("Expected a storage of kind {:?}, found {:?}.", kind, self.kind)));
+        }
+        if self.element_type != T::TYPE_TAG {
+            return Err(VoxelError::CorruptData(format!("Expected voxel element type {}, found {}.", T::TYPE_TAG, self.element_type)));
+        }
+        Ok(vpos!(coord_from_u32(self.size.x)?, coord_from_u32(self.size.y)?, coord_from_u32(self.size.z)?))
+    }
+}
+
+fn coord_from_u32<P: VoxelCoord + USizeAble>(value: u32) -> Result<P, VoxelError> {
+    let converted = P::from_usize(value as usize);
+    if converted.as_usize() != value as usize {
+        return Err(VoxelError::CorruptData(format!("Storage size {} does not fit in this storage's position type.", value)));
+    }
+    Ok(converted)
+}
+
+pub fn checksum(payload: &[u8]) -> u64 {
+    let mut hasher = FnvHasher::default();
+    hasher.write(payload);
+    hasher.finish()
+}
+
+/// Writes a header and the payload it describes. Returns the number of bytes written.
+pub fn write_storage<W: Write, P: VoxelCoord + USizeAble>(writer: &mut W, kind: StorageKind, element_type: u8,
+                                        size: VoxelSize<P>, payload: &[u8]) -> Result<usize, VoxelError> {
+    let mut header : Vec<u8> = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(&STORAGE_MAGIC);
+    header.extend_from_slice(&STORAGE_FORMAT_VERSION.to_le_bytes());
+    header.push(kind.to_byte());
+    header.push(element_type);
+    header.extend_from_slice(&(size.x.as_usize() as u32).to_le_bytes());
+    header.extend_from_slice(&(size.y.as_usize() as u32).to_le_bytes());
+    header.extend_from_slice(&(size.z.as_usize() as u32).to_le_bytes());
+    header.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    header.extend_from_slice(&checksum(payload).to_le_bytes());
+
+    writer.write_all(&header).map_err(|e| VoxelError::Other(Box::new(e)))?;
+    writer.write_all(payload).map_err(|e| VoxelError::Other(Box::new(e)))?;
+    Ok(header.len() + payload.len())
+}
+
+/// Reads a header and its payload, checking the magic number, version and checksum.
+/// The caller is responsible for checking the kind and element type with StorageHeader::expect().
+pub fn read_storage<R: Read>(reader: &mut R) -> Result<(StorageHeader, Vec<u8>), VoxelError> {
+    let mut header_bytes = [0u8; HEADER_LENGTH];
+    reader.read_exact(&mut header_bytes).map_err(|e| VoxelError::CorruptData(format!("Could not read storage header: {}", e)))?;
+    let mut header = PayloadReader::new(&header_bytes);
+    if header.read_slice(4)? != STORAGE_MAGIC {
+        return Err(VoxelError::CorruptData("Storage header has the wrong magic number.".to_owned()));
+    }
+    let version = header.read_u16()?;
+    if version != STORAGE_FORMAT_VERSION {
+        return Err(VoxelError::CorruptData(format!("Unsupported storage format version {}, expected {}.", version, STORAGE_FORMAT_VERSION)));
+    }
+    let kind_byte = header.read_u8()?;
+    let kind = match StorageKind::from_byte(kind_byte) {
+        Some(kind) => kind,
+        None => return Err(VoxelError::CorruptData(format!("Unknown storage kind {}.", kind_byte))),
+    };
+    let element_type = header.read_u8()?;
+    let size = vpos!(header.read_u32()?, header.read_u32()?, header.read_u32()?);
+    let payload_length = header.read_u32()?;
+    let expected_checksum = header.read_u64()?;
+
+    // Don't trust payload_length enough to allocate it all up front.
+    let mut payload = Vec::new();
+    reader.take(payload_length as u64).read_to_end(&mut payload).map_err(|e| VoxelError::Other(Box::new(e)))?;
+    if payload.len() != payload_length as usize {
+        return Err(VoxelError::CorruptData(format!("Storage payload is truncated: expected {} bytes, got {}.", payload_length, payload.len())));
+    }
+    if checksum(&payload) != expected_checksum {
+        return Err(VoxelError::CorruptData("Storage payload does not match its checksum.".to_owned()));
+    }
+    Ok((StorageHeader { kind : kind, element_type : element_type, size : size }, payload))
+}
+
+/// Cursor over a payload which reports running off the end as corruption rather than panicking.
+pub struct PayloadReader<'a> {
+    bytes : &'a [u8],
+    pos : usize,
+}
+
+impl <'a> PayloadReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> PayloadReader<'a> { PayloadReader { bytes : bytes, pos : 0 } }
+
+    pub fn read_slice(&mut self, len: usize) -> Result<&'a [u8], VoxelError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(VoxelError::CorruptData(format!("Unexpected end of data at byte {}.", self.pos)));
+        }
+        let slice = &self.bytes[self.pos .. self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+    pub fn read_u8(&mut self) -> Result<u8, VoxelError> { Ok(self.read_slice(1)?[0]) }
+    pub fn read_u16(&mut self) -> Result<u16, VoxelError> { Ok(u16::read_bytes(self.read_slice(2)?)) }
+    pub fn read_u32(&mut self) -> Result<u32, VoxelError> { Ok(u32::read_bytes(self.read_slice(4)?)) }
+    pub fn read_u64(&mut self) -> Result<u64, VoxelError> { Ok(u64::read_bytes(self.read_slice(8)?)) }
+    pub fn read_voxel<T: VoxelBinary>(&mut self) -> Result<T, VoxelError> { Ok(T::read_bytes(self.read_slice(T::BYTE_LENGTH)?)) }
+
+    /// Errors if there's anything left over, which would mean we misread the payload's layout.
+    pub fn finish(&self) -> Result<(), VoxelError> {
+        if self.pos != self.bytes.len() {
+            return Err(VoxelError::CorruptData(format!("{} unexpected trailing bytes in storage payload.", self.bytes.len() - self.pos)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+use voxel::voxelstorage::{VoxelStorage, VoxelStorageIOAble};
+#[cfg(test)]
+use voxel::voxelarray::VoxelArray;
+#[cfg(test)]
+use voxel::palettevoxelarray::PaletteVoxelArray;
+#[cfg(test)]
+use voxel::compactvoxelarray::CompactVoxelArray;
+
+#[test]
+fn test_storage_round_trip() {
+    const OURSIZE : usize  = 16 * 16 * 16;
+    let mut test_chunk : Vec<u32> = Vec::with_capacity(OURSIZE);
+    for i in 0 .. OURSIZE {
+        test_chunk.push((i % 37) as u32);
+    }
+
+    let array : VoxelArray<u32, u8> = VoxelArray::load_new(16, 16, 16, test_chunk.clone());
+    let mut buf : Vec<u8> = Vec::new();
+    let written = array.save(&mut buf).unwrap();
+    assert_eq!(written, buf.len());
+    assert_eq!(buf.len(), HEADER_LENGTH + OURSIZE * 4);
+    let loaded : VoxelArray<u32, u8> = VoxelArray::load(&mut buf.as_slice()).unwrap();
+    for pos in VoxelRange::new(vpos!(0, 0, 0), vpos!(16, 16, 16)) {
+        assert_eq!(loaded.get(pos).unwrap(), array.get(pos).unwrap());
+    }
+
+    let palette : PaletteVoxelArray<u32, u8> = PaletteVoxelArray::load_new(16, 16, 16, test_chunk.clone());
+    let mut buf : Vec<u8> = Vec::new();
+    palette.save(&mut buf).unwrap();
+    let loaded : PaletteVoxelArray<u32, u8> = PaletteVoxelArray::load(&mut buf.as_slice()).unwrap();
+    assert_eq!(loaded.to_vec(), test_chunk);
+
+    let uniform : CompactVoxelArray<u32, u8> = CompactVoxelArray::new_solid(16, 16, 16, 3);
+    let mut buf : Vec<u8> = Vec::new();
+    uniform.save(&mut buf).unwrap();
+    assert_eq!(buf.len(), HEADER_LENGTH + 4);
+    let loaded : CompactVoxelArray<u32, u8> = CompactVoxelArray::load(&mut buf.as_slice()).unwrap();
+    assert_eq!(loaded.uniform_value(), Some(&3));
+
+    let mixed : CompactVoxelArray<u32, u8> = CompactVoxelArray::load_new(16, 16, 16, test_chunk.clone());
+    let mut buf : Vec<u8> = Vec::new();
+    mixed.save(&mut buf).unwrap();
+    let loaded : CompactVoxelArray<u32, u8> = CompactVoxelArray::load(&mut buf.as_slice()).unwrap();
+    assert_eq!(loaded.get(vpos!(15, 15, 15)).unwrap(), ((OURSIZE - 1) % 37) as u32);
+}
+
+#[test]
+fn test_storage_rejects_corrupt_data() {
+    let mut palette : PaletteVoxelArray<u32, u8> = PaletteVoxelArray::new_empty(16, 16, 16);
+    palette.set(vpos!(1, 2, 3), 4).unwrap();
+    let mut buf : Vec<u8> = Vec::new();
+    palette.save(&mut buf).unwrap();
+
+    // Flip one bit in the payload - the checksum should catch it.
+    let mut flipped = buf.clone();
+    flipped[HEADER_LENGTH + 2] ^= 0x10;
+    assert!(PaletteVoxelArray::<u32, u8>::load(&mut flipped.as_slice()).is_err());
+
+    let truncated = &buf[0 .. buf.len() - 5];
+    assert!(PaletteVoxelArray::<u32, u8>::load(&mut &truncated[..]).is_err());
+
+    let mut bad_magic = buf.clone();
+    bad_magic[0] = b'Q';
+    assert!(PaletteVoxelArray::<u32, u8>::load(&mut bad_magic.as_slice()).is_err());
+
+    // Right storage kind, wrong element type.
+    assert!(PaletteVoxelArray::<u16, u8>::load(&mut buf.as_slice()).is_err());
+    // A VoxelArray can't be loaded from a palette.
+    assert!(VoxelArray::<u32, u8>::load(&mut buf.as_slice()).is_err());
+    // Empty input.
+    assert!(PaletteVoxelArray::<u32, u8>::load(&mut &[0u8; 0][..]).is_err());
+}
@@ -292,6 +292,13 @@ impl <T> VoxelRange<T> where T : VoxelCoord {
             },
         }
     }
+    /// The smallest range containing both this range and other.
+    pub fn get_union(&self, other : &VoxelRange<T>) -> VoxelRange<T> {
+        let a = self.get_validated();
+        let b = other.get_validated();
+        VoxelRange{lower: vpos!(cmp::min(a.lower.x, b.lower.x), cmp::min(a.lower.y, b.lower.y), cmp::min(a.lower.z, b.lower.z)),
+                    upper: vpos!(cmp::max(a.upper.x, b.upper.x), cmp::max(a.upper.y, b.upper.y), cmp::max(a.upper.z, b.upper.z))}
+    }
     /// Does the provided point fall within this VoxelRange?
     pub fn contains(&self, point : VoxelPos<T>) -> bool { 
          ( point.x >= self.lower.x ) && ( point.x < self.upper.x ) &&
@@ -949,3 +956,11 @@ fn test_contains() {
         assert!( ran.contains(i) );
     }
 }
+
+#[test]
+fn test_range_union() {
+    let a : VoxelRange<i32> = VoxelRange::new(vpos!(0, 0, 0), vpos!(2, 2, 2));
+    let b : VoxelRange<i32> = VoxelRange::new(vpos!(-3, 1, 5), vpos!(1, 2, 6));
+    assert_eq!(a.get_union(&b), VoxelRange::new(vpos!(-3, 0, 0), vpos!(2, 2, 6)));
+    assert_eq!(a.get_union(&a), a);
+}
@@ -8,6 +8,7 @@ use voxel::voxelevent::{VoxelEvent, VoxelEventInner};
 use std::error;
 use std::error::Error;
 use std::result::Result;
+use std::io::{Read, Write};
 
 pub trait Voxel : Clone + Debug {}
 impl<T> Voxel for T where T : Clone + Debug {}
@@ -17,6 +18,7 @@ pub enum VoxelErrorKind {
     NotYetLoaded,
     SetInvalidValue,
     InvalidValueAt,
+    CorruptData,
     Other,
 }
 /// An error reported upon trying to get or set a voxel outside of our range. 
@@ -26,6 +28,7 @@ pub enum VoxelError {
     NotYetLoaded(String),
     SetInvalidValue(String),
     InvalidValueAt(String),
+    CorruptData(String),
     Other(Box<dyn error::Error + 'static>),
 }
 
@@ -36,6 +39,7 @@ impl VoxelError {
             VoxelError::NotYetLoaded(_) => VoxelErrorKind::NotYetLoaded,
             VoxelError::SetInvalidValue(_) => VoxelErrorKind::SetInvalidValue,
             VoxelError::InvalidValueAt(_) => VoxelErrorKind::InvalidValueAt,
+            VoxelError::CorruptData(_) => VoxelErrorKind::CorruptData,
             VoxelError::Other(_) => VoxelErrorKind::Other,
         }
     }
@@ -48,6 +52,7 @@ impl Display for VoxelError {
             VoxelError::NotYetLoaded(pos) => write!(f, "Attempted to access a voxel position {}, which is not yet loaded.", pos),
             VoxelError::SetInvalidValue(pos) => write!(f, "Attempted to set voxel at {} to an invalid value.", pos),
             VoxelError::InvalidValueAt(pos) => write!(f, "Voxel at {} contains an invalid value, most likely corrupt.", pos),
+            VoxelError::CorruptData(reason) => write!(f, "Could not load voxel data, it is corrupt or in an unknown format: {}", reason),
             VoxelError::Other(err) => write!(f, "Other voxel error: {}", err),
         }
     }
@@ -93,12 +98,15 @@ pub trait VoxelStorage<T: Voxel, P: VoxelCoord> {
         Ok(())
     }
 }
-/*
-pub trait VoxelStorageIOAble<T : Clone, P: Copy + Integer> : VoxelStorage<T, P> where P : Copy + Integer {
-    fn load<R: Read + Sized>(&mut self, reader: &mut R);
-    fn save<W: Write + Sized>(&self, writer: &mut W) -> Result<usize, std::io::Error>;
+
+/// Any VoxelStorage which can be saved to and loaded from a byte stream.
+/// See voxel::voxelio for the format - it's versioned, little-endian on every
+/// platform, and checksummed, so loading bad data gives you a VoxelError rather than garbage.
+pub trait VoxelStorageIOAble<T: Voxel, P: VoxelCoord> : VoxelStorage<T, P> + Sized {
+    fn load<R: Read>(reader: &mut R) -> Result<Self, VoxelError>;
+    /// Returns the number of bytes written.
+    fn save<W: Write>(&self, writer: &mut W) -> Result<usize, VoxelError>;
 }
-*/
 
 /// Any VoxelStorage which has defined, finite bounds.
 /// Must provide a valid voxel for any position within
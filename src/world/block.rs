@@ -1,43 +1,965 @@
+//! Block IDs, names, definitions and states.
+//!
+//! Every block's properties come from a BlockDef, usually loaded from a JSON file in BLOCK_DEFINITIONS_DIR,
+//! which looks like this:
+//!
+//! ```text
+//! {
+//!     "name": "grass",
+//!     "textures": { "all": "dirt", "top": "grass" },
+//!     "hardness": 0.6,
+//!     "light_emission": 0,
+//!     "collision_box": [[0, 0, 0], [1, 1, 1]],
+//!     "properties": { "snowy": ["false", "true"] }
+//! }
+//! ```
+//!
+//! Everything but the name is optional. See BlockDef for the defaults. Blocks are full cubes unless they
+//! name a "model", which is loaded from BLOCK_MODELS_DIR; see blockmodel. Some states can turn their model
+//! around, so that e.g. "model_rotation": { "facing": { "east": 90 } } turns stairs facing east by 90 degrees.
+//!
+//! Chunks don't store blocks, they store block states: a block along with a value for each of its properties,
+//! like which way it's facing. Every combination of values a block can have gets its own BlockStateID, and
+//! a state is written down (in saves, or when talking to a server) by its name, e.g. "door[facing=north,half=top]".
+
 extern crate string_cache;
 extern crate parking_lot;
+extern crate serde_json;
 
 use self::string_cache::DefaultAtom as Atom;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use self::parking_lot::RwLock;
+use serde::Deserialize;
+
+use cgmath::{Point3, Vector3};
 
-use self::parking_lot::Mutex;
-use voxel::voxelarray::VoxelArray;
+use voxel::compactvoxelarray::CompactVoxelArray;
+use voxel::voxelmath::{VoxelAxis, VoxelPos};
+use world::blockmodel::{BlockModel, ray_hits_box};
 
 pub type BlockID = u32;
 pub type BlockName = Atom;
-pub type Chunk = VoxelArray<BlockID, u8>;
+pub type BlockStateID = u32;
+pub type Chunk = CompactVoxelArray<BlockStateID, u8>;
+
+/// Where block definitions are loaded from, one JSON file per block.
+pub const BLOCK_DEFINITIONS_DIR : &str = "blocks";
+
+/// Blocks the engine itself relies on, which always have these IDs. Air is what empty space is made of,
+/// missing stands in for blocks saved worlds have but we don't, and the rest are what our world generators
+/// build with. Any other block gets its ID when it's registered.
+///
+/// A block's default state gets its ID as soon as the block is registered, and these are registered first,
+/// so each of these is also the BlockStateID of that block's default state.
+pub const AIR : BlockID = 0;
+pub const STONE : BlockID = 1;
+pub const DIRT : BlockID = 2;
+pub const GRASS : BlockID = 3;
+pub const LOG : BlockID = 4;
+pub const LEAVES : BlockID = 5;
+pub const COAL_ORE : BlockID = 6;
+pub const MISSING : BlockID = 7;
+
+/// Names of the blocks above, in ID order.
+const BUILTIN_BLOCKS : [&str; 8] = ["air", "stone", "dirt", "grass", "log", "leaves", "coal_ore", "missing"];
+
+/// The most states one block can have, so a typo in a definition can't eat all of our memory.
+pub const MAX_STATES_PER_BLOCK : usize = 4096;
+
+/// Which textures go on which faces of a block. Faces without a texture of their own fall back to
+/// side (for the four faces around the block), and then to all.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BlockTextures {
+    pub all: Option<String>,
+    pub side: Option<String>,
+    /// +Y
+    pub top: Option<String>,
+    /// -Y
+    pub bottom: Option<String>,
+    /// +X
+    pub east: Option<String>,
+    /// -X
+    pub west: Option<String>,
+    /// +Z
+    pub south: Option<String>,
+    /// -Z
+    pub north: Option<String>,
+}
+
+impl BlockTextures {
+    /// The name of the texture on the face of the block pointing towards face, or None if it doesn't have one.
+    pub fn for_face(&self, face: VoxelAxis) -> Option<&str> {
+        let own = match face {
+            VoxelAxis::PosiY => &self.top,
+            VoxelAxis::NegaY => &self.bottom,
+            VoxelAxis::PosiX => &self.east,
+            VoxelAxis::NegaX => &self.west,
+            VoxelAxis::PosiZ => &self.south,
+            VoxelAxis::NegaZ => &self.north,
+        };
+        let side = match face {
+            VoxelAxis::PosiY | VoxelAxis::NegaY => &None,
+            _ => &self.side,
+        };
+        own.as_ref().or(side.as_ref()).or(self.all.as_ref()).map(|name| name.as_str())
+    }
+
+    /// Every texture named here.
+    pub fn names(&self) -> Vec<&str> {
+        [&self.all, &self.side, &self.top, &self.bottom, &self.east, &self.west, &self.south, &self.north].iter()
+            .filter_map(|name| name.as_ref().map(|name| name.as_str())).collect()
+    }
+}
+
+/// Corners of a box, in blocks, relative to the lower corner of the block it belongs to.
+pub type CollisionBox = [[f32; 3]; 2];
+
+fn default_true() -> bool { true }
+fn default_hardness() -> f32 { 1.0 }
+fn default_collision_box() -> Option<CollisionBox> { Some([[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]]) }
+fn default_specular_exponent() -> f32 { 16.0 }
+fn default_specular_strength() -> f32 { 0.5 }
+
+/// Everything there is to know about a type of block.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct BlockDef {
+    pub name: String,
+    /// Can it be hit and selected? Defaults to true.
+    #[serde(default = "default_true")]
+    pub solid: bool,
+    /// Does it hide the faces of blocks behind it? Defaults to true.
+    #[serde(default = "default_true")]
+    pub opaque: bool,
+    /// Is it drawn see-through, blended with whatever is behind it, like water or stained glass? Defaults to false.
+    /// Blocks which aren't opaque or translucent (like leaves) are drawn with holes where their textures are clear.
+    #[serde(default)]
+    pub translucent: bool,
+    /// A block with no textures at all isn't drawn.
+    #[serde(default)]
+    pub textures: BlockTextures,
+    /// How long it takes to break. Negative means it can't be broken. Defaults to 1.
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+    /// How much light it gives off, from 0 (none, the default) to 15.
+    #[serde(default)]
+    pub light_emission: u8,
+    /// What entities bump into. Defaults to the whole block; null means nothing does.
+    /// Blocks with models bump into their model's boxes instead, so for them this can only be left out or set to null.
+    #[serde(default = "default_collision_box")]
+    pub collision_box: Option<CollisionBox>,
+    /// Name of the model it's drawn with. None, the default, is a full cube.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Degrees to turn the model by (counterclockwise, looking down from above) in states with these property
+    /// values, by property and then value. Has to be a multiple of 90. If several properties turn it, the turns add up.
+    #[serde(default)]
+    pub model_rotation: BTreeMap<String, BTreeMap<String, f32>>,
+    /// Shininess of the block's material. See Material.
+    #[serde(default = "default_specular_exponent")]
+    pub specular_exponent: f32,
+    #[serde(default = "default_specular_strength")]
+    pub specular_strength: f32,
+    /// Each property the block has, and the values it can take. The first value is the default.
+    #[serde(default)]
+    pub properties: BTreeMap<String, Vec<String>>,
+}
+
+impl BlockDef {
+    /// A definition with every property at its default.
+    pub fn new(name: &str) -> BlockDef {
+        BlockDef {
+            name: name.to_owned(),
+            solid: true,
+            opaque: true,
+            translucent: false,
+            textures: BlockTextures::default(),
+            hardness: default_hardness(),
+            light_emission: 0,
+            collision_box: default_collision_box(),
+            model: None,
+            model_rotation: BTreeMap::new(),
+            specular_exponent: default_specular_exponent(),
+            specular_strength: default_specular_strength(),
+            properties: BTreeMap::new(),
+        }
+    }
+
+    /// The definition of air: empty space, which you can't see, hit or bump into.
+    pub fn air() -> BlockDef {
+        BlockDef {
+            solid: false,
+            opaque: false,
+            hardness: -1.0,
+            collision_box: None,
+            .. BlockDef::new("air")
+        }
+    }
+
+    /// Reads a definition from JSON.
+    pub fn from_json(json: &str) -> Result<BlockDef, Box<dyn Error>> {
+        let value : serde_json::Value = serde_json::from_str(json)?;
+        let has_collision_box = value.get("collision_box").map_or(false, |collision_box| !collision_box.is_null());
+        let def : BlockDef = serde_json::from_value(value)?;
+        if def.name.is_empty() {
+            return Err("Block definitions need a name".into());
+        }
+        if has_collision_box && def.model.is_some() {
+            return Err(format!("Block {} has a model, so it can't have a collision_box too", def.name).into());
+        }
+        if def.light_emission > 15 {
+            return Err(format!("Block {} gives off {} light, but the most is 15", def.name, def.light_emission).into());
+        }
+        let is_bad_name = |name: &str| name.is_empty() || name.contains(|c| c == '[' || c == ']' || c == ',' || c == '=');
+        if is_bad_name(&def.name) {
+            return Err(format!("Bad block name \"{}\"", def.name).into());
+        }
+        let mut state_count : usize = 1;
+        for (property, values) in def.properties.iter() {
+            if is_bad_name(property) || values.is_empty() || values.iter().any(|value| is_bad_name(value)) {
+                return Err(format!("Bad values for property {} of block {}", property, def.name).into());
+            }
+            state_count = state_count.saturating_mul(values.len());
+        }
+        if state_count > MAX_STATES_PER_BLOCK {
+            return Err(format!("Block {} has {} states, but the most is {}", def.name, state_count, MAX_STATES_PER_BLOCK).into());
+        }
+        if !def.model_rotation.is_empty() && def.model.is_none() {
+            return Err(format!("Block {} turns its model, but doesn't have one", def.name).into());
+        }
+        for (property, rotations) in def.model_rotation.iter() {
+            for (value, degrees) in rotations.iter() {
+                if !def.properties.get(property).map_or(false, |values| values.contains(value)) {
+                    return Err(format!("Block {} turns its model when {} is {}, but it can't be", def.name, property, value).into());
+                }
+                if degrees % 90.0 != 0.0 {
+                    return Err(format!("Block {} turns its model by {} degrees, which isn't a multiple of 90", def.name, degrees).into());
+                }
+            }
+        }
+        Ok(def)
+    }
+
+    /// The property values of every state this block can be in, default state first.
+    pub fn states(&self) -> Vec<Vec<(String, String)>> {
+        let mut states = vec![Vec::new()];
+        for (property, values) in self.properties.iter() {
+            states = states.into_iter().flat_map(|state| values.iter().map(move |value| {
+                let mut state = state.clone();
+                state.push((property.clone(), value.clone()));
+                state
+            })).collect();
+        }
+        states
+    }
+
+    /// Should this block be drawn at all?
+    pub fn is_visible(&self) -> bool { !self.textures.names().is_empty() }
+
+    /// Can this block be broken?
+    pub fn is_breakable(&self) -> bool { self.hardness >= 0.0 }
+}
+
+/// A block and a value for each of its properties.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockState {
+    pub block: BlockID,
+    /// (property, value) pairs, sorted by property.
+    pub properties: Vec<(String, String)>,
+}
 
+impl BlockState {
+    /// The value of one of this state's properties.
+    pub fn get(&self, property: &str) -> Option<&str> {
+        self.properties.iter().find(|(name, _)| name == property).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Splits a state name like "door[facing=north,half=top]" into its block's name and its properties.
+/// Returns None if it isn't shaped like a state name.
+fn parse_state_name(name: &str) -> Option<(&str, Vec<(&str, &str)>)> {
+    let (block, properties) = match name.find('[') {
+        Some(start) if name.ends_with(']') => (&name[..start], &name[start + 1..name.len() - 1]),
+        Some(_) => return None,
+        None => return Some((name, Vec::new())),
+    };
+    let mut result = Vec::new();
+    for pair in properties.split(',').filter(|pair| !pair.is_empty()) {
+        let mut parts = pair.splitn(2, '=');
+        match (parts.next(), parts.next()) {
+            (Some(property), Some(value)) => result.push((property, value)),
+            _ => return None,
+        }
+    }
+    Some((block, result))
+}
+
+/// Why a name-to-ID table from somewhere else (like a server) can't be used with ours.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockMappingError {
+    /// Blocks in their table which we don't have.
+    pub unknown: Vec<BlockName>,
+    /// Our blocks which aren't in their table.
+    pub missing: Vec<BlockName>,
+    /// Built-in blocks which their table gives a different ID.
+    pub moved_builtins: Vec<BlockName>,
+    /// Blocks whose IDs are too big, or shared with another block.
+    pub bad_ids: Vec<BlockName>,
+}
+
+impl BlockMappingError {
+    fn is_empty(&self) -> bool {
+        self.unknown.is_empty() && self.missing.is_empty() && self.moved_builtins.is_empty() && self.bad_ids.is_empty()
+    }
+
+    /// Lists the blocks in each non-empty list, using labels for unknown, missing, moved_builtins and bad_ids in that order.
+    fn describe(&self, labels: [&str; 4]) -> String {
+        let lists = [&self.unknown, &self.missing, &self.moved_builtins, &self.bad_ids];
+        let mut parts = Vec::new();
+        for (label, names) in labels.iter().zip(lists.iter()) {
+            if !names.is_empty() {
+                let names : Vec<&str> = names.iter().map(|name| &**name).collect();
+                parts.push(format!("{}: {}.", label, names.join(", ")));
+            }
+        }
+        parts.join(" ")
+    }
+
+    /// Explains the error to a client whose table the server compared with its own, so the client
+    /// can tell which side is missing what. Display describes it from the server's side instead.
+    pub fn describe_to_client(&self) -> String {
+        format!("Your blocks don't match the server's. {}",
+                self.describe(["Blocks the server doesn't have", "Blocks you don't have", "Built-in blocks with different IDs", "Blocks with bad IDs"]))
+    }
+}
+
+impl fmt::Display for BlockMappingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.describe(["Unknown blocks", "Missing blocks", "Built-in blocks with the wrong ID", "Blocks with bad IDs"]))
+    }
+}
+
+impl Error for BlockMappingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
+
+/// Maps block names to IDs, IDs to the blocks' definitions, and block states to their IDs.
 pub struct BlockRegistry {
     id_to_name : Vec<BlockName>,
     name_to_id : HashMap<BlockName,BlockID>,
+    defs : Vec<BlockDef>,
+    /// Indexed by BlockStateID. IDs are compact, going from 0 to the number of states, so this has every state exactly once.
+    states : Vec<BlockState>,
+    state_ids : HashMap<BlockState, BlockStateID>,
+    /// Indexed by BlockID.
+    default_states : Vec<BlockStateID>,
+    /// Block models, by name.
+    models : HashMap<String, BlockModel>,
+    /// The turned models of states whose definitions turn their model. See BlockDef::model_rotation.
+    turned_models : HashMap<BlockStateID, BlockModel>,
 }
 
 impl BlockRegistry {
-    pub fn id_for_name(&self, id : &BlockID) -> BlockName{
-        self.id_to_name.get(*id as usize).unwrap().clone()
+    /// A registry with just the built-in blocks in it. Until definitions are loaded for them,
+    /// every block but air has default properties.
+    pub fn new() -> BlockRegistry {
+        let mut registry = BlockRegistry {
+            id_to_name : Vec::new(),
+            name_to_id : HashMap::new(),
+            defs : Vec::new(),
+            states : Vec::new(),
+            state_ids : HashMap::new(),
+            default_states : Vec::new(),
+            models : HashMap::new(),
+            turned_models : HashMap::new(),
+        };
+        registry.define(BlockDef::air());
+        for name in BUILTIN_BLOCKS.iter().skip(1) {
+            registry.define(BlockDef::new(name));
+        }
+        registry
+    }
+
+    pub fn name_for_id(&self, id : BlockID) -> Option<BlockName> { self.id_to_name.get(id as usize).cloned() }
+    pub fn id_for_name(&self, name : &str) -> Option<BlockID> { self.name_to_id.get(&BlockName::from(name)).cloned() }
+    /// The name (see state_name()) and ID of every block state.
+    pub fn all_mappings(&self) -> HashMap<BlockName, BlockStateID> {
+        self.state_ids.values().map(|id| (BlockName::from(self.state_name(*id).unwrap()), *id)).collect()
     }
-    pub fn name_for_id(&self, name : &BlockName) -> BlockID{ self.name_to_id.get(name).unwrap().clone() }
-    pub fn all_mappings(&self) -> HashMap<BlockName, BlockID> { self.name_to_id.clone()}
-    pub fn register_block(&mut self, name: &BlockName) -> BlockID { 
+    /// Adds a block with default properties. Panics if there's already a block with that name.
+    pub fn register_block(&mut self, name: &BlockName) -> BlockID {
         {
             assert!(self.name_to_id.contains_key(name) == false);
         }
-        let new_id = self.id_to_name.len() as BlockID;
-        self.id_to_name.push(name.clone());
-        self.name_to_id.insert(name.clone(), new_id.clone());
-        return new_id;
+        self.define(BlockDef::new(name))
+    }
+
+    /// Adds a block, or replaces the definition of the block with the same name. Returns its ID.
+    /// Any of its states which are new get IDs, and its default state keeps the ID it had. The IDs of
+    /// states it no longer has are given up, and every state after them moves down to fill the gap.
+    pub fn define(&mut self, def: BlockDef) -> BlockID {
+        let name = BlockName::from(def.name.as_str());
+        let id = match self.name_to_id.get(&name) {
+            Some(id) => {
+                self.defs[*id as usize] = def;
+                *id
+            },
+            None => {
+                let new_id = self.id_to_name.len() as BlockID;
+                self.id_to_name.push(name.clone());
+                self.name_to_id.insert(name, new_id);
+                self.defs.push(def);
+                new_id
+            },
+        };
+        self.update_states(id);
+        self.update_turned_models();
+        id
+    }
+
+    /// Makes the states we have for a block match its definition.
+    fn update_states(&mut self, block: BlockID) {
+        let states : Vec<BlockState> = self.defs[block as usize].states().into_iter()
+            .map(|properties| BlockState { block: block, properties: properties }).collect();
+        let old_states : Vec<BlockState> = self.state_ids.keys().filter(|state| state.block == block).cloned().collect();
+        for state in old_states {
+            if !states.contains(&state) {
+                self.state_ids.remove(&state);
+            }
+        }
+        // The default state always keeps its ID, whatever its properties are now.
+        match self.default_states.get(block as usize).cloned() {
+            Some(default_id) => {
+                let old_default = self.states[default_id as usize].clone();
+                if self.state_ids.get(&old_default) == Some(&default_id) {
+                    self.state_ids.remove(&old_default);
+                }
+                self.states[default_id as usize] = states[0].clone();
+                self.state_ids.insert(states[0].clone(), default_id);
+            },
+            None => {
+                let default_id = self.add_state(states[0].clone());
+                self.default_states.push(default_id);
+            },
+        }
+        for state in states.into_iter().skip(1) {
+            if !self.state_ids.contains_key(&state) {
+                self.add_state(state);
+            }
+        }
+        if self.state_ids.len() < self.states.len() {
+            self.compact_states();
+        }
+    }
+
+    /// Drops states which aren't in state_ids any more, moving every state after them down, so that state IDs
+    /// stay compact. Registries which define the same blocks in the same order still end up with the same IDs.
+    fn compact_states(&mut self) {
+        let mut new_ids = vec![None; self.states.len()];
+        let mut states = Vec::with_capacity(self.state_ids.len());
+        for (old_id, state) in self.states.iter().enumerate() {
+            if self.state_ids.get(state) == Some(&(old_id as BlockStateID)) {
+                new_ids[old_id] = Some(states.len() as BlockStateID);
+                states.push(state.clone());
+            }
+        }
+        self.state_ids = states.iter().enumerate().map(|(id, state)| (state.clone(), id as BlockStateID)).collect();
+        self.states = states;
+        for default in self.default_states.iter_mut() {
+            *default = new_ids[*default as usize].unwrap();
+        }
+    }
+
+    /// Turns the models of every state that needs it. Has to be redone whenever states, definitions or models change.
+    fn update_turned_models(&mut self) {
+        let mut turned_models = HashMap::new();
+        for (state, id) in self.state_ids.iter() {
+            let def = &self.defs[state.block as usize];
+            let model = match def.model.as_ref().and_then(|name| self.models.get(name)) {
+                Some(model) => model,
+                None => continue,
+            };
+            let degrees : f32 = state.properties.iter()
+                .filter_map(|(property, value)| def.model_rotation.get(property).and_then(|rotations| rotations.get(value))).sum();
+            if degrees % 360.0 != 0.0 {
+                turned_models.insert(*id, model.turned(degrees));
+            }
+        }
+        self.turned_models = turned_models;
+    }
+
+    fn add_state(&mut self, state: BlockState) -> BlockStateID {
+        let id = self.states.len() as BlockStateID;
+        self.states.push(state.clone());
+        self.state_ids.insert(state, id);
+        id
+    }
+
+    /// Loads every block definition (any file ending in .json) in a directory. Files are read in order
+    /// of their names, so new blocks get the same IDs every time. Returns how many definitions were loaded.
+    pub fn load_definitions<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        for path in paths.iter() {
+            let def = BlockDef::from_json(&fs::read_to_string(path)?)
+                .map_err(|err| format!("Bad block definition {}: {}", path.display(), err))?;
+            if let Some(ref model) = def.model {
+                if !self.models.contains_key(model) {
+                    return Err(format!("Block {} uses model {}, which hasn't been loaded", def.name, model).into());
+                }
+            }
+            self.define(def);
+        }
+        Ok(paths.len())
+    }
+
+    /// Loads every block model (any file ending in .json) in a directory, naming each one after its file.
+    /// Models need to be loaded before the blocks which use them. Returns how many models were loaded.
+    pub fn load_models<P: AsRef<Path>>(&mut self, dir: P) -> Result<usize, Box<dyn Error>> {
+        let mut count = 0;
+        for entry in fs::read_dir(dir.as_ref())? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |extension| extension == "json") {
+                let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+                let model = BlockModel::from_json(&fs::read_to_string(&path)?)
+                    .map_err(|err| format!("Bad block model {}: {}", path.display(), err))?;
+                self.add_model(&name, model);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Adds a block model, or replaces the one with the same name.
+    pub fn add_model(&mut self, name: &str, model: BlockModel) {
+        self.models.insert(name.to_owned(), model);
+        self.update_turned_models();
+    }
+
+    /// The definition of a block, or None if no block has that ID.
+    pub fn get(&self, id: BlockID) -> Option<&BlockDef> { self.defs.get(id as usize) }
+
+    /// A block's default state, which is the state it gets placed in unless something says otherwise.
+    /// Unknown blocks get air's.
+    pub fn default_state(&self, block: BlockID) -> BlockStateID { self.default_states.get(block as usize).cloned().unwrap_or(AIR) }
+    /// The block and property values a state ID stands for, or None if it isn't one.
+    pub fn state(&self, id: BlockStateID) -> Option<&BlockState> { self.states.get(id as usize) }
+    /// The ID of a state, or None if its block doesn't have that state.
+    pub fn state_id(&self, state: &BlockState) -> Option<BlockStateID> { self.state_ids.get(state).cloned() }
+    /// Which block a state is a state of. Unknown states are air.
+    pub fn block_of(&self, id: BlockStateID) -> BlockID { self.state(id).map_or(AIR, |state| state.block) }
+    /// The definition of the block a state is a state of.
+    pub fn def_for_state(&self, id: BlockStateID) -> Option<&BlockDef> { self.get(self.block_of(id)) }
+
+    /// The same state, but with one of its properties set to something else. None if that isn't a state its block has.
+    pub fn with_property(&self, id: BlockStateID, property: &str, value: &str) -> Option<BlockStateID> {
+        let mut state = self.state(id)?.clone();
+        match state.properties.iter_mut().find(|(name, _)| name == property) {
+            Some(pair) => pair.1 = value.to_owned(),
+            None => return None,
+        }
+        self.state_id(&state)
+    }
+
+    /// A state's name: its block's name, and then its properties in brackets if it has any,
+    /// e.g. "stone" or "door[facing=north,half=top]".
+    pub fn state_name(&self, id: BlockStateID) -> Option<String> {
+        let state = self.state(id)?;
+        let block = self.name_for_id(state.block)?;
+        if state.properties.is_empty() {
+            return Some(block.to_string());
+        }
+        let properties : Vec<String> = state.properties.iter().map(|(property, value)| format!("{}={}", property, value)).collect();
+        Some(format!("{}[{}]", block, properties.join(",")))
+    }
+
+    /// Looks up a state by its name. Properties which are left out get their default value, so
+    /// a block's name on its own is its default state.
+    pub fn state_for_name(&self, name: &str) -> Option<BlockStateID> {
+        let (block, properties) = parse_state_name(name)?;
+        let mut state = self.state(self.default_state(self.id_for_name(block)?))?.clone();
+        for (property, value) in properties {
+            match state.properties.iter_mut().find(|(name, _)| name == property) {
+                Some(pair) => pair.1 = value.to_owned(),
+                None => return None,
+            }
+        }
+        self.state_id(&state)
+    }
+
+    /// The model a state is drawn with, turned the way the state says, or None if it's a full cube (or its model was never loaded).
+    pub fn model(&self, id: BlockStateID) -> Option<&BlockModel> {
+        match self.turned_models.get(&id) {
+            Some(model) => Some(model),
+            None => self.def_for_state(id)?.model.as_ref().and_then(|name| self.models.get(name)),
+        }
+    }
+
+    /// Unknown states are treated as air by the helpers below.
+    pub fn is_solid(&self, id: BlockStateID) -> bool { self.def_for_state(id).map_or(false, |def| def.solid) }
+    /// Blocks with models never count as opaque, since they don't fill their whole voxel.
+    pub fn is_opaque(&self, id: BlockStateID) -> bool { self.def_for_state(id).map_or(false, |def| def.opaque) && self.model(id).is_none() }
+    pub fn is_visible(&self, id: BlockStateID) -> bool {
+        self.def_for_state(id).map_or(false, |def| def.is_visible()) || self.model(id).map_or(false, |model| !model.texture_names().is_empty())
+    }
+    pub fn is_translucent(&self, id: BlockStateID) -> bool { self.def_for_state(id).map_or(false, |def| def.translucent) }
+
+    /// The boxes entities bump into, relative to the block's lower corner. Blocks with models
+    /// bump into the model's boxes, turned the way the state says, unless their collision_box is null.
+    pub fn collision_boxes(&self, id: BlockStateID) -> Vec<CollisionBox> {
+        match (self.def_for_state(id).and_then(|def| def.collision_box), self.model(id)) {
+            (None, _) => Vec::new(),
+            (Some(_), Some(model)) => model.bounds(),
+            (Some(collision_box), None) => vec![collision_box],
+        }
+    }
+
+    /// Does a ray starting at origin and going towards direction hit the block in state id at pos? The ray
+    /// should go through pos (like the positions a VoxelRaycast visits), so full cubes are hit if they're solid.
+    /// Blocks with models are only hit where the ray goes through one of the model's boxes.
+    pub fn is_hit_by_ray(&self, id: BlockStateID, pos: VoxelPos<i32>, origin: Point3<f32>, direction: Vector3<f32>) -> bool {
+        if !self.is_solid(id) {
+            return false;
+        }
+        match self.model(id) {
+            Some(model) => {
+                let origin = Point3::new(origin.x - pos.x as f32, origin.y - pos.y as f32, origin.z - pos.z as f32);
+                model.bounds().iter().any(|bounds| ray_hits_box(bounds, origin, direction).is_some())
+            },
+            None => true,
+        }
+    }
+
+    /// Every texture used by any block, sorted and without repeats.
+    pub fn texture_names(&self) -> Vec<String> {
+        let mut names : Vec<String> = self.defs.iter().flat_map(|def| def.textures.names()).map(|name| name.to_owned()).collect();
+        // Models can use textures their blocks don't.
+        for model in self.models.values() {
+            names.extend(model.texture_names().into_iter().map(|name| name.to_owned()));
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Checks whether we could switch to another registry's state IDs (see all_mappings()) with remap().
+    /// That only works if both have exactly the same block states, and agree on the IDs of the built-in blocks' default states.
+    pub fn compare_mappings(&self, theirs: &HashMap<BlockName, BlockStateID>) -> Result<(), BlockMappingError> {
+        let ours = self.all_mappings();
+        let builtin_defaults : HashMap<BlockName, BlockStateID> = (0..BUILTIN_BLOCKS.len() as BlockID)
+            .map(|block| (BlockName::from(self.state_name(self.default_state(block)).unwrap()), block)).collect();
+        let mut error = BlockMappingError::default();
+        let mut seen_ids = vec![false; ours.len()];
+        for (name, id) in theirs.iter() {
+            if !ours.contains_key(name) {
+                error.unknown.push(name.clone());
+                continue;
+            }
+            match builtin_defaults.get(name) {
+                Some(builtin_id) if builtin_id != id => error.moved_builtins.push(name.clone()),
+                _ => {},
+            }
+            match seen_ids.get_mut(*id as usize) {
+                Some(seen) if !*seen => *seen = true,
+                _ => error.bad_ids.push(name.clone()),
+            }
+        }
+        error.missing = ours.keys().filter(|name| !theirs.contains_key(*name)).cloned().collect();
+        for names in [&mut error.unknown, &mut error.missing, &mut error.moved_builtins, &mut error.bad_ids].iter_mut() {
+            names.sort_by(|a, b| (**a).cmp(&**b));
+        }
+        if error.is_empty() { Ok(()) } else { Err(error) }
+    }
+
+    /// Gives every block state the ID another registry (usually the server's) has for it. Fails, changing nothing,
+    /// if the mappings don't line up with our states. See compare_mappings().
+    /// Block IDs stay the same, since only state IDs end up in chunks.
+    pub fn remap(&mut self, theirs: &HashMap<BlockName, BlockStateID>) -> Result<(), BlockMappingError> {
+        self.compare_mappings(theirs)?;
+        let mut states : Vec<Option<BlockState>> = vec![None; theirs.len()];
+        for (state, old_id) in self.state_ids.iter() {
+            let new_id = theirs[&BlockName::from(self.state_name(*old_id).unwrap())];
+            states[new_id as usize] = Some(state.clone());
+        }
+        self.states = states.into_iter().map(|state| state.unwrap()).collect();
+        self.state_ids = self.states.iter().enumerate().map(|(id, state)| (state.clone(), id as BlockStateID)).collect();
+        for block in 0..self.defs.len() {
+            let default = self.defs[block].states().remove(0);
+            self.default_states[block] = self.state_ids[&BlockState { block: block as BlockID, properties: default }];
+        }
+        self.update_turned_models();
+        Ok(())
     }
 }
 
 lazy_static! {
-    pub static ref MASTER_BLOCK_REGISTRY : Mutex<BlockRegistry> = {
-        Mutex::new(BlockRegistry { 
-            id_to_name : Vec::new(),
-            name_to_id : HashMap::new(),
-        })
+    pub static ref MASTER_BLOCK_REGISTRY : RwLock<BlockRegistry> = RwLock::new(BlockRegistry::new());
+}
+
+#[test]
+fn test_block_def_json() {
+    let def = BlockDef::from_json(r#"{ "name": "grass", "textures": { "all": "dirt", "side": "grass_side", "top": "grass" },
+                                       "hardness": 0.6, "light_emission": 3, "collision_box": [[0, 0, 0], [1, 0.5, 1]] }"#).unwrap();
+    assert_eq!(def.name, "grass");
+    assert!(def.solid && def.opaque);
+    assert_eq!(def.hardness, 0.6);
+    assert_eq!(def.light_emission, 3);
+    assert_eq!(def.collision_box, Some([[0.0, 0.0, 0.0], [1.0, 0.5, 1.0]]));
+    assert_eq!(def.textures.for_face(VoxelAxis::PosiY), Some("grass"));
+    assert_eq!(def.textures.for_face(VoxelAxis::NegaY), Some("dirt"));
+    assert_eq!(def.textures.for_face(VoxelAxis::NegaZ), Some("grass_side"));
+    assert!(def.is_visible());
+
+    // Defaults.
+    let def = BlockDef::from_json(r#"{ "name": "glass", "opaque": false, "collision_box": null }"#).unwrap();
+    assert_eq!(def, BlockDef { opaque: false, collision_box: None, .. BlockDef::new("glass") });
+    assert!(!def.is_visible());
+    assert_eq!(def.textures.for_face(VoxelAxis::PosiX), None);
+    assert!(!def.translucent);
+    let def = BlockDef::from_json(r#"{ "name": "water", "opaque": false, "translucent": true }"#).unwrap();
+    assert!(def.translucent);
+
+    assert!(BlockDef::from_json(r#"{ "textures": { "all": "stone" } }"#).is_err());
+    assert!(BlockDef::from_json(r#"{ "name": "sun", "light_emission": 16 }"#).is_err());
+}
+
+#[test]
+fn test_block_registry() {
+    let mut registry = BlockRegistry::new();
+    assert_eq!(registry.id_for_name("air"), Some(AIR));
+    assert_eq!(registry.id_for_name("coal_ore"), Some(COAL_ORE));
+    assert_eq!(registry.name_for_id(GRASS), Some(BlockName::from("grass")));
+    assert!(!registry.is_solid(AIR) && !registry.is_opaque(AIR) && !registry.is_visible(AIR));
+    assert!(registry.is_solid(STONE));
+
+    // Redefining a block keeps its ID.
+    let mut stone = BlockDef::new("stone");
+    stone.textures.all = Some("stone".to_owned());
+    assert_eq!(registry.define(stone), STONE);
+    assert!(registry.is_visible(STONE));
+    let glass = registry.define(BlockDef { opaque: false, .. BlockDef::new("glass") });
+    assert_eq!(glass, MISSING + 1);
+    assert!(registry.is_solid(glass) && !registry.is_opaque(glass));
+    assert_eq!(registry.register_block(&BlockName::from("sand")), glass + 1);
+
+    // Unknown blocks.
+    assert_eq!(registry.get(1000), None);
+    assert!(!registry.is_solid(1000));
+    assert_eq!(registry.id_for_name("cheese"), None);
+
+    assert_eq!(registry.texture_names(), vec!["stone".to_owned()]);
+}
+
+#[test]
+fn test_block_remap() {
+    let mut ours = BlockRegistry::new();
+    let glass = ours.define(BlockDef { opaque: false, .. BlockDef::new("glass") });
+    let sand = ours.define(BlockDef::new("sand"));
+
+    // Same blocks, registered in a different order.
+    let mut theirs = BlockRegistry::new();
+    theirs.define(BlockDef::new("sand"));
+    theirs.define(BlockDef::new("glass"));
+    assert_eq!(ours.compare_mappings(&theirs.all_mappings()), Ok(()));
+    ours.remap(&theirs.all_mappings()).unwrap();
+    assert_eq!(ours.all_mappings(), theirs.all_mappings());
+    assert_eq!(ours.state_for_name("glass"), Some(sand));
+    assert_eq!(ours.state_name(glass), Some("sand".to_owned()));
+    assert!(!ours.is_opaque(sand) && ours.is_opaque(glass));
+    assert_eq!(ours.def_for_state(sand).unwrap().name, "glass");
+    assert_eq!(ours.state_for_name("stone"), Some(STONE));
+    assert_eq!(ours.default_state(ours.id_for_name("glass").unwrap()), sand);
+
+    // Different blocks.
+    let mut other = BlockRegistry::new();
+    other.define(BlockDef::new("glass"));
+    other.define(BlockDef::new("marble"));
+    let error = ours.remap(&other.all_mappings()).unwrap_err();
+    assert_eq!(error.unknown, vec![BlockName::from("marble")]);
+    assert_eq!(error.missing, vec![BlockName::from("sand")]);
+    assert_eq!(error.to_string(), "Unknown blocks: marble. Missing blocks: sand.");
+    // Told to the other side, who has the blocks we don't and is missing the ones we have.
+    assert_eq!(error.describe_to_client(), "Your blocks don't match the server's. Blocks the server doesn't have: marble. Blocks you don't have: sand.");
+    // Nothing changed.
+    assert_eq!(ours.all_mappings(), theirs.all_mappings());
+
+    // Built-in blocks can't move, and IDs have to make sense.
+    let mut mappings = ours.all_mappings();
+    mappings.insert(BlockName::from("stone"), DIRT);
+    mappings.insert(BlockName::from("dirt"), STONE);
+    mappings.insert(BlockName::from("sand"), 100);
+    let error = ours.compare_mappings(&mappings).unwrap_err();
+    assert_eq!(error.moved_builtins, vec![BlockName::from("dirt"), BlockName::from("stone")]);
+    assert_eq!(error.bad_ids, vec![BlockName::from("sand")]);
+    assert!(error.unknown.is_empty() && error.missing.is_empty());
+}
+
+#[test]
+fn test_builtin_block_definitions() {
+    // Every definition we ship loads, and all of the built-in blocks but air have one.
+    let mut registry = BlockRegistry::new();
+    registry.load_models(Path::new(env!("CARGO_MANIFEST_DIR")).join(::world::blockmodel::BLOCK_MODELS_DIR)).unwrap();
+    let count = registry.load_definitions(Path::new(env!("CARGO_MANIFEST_DIR")).join(BLOCK_DEFINITIONS_DIR)).unwrap();
+    assert!(count >= BUILTIN_BLOCKS.len() - 1);
+    for id in STONE..(BUILTIN_BLOCKS.len() as BlockID) {
+        assert!(registry.is_visible(id), "{:?} has no textures", registry.name_for_id(id));
+    }
+    for name in registry.texture_names() {
+        assert!(Path::new(env!("CARGO_MANIFEST_DIR")).join("textures").join(name.clone() + ".png").exists(), "No texture {}", name);
+    }
+}
+
+#[test]
+fn test_block_states() {
+    let mut registry = BlockRegistry::new();
+    let door_def = BlockDef::from_json(r#"{ "name": "door", "opaque": false,
+                                            "properties": { "half": ["bottom", "top"], "facing": ["north", "south", "east", "west"] } }"#).unwrap();
+    assert_eq!(door_def.states().len(), 8);
+    let door = registry.define(door_def);
+    let sand = registry.define(BlockDef::new("sand"));
+
+    // Every combination gets its own state, and the default one is the first value of each property.
+    let closed = registry.default_state(door);
+    assert_eq!(registry.state_name(closed), Some("door[facing=north,half=bottom]".to_owned()));
+    assert_eq!(registry.all_mappings().len(), BUILTIN_BLOCKS.len() + 8 + 1);
+    let top_east = registry.state_for_name("door[half=top,facing=east]").unwrap();
+    assert_eq!(registry.block_of(top_east), door);
+    assert_eq!(registry.state(top_east).unwrap().get("facing"), Some("east"));
+    assert_eq!(registry.with_property(closed, "half", "top").and_then(|id| registry.with_property(id, "facing", "east")), Some(top_east));
+    assert_eq!(registry.state_for_name("door[half=top]"), registry.with_property(closed, "half", "top"));
+    assert_eq!(registry.state_for_name("door"), Some(closed));
+    assert!(registry.is_solid(top_east) && !registry.is_opaque(top_east));
+    assert_eq!(registry.state_for_name("sand"), Some(registry.default_state(sand)));
+    assert_eq!(registry.state_name(STONE), Some("stone".to_owned()));
+
+    // Things which aren't states.
+    assert_eq!(registry.with_property(closed, "facing", "up"), None);
+    assert_eq!(registry.with_property(closed, "color", "red"), None);
+    assert_eq!(registry.state_for_name("door[facing=up]"), None);
+    assert_eq!(registry.state_for_name("door[hinge=left]"), None);
+    assert_eq!(registry.state_for_name("door[half=top"), None);
+    assert_eq!(registry.state_for_name("cheese"), None);
+    assert_eq!(registry.block_of(100000), AIR);
+
+    // Redefining a block keeps the ID of its default state.
+    let mut door_def = registry.get(door).unwrap().clone();
+    door_def.properties.insert("half".to_owned(), vec!["top".to_owned(), "bottom".to_owned()]);
+    door_def.properties.remove("facing");
+    assert_eq!(registry.define(door_def), door);
+    assert_eq!(registry.state_name(closed), Some("door[half=top]".to_owned()));
+    assert_eq!(registry.state_for_name("door[half=top]"), Some(closed));
+    assert_eq!(registry.state_for_name("door[facing=north]"), None);
+    assert_eq!(registry.all_mappings().len(), BUILTIN_BLOCKS.len() + 2 + 1);
+    // The states it lost gave up their IDs, so every ID is in use.
+    let mut ids : Vec<BlockStateID> = registry.all_mappings().values().cloned().collect();
+    ids.sort();
+    assert_eq!(ids, (0..ids.len() as BlockStateID).collect::<Vec<BlockStateID>>());
+    assert_eq!(registry.state_for_name("sand"), Some(registry.default_state(sand)));
+
+    // Remapping moves states around without touching block IDs.
+    let mut theirs = BlockRegistry::new();
+    theirs.define(BlockDef::new("sand"));
+    theirs.define(registry.get(door).unwrap().clone());
+    registry.remap(&theirs.all_mappings()).unwrap();
+    assert_eq!(registry.all_mappings(), theirs.all_mappings());
+    assert_eq!(registry.id_for_name("door"), Some(door));
+    assert_eq!(registry.default_state(door), theirs.default_state(theirs.id_for_name("door").unwrap()));
+
+    // Two registries which redefined a block with fewer properties still match, both ways around.
+    let redefined = || {
+        let mut registry = BlockRegistry::new();
+        registry.define(BlockDef::from_json(r#"{ "name": "door", "properties": { "half": ["bottom", "top"], "facing": ["north", "south"] } }"#).unwrap());
+        registry.define(BlockDef::from_json(r#"{ "name": "door", "properties": { "half": ["bottom", "top"] } }"#).unwrap());
+        registry.define(BlockDef::new("sand"));
+        registry
     };
+    let (mut ours, theirs) = (redefined(), redefined());
+    assert_eq!(ours.compare_mappings(&theirs.all_mappings()), Ok(()));
+    ours.remap(&theirs.all_mappings()).unwrap();
+    assert_eq!(ours.all_mappings(), theirs.all_mappings());
+    // ...and match one which never had the states that were dropped, which defined its blocks in another order.
+    let mut fresh = BlockRegistry::new();
+    fresh.define(BlockDef::new("sand"));
+    fresh.define(BlockDef::from_json(r#"{ "name": "door", "properties": { "half": ["bottom", "top"] } }"#).unwrap());
+    assert_eq!(fresh.compare_mappings(&ours.all_mappings()), Ok(()));
+    fresh.remap(&ours.all_mappings()).unwrap();
+    assert_eq!(fresh.all_mappings(), ours.all_mappings());
+    assert_eq!(fresh.state_for_name("door[half=top]"), ours.state_for_name("door[half=top]"));
+    assert_eq!(ours.compare_mappings(&fresh.all_mappings()), Ok(()));
+
+    assert!(BlockDef::from_json(r#"{ "name": "door", "properties": { "open": [] } }"#).is_err());
+    assert!(BlockDef::from_json(r#"{ "name": "door", "properties": { "open": ["yes=no"] } }"#).is_err());
+    assert!(BlockDef::from_json(r#"{ "name": "door[open=true]" }"#).is_err());
+    // Blocks with models get their shape from the model.
+    assert!(BlockDef::from_json(r#"{ "name": "slab", "model": "slab", "collision_box": [[0, 0, 0], [1, 0.5, 1]] }"#).is_err());
+    assert!(BlockDef::from_json(r#"{ "name": "plant", "model": "cross", "collision_box": null }"#).is_ok());
+    assert!(BlockDef::from_json(r#"{ "name": "huge", "properties": { "a": ["0","1","2","3","4","5","6","7"], "b": ["0","1","2","3","4","5","6","7"],
+                                                                     "c": ["0","1","2","3","4","5","6","7"], "d": ["0","1","2","3","4","5","6","7"], "e": ["0","1"] } }"#).is_err());
+}
+
+#[test]
+fn test_block_models() {
+    use world::blockmodel::ModelBox;
+    let mut registry = BlockRegistry::new();
+    registry.add_model("slab", BlockModel { boxes: vec![ModelBox::new([0.0, 0.0, 0.0], [1.0, 0.5, 1.0])] });
+    let slab = registry.define(BlockDef { model: Some("slab".to_owned()), .. BlockDef::new("slab") });
+    let plant = registry.define(BlockDef { model: Some("slab".to_owned()), collision_box: None, .. BlockDef::new("plant") });
+    // A model nobody loaded.
+    let odd = registry.define(BlockDef { model: Some("odd".to_owned()), .. BlockDef::new("odd") });
+
+    assert!(registry.model(slab).is_some() && registry.model(STONE).is_none() && registry.model(odd).is_none());
+    // Slabs don't fill their block, so they can't hide anything.
+    assert!(!registry.is_opaque(slab) && registry.is_opaque(odd));
+
+    let half = vec![[[0.0, 0.0, 0.0], [1.0, 0.5, 1.0]]];
+    let whole = vec![[[0.0, 0.0, 0.0], [1.0, 1.0, 1.0]]];
+    assert_eq!(registry.collision_boxes(slab), half);
+    assert!(registry.collision_boxes(plant).is_empty());
+    assert_eq!(registry.collision_boxes(STONE), whole);
+    assert!(registry.collision_boxes(AIR).is_empty());
+
+    // Rays over the top half of a slab miss it.
+    let pos = VoxelPos { x: 3, y: 1, z: -2 };
+    let forward = Vector3::new(1.0, 0.0, 0.0);
+    assert!(registry.is_hit_by_ray(slab, pos, Point3::new(0.0, 1.25, -1.5), forward));
+    assert!(!registry.is_hit_by_ray(slab, pos, Point3::new(0.0, 1.75, -1.5), forward));
+    assert!(registry.is_hit_by_ray(plant, pos, Point3::new(0.0, 1.25, -1.5), forward));
+    assert!(!registry.is_hit_by_ray(plant, pos, Point3::new(0.0, 1.75, -1.5), forward));
+    assert!(registry.is_hit_by_ray(STONE, pos, Point3::new(0.0, 1.75, -1.5), forward));
+    assert!(!registry.is_hit_by_ray(AIR, pos, Point3::new(0.0, 1.25, -1.5), forward));
+
+    // A slab standing on its side, which turns to face whichever way its state says.
+    registry.add_model("wall", BlockModel { boxes: vec![ModelBox::new([0.0, 0.0, 0.5], [1.0, 1.0, 1.0])] });
+    let wall = registry.define(BlockDef::from_json(r#"{ "name": "wall", "model": "wall", "properties": { "facing": ["south", "east", "north"] },
+                                                        "model_rotation": { "facing": { "east": 90, "north": 180 } } }"#).unwrap());
+    let south = registry.default_state(wall);
+    let east = registry.with_property(south, "facing", "east").unwrap();
+    let north = registry.with_property(south, "facing", "north").unwrap();
+    assert_eq!(registry.model(south).unwrap().bounds(), vec![[[0.0, 0.0, 0.5], [1.0, 1.0, 1.0]]]);
+    assert_eq!(registry.model(east).unwrap().bounds(), vec![[[0.5, 0.0, 0.0], [1.0, 1.0, 1.0]]]);
+    assert_eq!(registry.model(north).unwrap().bounds(), vec![[[0.0, 0.0, 0.0], [1.0, 1.0, 0.5]]]);
+    assert_eq!(registry.collision_boxes(east), vec![[[0.5, 0.0, 0.0], [1.0, 1.0, 1.0]]]);
+    // Replacing the model replaces the turned ones too.
+    registry.add_model("wall", BlockModel { boxes: vec![ModelBox::new([0.0, 0.0, 0.75], [1.0, 1.0, 1.0])] });
+    assert_eq!(registry.model(east).unwrap().bounds(), vec![[[0.75, 0.0, 0.0], [1.0, 1.0, 1.0]]]);
+
+    assert!(BlockDef::from_json(r#"{ "name": "wall", "properties": { "facing": ["south", "east"] },
+                                     "model_rotation": { "facing": { "east": 90 } } }"#).is_err());
+    assert!(BlockDef::from_json(r#"{ "name": "wall", "model": "wall", "properties": { "facing": ["south", "east"] },
+                                     "model_rotation": { "facing": { "up": 90 } } }"#).is_err());
+    assert!(BlockDef::from_json(r#"{ "name": "wall", "model": "wall", "properties": { "facing": ["south", "east"] },
+                                     "model_rotation": { "facing": { "east": 45 } } }"#).is_err());
 }
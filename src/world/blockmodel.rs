@@ -0,0 +1,338 @@
+//! Shapes for blocks which aren't full cubes, like slabs, stairs, fences and plants.
+//!
+//! A model is made of boxes, and is loaded from a JSON file in BLOCK_MODELS_DIR, named after the model:
+//!
+//! ```text
+//! {
+//!     "boxes": [
+//!         {
+//!             "from": [0, 0, 0],
+//!             "to": [1, 0.5, 1],
+//!             "faces": { "top": {}, "bottom": {}, "east": {}, "west": {}, "south": {}, "north": { "texture": "log", "uv": [0, 0, 1, 0.5] } }
+//!         }
+//!     ]
+//! }
+//! ```
+//!
+//! Positions are in blocks, relative to the block's lower corner. A box only has the faces it lists, or all six
+//! if it doesn't list any. A face uses the block's own texture for that side unless it names one, and its UVs
+//! ([left, top, right, bottom], from 0 to 1 across the texture) default to the part of the texture that lines up
+//! with where the face is in the block, so the sides of a slab show the bottom half of the texture.
+//! A box can also be turned around the vertical line through its middle, by "rotation" degrees, for things like crossed plants.
+//!
+//! Blocks bump into and get selected by the same boxes they're drawn with. See BlockRegistry::collision_boxes()
+//! and BlockRegistry::is_hit_by_ray().
+
+extern crate serde_json;
+
+use std::error::Error;
+
+use cgmath::{Point3, Vector3};
+use serde::Deserialize;
+
+use voxel::voxelmath::{VoxelAxis, VoxelAxisUnsigned, VoxelAxisSign};
+use world::block::CollisionBox;
+
+/// Where block models are loaded from, one JSON file per model.
+pub const BLOCK_MODELS_DIR : &str = "models";
+
+/// One side of a box in a model.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ModelFace {
+    /// The texture to draw this face with, instead of the block's texture for this side.
+    pub texture: Option<String>,
+    /// Which part of the texture to draw: [left, top, right, bottom], from 0 to 1.
+    pub uv: Option<[f32; 4]>,
+}
+
+/// The faces a box in a model has. Named like BlockTextures' are.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ModelFaces {
+    /// +Y
+    pub top: Option<ModelFace>,
+    /// -Y
+    pub bottom: Option<ModelFace>,
+    /// +X
+    pub east: Option<ModelFace>,
+    /// -X
+    pub west: Option<ModelFace>,
+    /// +Z
+    pub south: Option<ModelFace>,
+    /// -Z
+    pub north: Option<ModelFace>,
+}
+
+impl ModelFaces {
+    /// Every face, with nothing special about any of them.
+    pub fn all() -> ModelFaces {
+        ModelFaces {
+            top: Some(ModelFace::default()),
+            bottom: Some(ModelFace::default()),
+            east: Some(ModelFace::default()),
+            west: Some(ModelFace::default()),
+            south: Some(ModelFace::default()),
+            north: Some(ModelFace::default()),
+        }
+    }
+
+    /// The face pointing towards face (before the box is rotated), if the box has one.
+    pub fn for_face(&self, face: VoxelAxis) -> Option<&ModelFace> {
+        match face {
+            VoxelAxis::PosiY => self.top.as_ref(),
+            VoxelAxis::NegaY => self.bottom.as_ref(),
+            VoxelAxis::PosiX => self.east.as_ref(),
+            VoxelAxis::NegaX => self.west.as_ref(),
+            VoxelAxis::PosiZ => self.south.as_ref(),
+            VoxelAxis::NegaZ => self.north.as_ref(),
+        }
+    }
+}
+
+/// Which of a point's coordinates lies along axis.
+pub fn axis_index(axis: VoxelAxisUnsigned) -> usize {
+    match axis {
+        VoxelAxisUnsigned::X => 0,
+        VoxelAxisUnsigned::Y => 1,
+        VoxelAxisUnsigned::Z => 2,
+    }
+}
+
+/// A box in a model.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct ModelBox {
+    /// Lower corner.
+    pub from: [f32; 3],
+    /// Upper corner.
+    pub to: [f32; 3],
+    #[serde(default = "ModelFaces::all")]
+    pub faces: ModelFaces,
+    /// Degrees to turn the box around the vertical line through its middle, by the right hand rule (so
+    /// counterclockwise, looking down from above).
+    #[serde(default)]
+    pub rotation: f32,
+}
+
+impl ModelBox {
+    /// A box with all of its faces, which isn't rotated.
+    pub fn new(from: [f32; 3], to: [f32; 3]) -> ModelBox {
+        ModelBox { from: from, to: to, faces: ModelFaces::all(), rotation: 0.0 }
+    }
+
+    /// Turns a point by this box's rotation. Positions turn around the middle of the box, and directions (like normals) just turn.
+    pub fn rotate(&self, point: [f32; 3], is_direction: bool) -> [f32; 3] {
+        if self.rotation == 0.0 {
+            return point;
+        }
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let (center_x, center_z) = if is_direction { (0.0, 0.0) }
+                                   else { ((self.from[0] + self.to[0]) / 2.0, (self.from[2] + self.to[2]) / 2.0) };
+        let (x, z) = (point[0] - center_x, point[2] - center_z);
+        [center_x + (x * cos) + (z * sin), point[1], center_z - (x * sin) + (z * cos)]
+    }
+
+    /// The same box, turned a quarter turn (by the right hand rule) around the vertical line through the middle of the block.
+    fn turned_quarter(&self) -> ModelBox {
+        // (x, z) ends up at (z, 1 - x).
+        if self.rotation != 0.0 {
+            // Boxes which are already rotated keep their shape and faces, and just move and rotate some more.
+            let half = [(self.to[0] - self.from[0]) / 2.0, (self.to[2] - self.from[2]) / 2.0];
+            let center = [(self.from[2] + self.to[2]) / 2.0, 1.0 - ((self.from[0] + self.to[0]) / 2.0)];
+            return ModelBox {
+                from: [center[0] - half[0], self.from[1], center[1] - half[1]],
+                to: [center[0] + half[0], self.to[1], center[1] + half[1]],
+                faces: self.faces.clone(),
+                rotation: (self.rotation + 90.0) % 360.0,
+            };
+        }
+        let faces = &self.faces;
+        ModelBox {
+            from: [self.from[2], self.from[1], 1.0 - self.to[0]],
+            to: [self.to[2], self.to[1], 1.0 - self.from[0]],
+            faces: ModelFaces {
+                top: faces.top.clone(),
+                bottom: faces.bottom.clone(),
+                // Whatever faced south now faces east, and so on around.
+                east: faces.south.clone(),
+                north: faces.east.clone(),
+                west: faces.north.clone(),
+                south: faces.west.clone(),
+            },
+            rotation: 0.0,
+        }
+    }
+
+    /// The smallest unrotated box this box fits in.
+    pub fn bounds(&self) -> CollisionBox {
+        if self.rotation == 0.0 {
+            return [self.from, self.to];
+        }
+        let mut lower = [::std::f32::MAX; 3];
+        let mut upper = [::std::f32::MIN; 3];
+        for &x in [self.from[0], self.to[0]].iter() {
+            for &z in [self.from[2], self.to[2]].iter() {
+                let corner = self.rotate([x, self.from[1], z], false);
+                for i in 0..3 {
+                    lower[i] = lower[i].min(corner[i]);
+                    upper[i] = upper[i].max(corner[i]);
+                }
+            }
+        }
+        lower[1] = self.from[1];
+        upper[1] = self.to[1];
+        [lower, upper]
+    }
+
+    /// Does the face pointing towards face lie flat against that side of the block? If it does, it's hidden
+    /// when the block on that side is opaque.
+    pub fn touches_side(&self, face: VoxelAxis) -> bool {
+        let axis = axis_index(face.into());
+        if self.rotation != 0.0 && axis != 1 {
+            return false;
+        }
+        match face.get_sign() {
+            VoxelAxisSign::POSI => self.to[axis] >= 1.0,
+            VoxelAxisSign::NEGA => self.from[axis] <= 0.0,
+        }
+    }
+}
+
+/// The shape of a block which isn't a full cube. See the module documentation.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct BlockModel {
+    pub boxes: Vec<ModelBox>,
+}
+
+impl BlockModel {
+    /// Reads a model from JSON.
+    pub fn from_json(json: &str) -> Result<BlockModel, Box<dyn Error>> {
+        let model : BlockModel = serde_json::from_str(json)?;
+        if model.boxes.is_empty() {
+            return Err("Block models need at least one box".into());
+        }
+        for model_box in model.boxes.iter() {
+            if (0..3).any(|i| !(model_box.from[i] <= model_box.to[i])) {
+                return Err(format!("Box from {:?} to {:?} is inside out", model_box.from, model_box.to).into());
+            }
+        }
+        Ok(model)
+    }
+
+    /// The same model, turned around the vertical line through the middle of the block by a multiple of 90 degrees
+    /// (counterclockwise, looking down from above). Other angles are rounded to the nearest one.
+    pub fn turned(&self, degrees: f32) -> BlockModel {
+        let quarters = ((((degrees / 90.0).round() as i32) % 4) + 4) % 4;
+        let mut model = self.clone();
+        for _ in 0..quarters {
+            model.boxes = model.boxes.iter().map(|model_box| model_box.turned_quarter()).collect();
+        }
+        model
+    }
+
+    /// Every texture the model's faces name themselves.
+    pub fn texture_names(&self) -> Vec<&str> {
+        self.boxes.iter().flat_map(|model_box| {
+            let faces = &model_box.faces;
+            vec![&faces.top, &faces.bottom, &faces.east, &faces.west, &faces.south, &faces.north].into_iter()
+                .filter_map(|face| face.as_ref().and_then(|face| face.texture.as_ref()).map(|name| name.as_str()))
+        }).collect()
+    }
+
+    /// The bounds of each of the model's boxes, which is the shape of the block for bumping into and selecting.
+    pub fn bounds(&self) -> Vec<CollisionBox> {
+        self.boxes.iter().map(|model_box| model_box.bounds()).collect()
+    }
+}
+
+/// How far along direction a ray starting at origin goes before it hits the box, or None if it never does.
+/// A ray which starts inside the box hits it straight away.
+pub fn ray_hits_box(bounds: &CollisionBox, origin: Point3<f32>, direction: Vector3<f32>) -> Option<f32> {
+    let origin = [origin.x, origin.y, origin.z];
+    let direction = [direction.x, direction.y, direction.z];
+    let mut enter = 0.0f32;
+    let mut exit = ::std::f32::MAX;
+    for i in 0..3 {
+        if direction[i] == 0.0 {
+            // Parallel to this pair of sides, so we're either always between them or never.
+            if origin[i] < bounds[0][i] || origin[i] > bounds[1][i] {
+                return None;
+            }
+            continue;
+        }
+        let a = (bounds[0][i] - origin[i]) / direction[i];
+        let b = (bounds[1][i] - origin[i]) / direction[i];
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+    }
+    if enter <= exit { Some(enter) } else { None }
+}
+
+#[test]
+fn test_block_model_json() {
+    let model = BlockModel::from_json(r#"{ "boxes": [
+        { "from": [0, 0, 0], "to": [1, 0.5, 1] },
+        { "from": [0, 0.5, 0.5], "to": [1, 1, 1], "faces": { "top": {}, "north": { "texture": "log", "uv": [0, 0, 1, 0.5] } } }
+    ] }"#).unwrap();
+    assert_eq!(model.boxes[0], ModelBox::new([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]));
+    let faces = &model.boxes[1].faces;
+    assert_eq!(faces.for_face(VoxelAxis::PosiY), Some(&ModelFace::default()));
+    assert_eq!(faces.for_face(VoxelAxis::NegaZ), Some(&ModelFace { texture: Some("log".to_owned()), uv: Some([0.0, 0.0, 1.0, 0.5]) }));
+    assert_eq!(faces.for_face(VoxelAxis::NegaY), None);
+    assert_eq!(model.texture_names(), vec!["log"]);
+    assert_eq!(model.bounds(), vec![[[0.0, 0.0, 0.0], [1.0, 0.5, 1.0]], [[0.0, 0.5, 0.5], [1.0, 1.0, 1.0]]]);
+
+    // The slab's bottom and sides are against the sides of the block, but its top isn't.
+    assert!(model.boxes[0].touches_side(VoxelAxis::NegaY) && model.boxes[0].touches_side(VoxelAxis::PosiX));
+    assert!(!model.boxes[0].touches_side(VoxelAxis::PosiY));
+    assert!(!model.boxes[1].touches_side(VoxelAxis::NegaZ) && model.boxes[1].touches_side(VoxelAxis::PosiZ));
+
+    assert!(BlockModel::from_json(r#"{ "boxes": [] }"#).is_err());
+    assert!(BlockModel::from_json(r#"{ "boxes": [{ "from": [0, 1, 0], "to": [1, 0, 1] }] }"#).is_err());
+    assert!(BlockModel::from_json(r#"{ "boxes": [{ "from": [0, 0], "to": [1, 1, 1] }] }"#).is_err());
+}
+
+#[test]
+fn test_rotated_box() {
+    // A flat panel across the middle of the block, turned to run corner to corner.
+    let panel = ModelBox { rotation: 90.0, .. ModelBox::new([0.0, 0.0, 0.5], [1.0, 1.0, 0.5]) };
+    let near = |a: [f32; 3], b: [f32; 3]| (0..3).all(|i| (a[i] - b[i]).abs() < 0.0001);
+    assert!(near(panel.rotate([1.0, 0.0, 0.5], false), [0.5, 0.0, 0.0]));
+    assert!(near(panel.rotate([0.0, 0.0, -1.0], true), [-1.0, 0.0, 0.0]));
+    let bounds = panel.bounds();
+    assert!(near(bounds[0], [0.5, 0.0, 0.0]) && near(bounds[1], [0.5, 1.0, 1.0]));
+
+    // Turning a whole model moves its boxes around the middle of the block.
+    let stairs = BlockModel { boxes: vec![ModelBox::new([0.0, 0.0, 0.0], [1.0, 0.5, 1.0]),
+                                          ModelBox { faces: ModelFaces { south: Some(ModelFace::default()), .. ModelFaces::default() },
+                                                     .. ModelBox::new([0.0, 0.5, 0.5], [1.0, 1.0, 1.0]) },
+                                          ModelBox { rotation: 45.0, .. ModelBox::new([0.75, 0.0, 0.0], [1.0, 1.0, 0.25]) }] };
+    assert_eq!(stairs.turned(0.0), stairs);
+    assert_eq!(stairs.turned(360.0), stairs);
+    let east = stairs.turned(90.0);
+    assert_eq!(east.boxes[0].bounds(), [[0.0, 0.0, 0.0], [1.0, 0.5, 1.0]]);
+    assert_eq!(east.boxes[1].bounds(), [[0.5, 0.5, 0.0], [1.0, 1.0, 1.0]]);
+    assert_eq!(east.boxes[1].faces, ModelFaces { east: Some(ModelFace::default()), .. ModelFaces::default() });
+    assert_eq!(east.boxes[2].rotation, 135.0);
+    assert!(near(east.boxes[2].from, [0.0, 0.0, 0.0]) && near(east.boxes[2].to, [0.25, 1.0, 0.25]));
+    assert_eq!(stairs.turned(-90.0), stairs.turned(270.0));
+    assert_eq!(stairs.turned(180.0).boxes[1].bounds(), [[0.0, 0.5, 0.0], [1.0, 1.0, 0.5]]);
+    assert_eq!(stairs.turned(180.0).boxes[1].faces.north, Some(ModelFace::default()));
+    // Turned boxes don't line up with the sides of the block, except at the top and bottom.
+    assert!(!panel.touches_side(VoxelAxis::PosiX));
+    assert!(panel.touches_side(VoxelAxis::PosiY));
+}
+
+#[test]
+fn test_ray_hits_box() {
+    let slab = [[0.0, 0.0, 0.0], [1.0, 0.5, 1.0]];
+    assert_eq!(ray_hits_box(&slab, Point3::new(0.5, 3.0, 0.5), Vector3::new(0.0, -1.0, 0.0)), Some(2.5));
+    assert_eq!(ray_hits_box(&slab, Point3::new(-2.0, 0.25, 0.5), Vector3::new(1.0, 0.0, 0.0)), Some(2.0));
+    // Over the top of it, or pointing away from it.
+    assert_eq!(ray_hits_box(&slab, Point3::new(-2.0, 0.75, 0.5), Vector3::new(1.0, 0.0, 0.0)), None);
+    assert_eq!(ray_hits_box(&slab, Point3::new(0.5, 3.0, 0.5), Vector3::new(0.0, 1.0, 0.0)), None);
+    assert_eq!(ray_hits_box(&slab, Point3::new(-1.0, 3.0, 0.5), Vector3::new(1.0, -1.0, 0.0)), None);
+    assert_eq!(ray_hits_box(&slab, Point3::new(-1.0, 1.25, 0.5), Vector3::new(1.0, -1.0, 0.0)), Some(1.0));
+    // From inside.
+    assert_eq!(ray_hits_box(&slab, Point3::new(0.5, 0.25, 0.5), Vector3::new(0.0, 1.0, 0.0)), Some(0.0));
+}
@@ -0,0 +1,309 @@
+//! Background chunk generation.
+
+extern crate parking_lot;
+extern crate crossbeam;
+
+use self::parking_lot::{Mutex, Condvar};
+use self::crossbeam::crossbeam_channel::{unbounded, Sender, Receiver};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::thread::JoinHandle;
+use std::collections::{HashMap, HashSet};
+
+use voxel::voxelstorage::*;
+use voxel::voxelmath::*;
+use world::block::Chunk;
+use world::generators::WorldGenerator;
+use world::storage::RegionStore;
+
+/// A chunk we would like to have loaded, and how far it is from the nearest player.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChunkRequest {
+    pub bounds: VoxelRange<i32>,
+    pub distance: f32,
+}
+
+struct Queue {
+    /// Requested but not yet picked up by a worker.
+    pending: HashMap<VoxelPos<i32>, ChunkRequest>,
+    /// Picked up by a worker, and not yet handed back to the Dimension.
+    in_progress: HashSet<VoxelPos<i32>>,
+}
+
+impl Queue {
+    /// See ChunkGenPool::set_requests()
+    fn set_requests(&mut self, wanted: HashMap<VoxelPos<i32>, ChunkRequest>) {
+        let in_progress = &self.in_progress;
+        self.pending = wanted.into_iter().filter(|(pos, _)| !in_progress.contains(pos)).collect();
+    }
+
+    /// Takes the pending request closest to a player.
+    fn pop_nearest(&mut self) -> Option<(VoxelPos<i32>, ChunkRequest)> {
+        let mut nearest : Option<(VoxelPos<i32>, ChunkRequest)> = None;
+        for (pos, request) in self.pending.iter() {
+            let closer = match nearest {
+                Some((_, ref best)) => request.distance < best.distance,
+                None => true,
+            };
+            if closer {
+                nearest = Some((*pos, *request));
+            }
+        }
+        if let Some((pos, _)) = nearest {
+            self.pending.remove(&pos);
+            self.in_progress.insert(pos);
+        }
+        nearest
+    }
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    wake: Condvar,
+    shutdown: AtomicBool,
+}
+
+/// A pool of worker threads which load chunks from disk or generate them, nearest chunks first.
+/// Finished chunks are picked up with poll_finished(), so the Dimension stays single-threaded.
+pub struct ChunkGenPool {
+    shared: Arc<Shared>,
+    results: Receiver<(VoxelPos<i32>, Chunk)>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+/// Gets a chunk from storage if it has been saved before, otherwise generates it.
+fn load_or_generate_chunk(chunk_pos: VoxelPos<i32>, range: VoxelRange<i32>, generator: &dyn WorldGenerator,
+                            storage: &Option<Arc<RegionStore>>, dimension_id: u32) -> Chunk {
+    if let Some(ref storage) = storage {
+        match storage.load_chunk(chunk_pos) {
+            Ok(Some(chunk)) => {
+                let size = chunk.get_bounds().upper;
+                let expected = range.get_size();
+                if (size.x as i32 == expected.x) && (size.y as i32 == expected.y) && (size.z as i32 == expected.z) {
+                    return chunk;
+                }
+                error!("Saved chunk at {} is the wrong size, regenerating it.", chunk_pos);
+            },
+            Ok(None) => {},
+            Err(error) => error!("Could not load saved chunk at {}, regenerating it: {}", chunk_pos, error),
+        }
+    }
+    generator.generate(range, dimension_id)
+}
+
+fn worker_loop(shared: Arc<Shared>, generator: Arc<dyn WorldGenerator>, storage: Option<Arc<RegionStore>>,
+                dimension_id: u32, results: Sender<(VoxelPos<i32>, Chunk)>) {
+    loop {
+        let next = {
+            let mut queue = shared.queue.lock();
+            loop {
+                if shared.shutdown.load(Ordering::Relaxed) {
+                    return;
+                }
+                match queue.pop_nearest() {
+                    Some(next) => break next,
+                    None => shared.wake.wait(&mut queue),
+                }
+            }
+        };
+        let (pos, request) = next;
+        let chunk = load_or_generate_chunk(pos, request.bounds, &*generator, &storage, dimension_id);
+        if results.send((pos, chunk)).is_err() {
+            // Our pool is gone.
+            return;
+        }
+    }
+}
+
+impl ChunkGenPool {
+    pub fn new(generator: Arc<dyn WorldGenerator>, storage: Option<Arc<RegionStore>>, dimension_id: u32, worker_count: usize) -> ChunkGenPool {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue { pending: HashMap::new(), in_progress: HashSet::new() }),
+            wake: Condvar::new(),
+            shutdown: AtomicBool::new(false),
+        });
+        let (sender, receiver) = unbounded();
+        let mut workers = Vec::with_capacity(worker_count);
+        for i in 0..worker_count.max(1) {
+            let shared = shared.clone();
+            let generator = generator.clone();
+            let storage = storage.clone();
+            let sender = sender.clone();
+            workers.push(thread::Builder::new()
+                .name(format!("chunkgen-{}-{}", dimension_id, i))
+                .spawn(move || worker_loop(shared, generator, storage, dimension_id, sender))
+                .expect("Could not spawn a chunk generation thread."));
+        }
+        ChunkGenPool {
+            shared: shared,
+            results: receiver,
+            workers: workers,
+        }
+    }
+
+    /// Replaces the set of chunks we want generated. Pending requests that aren't in wanted are cancelled,
+    /// and ones that are get their distance updated. Chunks already being worked on are left alone.
+    pub fn set_requests(&self, wanted: HashMap<VoxelPos<i32>, ChunkRequest>) {
+        let mut queue = self.shared.queue.lock();
+        queue.set_requests(wanted);
+        if !queue.pending.is_empty() {
+            self.shared.wake.notify_all();
+        }
+    }
+
+    /// Is this chunk waiting for or being worked on by a worker?
+    pub fn is_requested(&self, pos: VoxelPos<i32>) -> bool {
+        let queue = self.shared.queue.lock();
+        queue.pending.contains_key(&pos) || queue.in_progress.contains(&pos)
+    }
+
+    /// Takes every chunk the workers have finished since the last call.
+    pub fn poll_finished(&self) -> Vec<(VoxelPos<i32>, Chunk)> {
+        let finished : Vec<(VoxelPos<i32>, Chunk)> = self.results.try_iter().collect();
+        if !finished.is_empty() {
+            let mut queue = self.shared.queue.lock();
+            for (pos, _) in finished.iter() {
+                queue.in_progress.remove(pos);
+            }
+        }
+        finished
+    }
+}
+
+impl Drop for ChunkGenPool {
+    fn drop(&mut self) {
+        self.shared.shutdown.store(true, Ordering::Relaxed);
+        {
+            // Hold the lock so no worker can miss the wakeup between checking shutdown and waiting.
+            let _queue = self.shared.queue.lock();
+            self.shared.wake.notify_all();
+        }
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+use world::block::BlockStateID;
+#[cfg(test)]
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+fn test_request(pos: VoxelPos<i32>, distance: f32) -> ChunkRequest {
+    ChunkRequest { bounds: VoxelRange::new_origin_size(pos, vpos!(1, 1, 1)), distance: distance }
+}
+
+#[test]
+fn test_chunkgen_queue_order() {
+    let mut queue = Queue { pending: HashMap::new(), in_progress: HashSet::new() };
+    let mut wanted = HashMap::new();
+    for i in 0..8 {
+        wanted.insert(vpos!(i, 0, 0), test_request(vpos!(i, 0, 0), (8 - i) as f32));
+    }
+    queue.set_requests(wanted);
+    assert_eq!(queue.pop_nearest().unwrap().0, vpos!(7, 0, 0));
+    assert_eq!(queue.pop_nearest().unwrap().0, vpos!(6, 0, 0));
+    assert!(queue.in_progress.contains(&vpos!(7, 0, 0)));
+
+    // A player moved: chunk 0 is now the nearest one, and chunks 1 through 4 are out of range.
+    let mut wanted = HashMap::new();
+    wanted.insert(vpos!(0, 0, 0), test_request(vpos!(0, 0, 0), 0.5));
+    wanted.insert(vpos!(5, 0, 0), test_request(vpos!(5, 0, 0), 3.0));
+    wanted.insert(vpos!(7, 0, 0), test_request(vpos!(7, 0, 0), 1.0));
+    queue.set_requests(wanted);
+    // 7 is already being worked on, so it must not be queued a second time.
+    assert_eq!(queue.pending.len(), 2);
+    assert_eq!(queue.pop_nearest().unwrap().0, vpos!(0, 0, 0));
+    assert_eq!(queue.pop_nearest().unwrap().0, vpos!(5, 0, 0));
+    assert!(queue.pop_nearest().is_none());
+}
+
+#[cfg(test)]
+struct TestGenerator;
+
+#[cfg(test)]
+impl WorldGenerator for TestGenerator {
+    fn generate(&self, bounds: VoxelRange<i32>, _dimension_id: u32) -> Chunk {
+        Chunk::new_solid(1, 1, 1, bounds.lower.x as BlockStateID)
+    }
+}
+
+/// Generates like TestGenerator, but only once it's been handed a go-ahead for each chunk.
+#[cfg(test)]
+struct GatedGenerator {
+    gate: Receiver<()>,
+}
+
+#[cfg(test)]
+impl WorldGenerator for GatedGenerator {
+    fn generate(&self, bounds: VoxelRange<i32>, dimension_id: u32) -> Chunk {
+        // If the test has given up on us, just finish.
+        let _ = self.gate.recv();
+        TestGenerator.generate(bounds, dimension_id)
+    }
+}
+
+/// Polls the pool until it has finished count chunks, failing instead of hanging if that takes too long.
+#[cfg(test)]
+fn wait_for_chunks(pool: &ChunkGenPool, count: usize) -> Vec<(VoxelPos<i32>, Chunk)> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut finished = Vec::new();
+    while finished.len() < count {
+        assert!(Instant::now() < deadline, "Timed out with {} of {} chunks finished", finished.len(), count);
+        finished.append(&mut pool.poll_finished());
+        thread::sleep(Duration::from_millis(1));
+    }
+    finished
+}
+
+#[test]
+fn test_chunkgen_pool() {
+    let pool = ChunkGenPool::new(Arc::new(TestGenerator), None, 0, 2);
+    let mut wanted = HashMap::new();
+    for i in 0..16 {
+        wanted.insert(vpos!(i, 0, 0), test_request(vpos!(i, 0, 0), i as f32));
+    }
+    pool.set_requests(wanted);
+    let finished = wait_for_chunks(&pool, 16);
+    for (pos, chunk) in finished {
+        assert_eq!(chunk.uniform_value(), Some(&(pos.x as BlockStateID)));
+        assert!(!pool.is_requested(pos));
+    }
+}
+
+#[test]
+fn test_chunkgen_pool_cancel() {
+    let (gate, gate_receiver) = unbounded();
+    let pool = ChunkGenPool::new(Arc::new(GatedGenerator { gate: gate_receiver }), None, 0, 1);
+    let mut wanted = HashMap::new();
+    for i in 0..4 {
+        wanted.insert(vpos!(i, 0, 0), test_request(vpos!(i, 0, 0), i as f32));
+    }
+    pool.set_requests(wanted);
+    // Wait for our one worker to get stuck on the nearest chunk.
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !pool.shared.queue.lock().in_progress.contains(&vpos!(0, 0, 0)) {
+        assert!(Instant::now() < deadline, "Timed out waiting for a worker to start");
+        thread::sleep(Duration::from_millis(1));
+    }
+
+    // Chunks 1 and 2 aren't wanted any more, so they're cancelled and never generated.
+    let mut wanted = HashMap::new();
+    wanted.insert(vpos!(0, 0, 0), test_request(vpos!(0, 0, 0), 0.0));
+    wanted.insert(vpos!(3, 0, 0), test_request(vpos!(3, 0, 0), 3.0));
+    pool.set_requests(wanted);
+    assert!(!pool.is_requested(vpos!(1, 0, 0)) && !pool.is_requested(vpos!(2, 0, 0)));
+    assert!(pool.is_requested(vpos!(0, 0, 0)) && pool.is_requested(vpos!(3, 0, 0)));
+    for _ in 0..2 {
+        gate.send(()).unwrap();
+    }
+    let mut finished : Vec<VoxelPos<i32>> = wait_for_chunks(&pool, 2).into_iter().map(|(pos, _)| pos).collect();
+    finished.sort_by_key(|pos| pos.x);
+    assert_eq!(finished, vec![vpos!(0, 0, 0), vpos!(3, 0, 0)]);
+    // Nothing else was waiting, so nothing else gets generated even once the gate is gone.
+    drop(gate);
+    thread::sleep(Duration::from_millis(20));
+    assert!(pool.poll_finished().is_empty());
+}
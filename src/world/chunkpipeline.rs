@@ -0,0 +1,167 @@
+//! Tracks where a chunk is in the generate -> mesh pipeline.
+
+extern crate parking_lot;
+
+use self::parking_lot::Mutex;
+
+/// Where a chunk is in the generate -> mesh pipeline.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    /// Queued on or being worked on by a chunk generation worker. There's no ChunkEntry for it yet.
+    Generating,
+    /// Has voxel data, but no up-to-date mesh and no mesh being built.
+    Generated,
+    /// A mesh is being built from the current voxel data.
+    Meshing,
+    /// The mesh reflects the current voxel data.
+    Meshed,
+    /// A mesh is being built, but the voxel data has changed since it started, so it will be out of date.
+    StaleWhileMeshing,
+}
+
+struct PipelineInner {
+    state: ChunkState,
+    /// Incremented every time the chunk's voxel data changes.
+    revision: u64,
+}
+
+/// A chunk's ChunkState plus a revision counter, so a mesher can tell whether the mesh it just
+/// built is still current. Every transition happens under one lock, so an edit can never be lost
+/// between a mesh starting and finishing.
+pub struct ChunkPipeline {
+    inner: Mutex<PipelineInner>,
+}
+
+impl ChunkPipeline {
+    /// A pipeline for a chunk which has just been generated.
+    pub fn new() -> ChunkPipeline {
+        ChunkPipeline { inner: Mutex::new(PipelineInner { state: ChunkState::Generated, revision: 0 }) }
+    }
+
+    pub fn state(&self) -> ChunkState { self.inner.lock().state }
+
+    pub fn revision(&self) -> u64 { self.inner.lock().revision }
+
+    /// Call this after changing the chunk's voxel data, or when a neighbouring chunk's blocks along
+    /// our border change (or it loads or unloads), since our mesh culls faces against those.
+    pub fn mark_modified(&self) {
+        let mut inner = self.inner.lock();
+        inner.revision += 1;
+        inner.state = match inner.state {
+            ChunkState::Meshing | ChunkState::StaleWhileMeshing => ChunkState::StaleWhileMeshing,
+            _ => ChunkState::Generated,
+        };
+    }
+
+    /// If this chunk needs a mesh and nobody is building one, claims it for meshing and returns
+    /// the revision the new mesh will be built from. Pass that to finish_meshing() when done.
+    pub fn begin_meshing(&self) -> Option<u64> {
+        let mut inner = self.inner.lock();
+        match inner.state {
+            ChunkState::Generated => {
+                inner.state = ChunkState::Meshing;
+                Some(inner.revision)
+            },
+            _ => None,
+        }
+    }
+
+    /// Reports that a mesh of the given revision is finished. Returns true if the mesh is current
+    /// and should be used. Otherwise, the chunk goes back to Generated to be meshed again.
+    pub fn finish_meshing(&self, revision: u64) -> bool {
+        let mut inner = self.inner.lock();
+        match inner.state {
+            ChunkState::Meshing if inner.revision == revision => {
+                inner.state = ChunkState::Meshed;
+                true
+            },
+            ChunkState::Meshing | ChunkState::StaleWhileMeshing => {
+                inner.state = ChunkState::Generated;
+                false
+            },
+            // Nobody was meshing this chunk, so whatever this mesh is, it's not one we asked for.
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn test_pipeline_mesh() {
+    let pipeline = ChunkPipeline::new();
+    assert_eq!(pipeline.state(), ChunkState::Generated);
+    let revision = pipeline.begin_meshing().unwrap();
+    assert_eq!(pipeline.state(), ChunkState::Meshing);
+    // Only one mesher at a time.
+    assert!(pipeline.begin_meshing().is_none());
+    assert!(pipeline.finish_meshing(revision));
+    assert_eq!(pipeline.state(), ChunkState::Meshed);
+    assert!(pipeline.begin_meshing().is_none());
+
+    pipeline.mark_modified();
+    assert_eq!(pipeline.state(), ChunkState::Generated);
+    assert_eq!(pipeline.begin_meshing(), Some(revision + 1));
+}
+
+#[test]
+fn test_pipeline_edit_while_meshing() {
+    let pipeline = ChunkPipeline::new();
+    let first = pipeline.begin_meshing().unwrap();
+    pipeline.mark_modified();
+    assert_eq!(pipeline.state(), ChunkState::StaleWhileMeshing);
+    pipeline.mark_modified();
+    assert_eq!(pipeline.state(), ChunkState::StaleWhileMeshing);
+    assert!(pipeline.begin_meshing().is_none());
+
+    // The mesh which was in flight during the edits is thrown out, and the chunk is meshed again.
+    assert!(!pipeline.finish_meshing(first));
+    assert_eq!(pipeline.state(), ChunkState::Generated);
+    let second = pipeline.begin_meshing().unwrap();
+    assert_eq!(second, first + 2);
+    assert!(pipeline.finish_meshing(second));
+    assert_eq!(pipeline.state(), ChunkState::Meshed);
+    // A late report of a mesh nobody is waiting on changes nothing.
+    assert!(!pipeline.finish_meshing(first));
+    assert_eq!(pipeline.state(), ChunkState::Meshed);
+}
+
+#[test]
+fn test_pipeline_concurrent_edits() {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    const EDITS : u64 = 2000;
+    let pipeline = Arc::new(ChunkPipeline::new());
+    let editing_done = Arc::new(AtomicBool::new(false));
+
+    let mesher = {
+        let pipeline = pipeline.clone();
+        let editing_done = editing_done.clone();
+        thread::spawn(move || {
+            let mut accepted = Vec::new();
+            loop {
+                // Read this before trying to mesh, so we make one last pass after the final edit.
+                let done = editing_done.load(Ordering::SeqCst);
+                if let Some(revision) = pipeline.begin_meshing() {
+                    thread::yield_now();
+                    if pipeline.finish_meshing(revision) {
+                        accepted.push(revision);
+                    }
+                }
+                else if done {
+                    return accepted;
+                }
+            }
+        })
+    };
+    for _ in 0..EDITS {
+        pipeline.mark_modified();
+    }
+    editing_done.store(true, Ordering::SeqCst);
+
+    let accepted = mesher.join().unwrap();
+    // Whatever the interleaving, the last mesh we kept reflects the last edit, and we never kept an older mesh after a newer one.
+    assert_eq!(*accepted.last().unwrap(), EDITS);
+    assert!(accepted.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(pipeline.state(), ChunkState::Meshed);
+}
@@ -4,16 +4,24 @@ extern crate parking_lot;
 
 use self::parking_lot::RwLock;
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::error::Error;
 use std::fmt;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use cgmath::{Point3, MetricSpace};
-use world::generators::{WorldGenerator, PerlinGenerator};
+use world::generators::WorldGenerator;
+#[cfg(test)]
+use world::generators::PerlinGenerator;
+use world::chunkgen::{ChunkGenPool, ChunkRequest};
+use world::loadpolicy::ChunkLoadPolicy;
+use world::chunkpipeline::{ChunkPipeline, ChunkState};
+use util::event::{EventBus, SimpleEventBus};
 use voxel::voxelstorage::*;
 use voxel::voxelmath::*;
-use world::block::{BlockID, Chunk};
+use voxel::voxelarray::xyz_to_i;
+use world::block::{BlockStateID, Chunk, AIR};
+use world::storage::RegionStore;
 
 /// An error reported upon trying to get or set a voxel which is not currently loaded. 
 #[derive(Debug, Copy, Clone)]
@@ -37,32 +45,131 @@ impl<T, S> Error for ChunkedVoxelError<T, S> where T : VoxelCoord, S : VoxelCoor
     }
 }
 
-// TODO: Rewrite this to use the standard Futures API.
-/// State used for multithreaded chunk loading. Chunk is dirty and needs to be generated.
-pub static CHUNK_STATE_DIRTY: usize = 0;
-/// State used for multithreaded chunk loading. Chunk mesh is currently being generated.
-pub static CHUNK_STATE_WRITING: usize = 1;
-/// State used for multithreaded chunk loading. Chunk is finished being generated.
-pub static CHUNK_STATE_CLEAN: usize = 2;
+/// Something happened to one of a Dimension's chunks.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChunkEvent {
+    /// A chunk finished generating or loading from disk, and is now in Dimension::chunks.
+    Loaded(VoxelPos<i32>),
+    /// A chunk was removed from Dimension::chunks (after being saved, if it needed to be).
+    Unloaded(VoxelPos<i32>),
+    /// Blocks in a chunk changed. The range, in block coordinates, covers every block which changed.
+    Modified(VoxelPos<i32>, VoxelRange<i32>),
+}
 
 pub struct ChunkEntry { 
     pub data: RwLock<Chunk>,
-    pub state: AtomicUsize,
+    pub pipeline: ChunkPipeline,
     pub bounds: VoxelRange<i32>,
+    /// Has this chunk changed since it was generated or last saved?
+    pub modified: AtomicBool,
+}
+
+impl ChunkEntry {
+    fn new(chunk: Chunk, bounds: VoxelRange<i32>) -> ChunkEntry {
+        ChunkEntry {
+            data: RwLock::new(chunk),
+            pipeline: ChunkPipeline::new(),
+            bounds: bounds,
+            modified: AtomicBool::new(false),
+        }
+    }
+}
+
+/// The layer of blocks just past each side of a chunk, copied out of its neighbours so the chunk can be
+/// meshed without holding on to them. Sides whose neighbour isn't loaded are None.
+#[derive(Clone, Debug, Default)]
+pub struct ChunkNeighbors {
+    /// One layer per side, in VoxelAxis::iter_all() order. Each is laid out like a chunk which is one block thick along that side's axis.
+    sides: [Option<Vec<BlockStateID>>; 6],
+    /// Size of the chunk we're the neighbours of.
+    size: VoxelSize<u32>,
 }
 
+impl ChunkNeighbors {
+    /// Neighbours for a chunk of the given size, with none of them loaded.
+    pub fn new(size: VoxelSize<u32>) -> ChunkNeighbors {
+        ChunkNeighbors { sides: Default::default(), size: size }
+    }
+
+    fn side_index(side: VoxelAxis) -> usize { VoxelAxis::iter_all().position(|axis| axis == side).unwrap() }
+
+    /// Where the block next to pos (a position in our chunk, on the given side) is in that side's layer.
+    fn layer_index(&self, side: VoxelAxis, pos: VoxelPos<u32>) -> usize {
+        let mut layer_size = self.size;
+        layer_size.set_coord_for_axis(side.into(), 1);
+        let mut pos = pos;
+        pos.set_coord_for_axis(side.into(), 0);
+        xyz_to_i(pos.x, pos.y, pos.z, layer_size.x, layer_size.y, layer_size.z)
+    }
+
+    /// Copies the layer of neighbor (the chunk on the given side of ours) which touches our chunk.
+    pub fn set_side(&mut self, side: VoxelAxis, neighbor: &Chunk) {
+        let mut layer_size = self.size;
+        layer_size.set_coord_for_axis(side.into(), 1);
+        // The neighbour's blocks touching ours are along its side facing back towards us.
+        let touching = match side.get_sign() {
+            VoxelAxisSign::POSI => 0,
+            VoxelAxisSign::NEGA => self.size.coord_for_axis(side.into()) - 1,
+        };
+        let mut layer = Vec::with_capacity((layer_size.x * layer_size.y * layer_size.z) as usize);
+        for z in 0..layer_size.z {
+            for y in 0..layer_size.y {
+                for x in 0..layer_size.x {
+                    let mut pos = vpos!(x, y, z);
+                    pos.set_coord_for_axis(side.into(), touching);
+                    layer.push(neighbor.get(vpos!(pos.x as u8, pos.y as u8, pos.z as u8)).unwrap_or(AIR));
+                }
+            }
+        }
+        self.sides[ChunkNeighbors::side_index(side)] = Some(layer);
+    }
+
+    /// The block just past the given side of our chunk from pos, which has to be on that side. None if that neighbour isn't loaded.
+    pub fn get(&self, side: VoxelAxis, pos: VoxelPos<u8>) -> Option<BlockStateID> {
+        let layer = self.sides[ChunkNeighbors::side_index(side)].as_ref()?;
+        layer.get(self.layer_index(side, vpos!(pos.x as u32, pos.y as u32, pos.z as u32))).cloned()
+    }
+}
+
+pub type DimensionID = u32;
+
 /// A dimension.
 pub struct Dimension {
+    pub id: DimensionID,
+    /// Human-readable name, i.e. "Overworld".
+    pub name: String,
     pub chunks: HashMap<VoxelPos<i32>, Arc<ChunkEntry>>,
     pub chunk_size: VoxelSize<u32>,
+    /// Which chunks we keep loaded around players.
+    pub load_policy: ChunkLoadPolicy,
+    /// Where chunks are saved to and loaded from. None if this dimension isn't persisted (i.e. we're a client joined to a server).
+    storage: Option<Arc<RegionStore>>,
+    generator: Arc<dyn WorldGenerator>,
+    /// Started the first time we need to load a chunk.
+    chunk_gen: Option<ChunkGenPool>,
+    /// Subscribe to this to find out when chunks are loaded, unloaded or modified. Events go out in process_chunk_events().
+    pub chunk_events: SimpleEventBus<ChunkEvent>,
+    /// Blocks changed since the last process_chunk_events(), merged into one range per chunk.
+    pending_modifications: HashMap<VoxelPos<i32>, VoxelRange<i32>>,
+    /// Chunks whose neighbours have loaded, unloaded or changed along their border since the last process_chunk_events(),
+    /// which marks them all for remeshing at once. A chunk in the middle of a loading wave only gets remeshed once that way.
+    pending_remeshes: HashSet<VoxelPos<i32>>,
 }
 
+/// How many threads each dimension uses to generate chunks.
+const CHUNK_GEN_THREADS: usize = 3;
+
 pub fn blockpos_to_chunk(point: VoxelPos<i32>, chunk_size : VoxelSize<u32>) -> VoxelPos<i32> {
     vpos!((point.x as f32 / chunk_size.x as f32).floor() as i32, 
         (point.y as f32 / chunk_size.y as f32).floor() as i32, 
         (point.z as f32 / chunk_size.z as f32).floor() as i32)
 }
 
+/// Which chunk a (non-integer) position, such as a player's, is inside of.
+pub fn point_to_chunk(point: Point3<f32>, chunk_size : VoxelSize<u32>) -> VoxelPos<i32> {
+    blockpos_to_chunk(vpos!(point.x.floor() as i32, point.y.floor() as i32, point.z.floor() as i32), chunk_size)
+}
+
 pub fn chunkpos_to_block(point: VoxelPos<i32>, chunk_size : VoxelSize<u32>) -> VoxelPos<i32> { 
     vpos!(point.x * chunk_size.x as i32, 
         point.y * chunk_size.y as i32, 
@@ -80,10 +187,11 @@ pub fn chunkpos_to_center(point: VoxelPos<i32>, chunk_size : VoxelSize<u32>) ->
 fn test_chunkpos() { 
     assert!(blockpos_to_chunk(vpos!(6, -1, 7), vpos!(16, 16, 16)) == vpos!(0, -1, 0));
     assert!(blockpos_to_chunk(vpos!(17, -25, 2), vpos!(8, 24, 4)) == vpos!(2, -2, 0));
+    assert!(point_to_chunk(Point3::new(-0.5, 15.9, 16.0), vpos!(16, 16, 16)) == vpos!(-1, 0, 1));
 }
 
-impl VoxelStorage<BlockID, i32> for Dimension {
-    fn get(&self, coord: VoxelPos<i32>) -> Result<BlockID, VoxelError>{
+impl VoxelStorage<BlockStateID, i32> for Dimension {
+    fn get(&self, coord: VoxelPos<i32>) -> Result<BlockStateID, VoxelError>{
         let size = self.chunk_size.clone();
         let chunkpos = blockpos_to_chunk(coord, size);
         // Do we have a chunk that would contain this block position?
@@ -111,7 +219,7 @@ impl VoxelStorage<BlockID, i32> for Dimension {
             None => return Err(VoxelError::NotYetLoaded(format!("{}", coord))),
         }
     }
-    fn set(&mut self, coord: VoxelPos<i32>, value: BlockID) -> Result<(), VoxelError>{
+    fn set(&mut self, coord: VoxelPos<i32>, value: BlockStateID) -> Result<(), VoxelError>{
         let size = self.chunk_size.clone();
         // Do we have a chunk that would contain this block position?
         let chunkpos = blockpos_to_chunk(coord, size);
@@ -131,8 +239,19 @@ impl VoxelStorage<BlockID, i32> for Dimension {
                         let position = vpos!(pos.x as u8, pos.y as u8, pos.z as u8);
                         let current = locked.get(position)?;
                         if current != value {
-                            chunk_entry.state.store(CHUNK_STATE_DIRTY, Ordering::Relaxed); //Mark for remesh.
                             locked.set(position, value)?;
+                            chunk_entry.pipeline.mark_modified(); //Mark for remesh.
+                            // Neighbouring chunks hide their faces behind this block, or don't, so they need remeshing too.
+                            for side in VoxelAxis::iter_all().filter(|side| bounds.is_on_side(coord, *side)) {
+                                self.mark_for_remesh(chunkpos.get_neighbor(side));
+                            }
+                            chunk_entry.modified.store(true, Ordering::Relaxed); //Mark for saving.
+                            let changed = VoxelRange::new_origin_size(coord, vpos!(1, 1, 1));
+                            let dirty = match self.pending_modifications.get(&chunkpos) {
+                                Some(range) => range.get_union(&changed),
+                                None => changed,
+                            };
+                            self.pending_modifications.insert(chunkpos, dirty);
                         }
                     },
                     // Position is not inside our chunk's bounds.
@@ -148,15 +267,170 @@ impl VoxelStorage<BlockID, i32> for Dimension {
 }
 
 impl Dimension {
-    pub fn new() -> Dimension {
+    pub fn new(id: DimensionID, name: &str, generator: Box<dyn WorldGenerator>, chunk_size: VoxelSize<u32>) -> Dimension {
         Dimension {
+            id: id,
+            name: name.to_owned(),
             chunks: HashMap::new(),
-            chunk_size: vpos!(16, 16, 16),
+            chunk_size: chunk_size,
+            load_policy: ChunkLoadPolicy::default(),
+            storage: None,
+            generator: Arc::from(generator),
+            chunk_gen: None,
+            chunk_events: SimpleEventBus::new(),
+            pending_modifications: HashMap::new(),
+            pending_remeshes: HashSet::new(),
+        }
+    }
+
+    /// Marks every chunk whose neighbours changed since the last call for remeshing, and sends out every ChunkEvent
+    /// since the last call to our subscribers. Call this once a tick, after loading chunks.
+    pub fn process_chunk_events(&mut self) {
+        for chunk_pos in self.pending_remeshes.drain() {
+            if let Some(entry) = self.chunks.get(&chunk_pos) {
+                // Chunks which are waiting to be meshed will see their neighbours when they are.
+                if entry.pipeline.state() != ChunkState::Generated {
+                    entry.pipeline.mark_modified();
+                }
+            }
+        }
+        for (chunk_pos, range) in self.pending_modifications.drain() {
+            // Can't fail, we own both ends of this channel.
+            self.chunk_events.push(ChunkEvent::Modified(chunk_pos, range)).unwrap();
+        }
+        self.chunk_events.process();
+    }
+
+    /// Persist this dimension's chunks to the given store, and prefer chunks saved there over generating new ones.
+    pub fn set_storage(&mut self, storage: RegionStore) {
+        self.storage = Some(Arc::new(storage));
+        // Workers started before this don't know about our storage.
+        self.chunk_gen = None;
+    }
+
+    /// The blocks covered by the chunk at chunk_pos.
+    pub fn chunk_range(&self, chunk_pos: VoxelPos<i32>) -> VoxelRange<i32> {
+        let origin = chunkpos_to_block(chunk_pos, self.chunk_size);
+        let mut range = VoxelRange{lower: origin,
+                upper : origin + vpos!(self.chunk_size.x as i32, self.chunk_size.y as i32, self.chunk_size.z as i32)};
+        range.validate();
+        range
+    }
+
+    /// Hands chunks which finished generating over to the dimension.
+    fn insert_finished_chunks(&mut self) {
+        let finished = match self.chunk_gen {
+            Some(ref pool) => pool.poll_finished(),
+            None => return,
+        };
+        for (chunk_pos, chunk) in finished {
+            self.insert_chunk(chunk_pos, chunk);
+        }
+    }
+
+    fn insert_chunk(&mut self, chunk_pos: VoxelPos<i32>, chunk: Chunk) {
+        let range = self.chunk_range(chunk_pos);
+        self.chunks.insert(chunk_pos, Arc::new(ChunkEntry::new(chunk, range)));
+        // Our neighbours can now hide the faces they have up against us.
+        for side in VoxelAxis::iter_all() {
+            self.mark_for_remesh(chunk_pos.get_neighbor(side));
+        }
+        self.chunk_events.push(ChunkEvent::Loaded(chunk_pos)).unwrap();
+    }
+
+    /// Makes a loaded chunk's mesh out of date, if there's a chunk there, the next time process_chunk_events() is called.
+    fn mark_for_remesh(&mut self, chunk_pos: VoxelPos<i32>) {
+        self.pending_remeshes.insert(chunk_pos);
+    }
+
+    /// Copies what the mesher needs to know about the chunks around the one at chunk_pos.
+    pub fn chunk_neighbors(&self, chunk_pos: VoxelPos<i32>) -> ChunkNeighbors {
+        let mut neighbors = ChunkNeighbors::new(self.chunk_size);
+        for side in VoxelAxis::iter_all() {
+            if let Some(entry) = self.chunks.get(&chunk_pos.get_neighbor(side)) {
+                neighbors.set_side(side, &entry.data.read());
+            }
+        }
+        neighbors
+    }
+
+    /// Asks our worker pool for exactly these chunks (minus any already loaded), cancelling anything else it was going to generate.
+    fn request_chunks(&mut self, mut wanted: HashMap<VoxelPos<i32>, ChunkRequest>) {
+        wanted.retain(|pos, _| !self.chunks.contains_key(pos));
+        if self.chunk_gen.is_none() {
+            if wanted.is_empty() {
+                return;
+            }
+            self.chunk_gen = Some(ChunkGenPool::new(self.generator.clone(), self.storage.clone(), self.id, CHUNK_GEN_THREADS));
+        }
+        self.chunk_gen.as_ref().unwrap().set_requests(wanted);
+    }
+
+    /// Removes the chunks at the given positions, saving any of them that have been modified.
+    fn unload_chunks(&mut self, positions: Vec<VoxelPos<i32>>) {
+        let mut removed = Vec::new();
+        for pos in positions {
+            if let Some(entry) = self.chunks.remove(&pos) {
+                removed.push((pos, entry));
+                self.pending_modifications.remove(&pos);
+            }
+        }
+        self.save_entries(removed.iter().filter(|(_, entry)| entry.modified.load(Ordering::Relaxed)));
+        for (pos, _) in removed.iter() {
+            // Faces which were hidden against this chunk aren't any more.
+            for side in VoxelAxis::iter_all() {
+                self.mark_for_remesh(pos.get_neighbor(side));
+            }
+            self.chunk_events.push(ChunkEvent::Unloaded(*pos)).unwrap();
+        }
+    }
+
+    /// Saves every loaded chunk that has been modified since it was last saved.
+    pub fn save_all(&self) {
+        let modified : Vec<(VoxelPos<i32>, Arc<ChunkEntry>)> = self.chunks.iter()
+            .filter(|(_, entry)| entry.modified.load(Ordering::Relaxed))
+            .map(|(pos, entry)| (*pos, entry.clone())).collect();
+        self.save_entries(modified.iter());
+    }
+
+    fn save_entries<'a, I>(&self, entries: I) where I: Iterator<Item=&'a (VoxelPos<i32>, Arc<ChunkEntry>)> {
+        let storage = match self.storage {
+            Some(ref storage) => storage,
+            None => return,
+        };
+        let entries : Vec<&(VoxelPos<i32>, Arc<ChunkEntry>)> = entries.collect();
+        if entries.is_empty() {
+            return;
+        }
+        let locks : Vec<_> = entries.iter().map(|(pos, entry)| (*pos, entry.data.read())).collect();
+        match storage.save_chunks(locks.iter().map(|(pos, lock)| (*pos, &**lock)).collect()) {
+            Ok(_) => {
+                for (_, entry) in entries.iter() {
+                    entry.modified.store(false, Ordering::Relaxed);
+                }
+            },
+            Err(error) => error!("Could not save {} chunks: {}", entries.len(), error),
         }
     }
 
     pub fn is_chunk_loaded(&self, chunk_pos : VoxelPos<i32> ) -> bool {self.chunks.contains_key(&chunk_pos)}
 
+    /// Where a chunk is in the generate -> mesh pipeline. None if it's neither loaded nor being generated.
+    pub fn chunk_state(&self, chunk_pos : VoxelPos<i32>) -> Option<ChunkState> {
+        match self.chunks.get(&chunk_pos) {
+            Some(entry) => Some(entry.pipeline.state()),
+            None => match self.chunk_gen {
+                Some(ref pool) if pool.is_requested(chunk_pos) => Some(ChunkState::Generating),
+                _ => None,
+            },
+        }
+    }
+
+    /// How many of our loaded chunks are a single value all the way through (i.e. all air or all stone).
+    pub fn uniform_chunk_count(&self) -> usize {
+        self.chunks.values().filter(|entry| entry.data.read().is_uniform()).count()
+    }
+
     pub fn loaded_chunk_list(&self) -> Vec<VoxelPos<i32>> {
         let mut result = Vec::new();
         for pos in self.chunks.keys() {
@@ -165,109 +439,152 @@ impl Dimension {
         result
     }
 
-    /// Adds new chunks as the player moves closer to them, and removes old chunks as the player
-    /// moves away.
-    pub fn load_unload_chunks_clientside(&mut self, player_pos: Point3<f32>) {
-        const CHUNK_RADIUS: i32 = 2;
-        const CHUNK_DISTANCE: f32 = CHUNK_RADIUS as f32 * 2.0 * 16.0;
-        const RETAIN_RADIUS: f32 = CHUNK_DISTANCE + 4.0; // offset added to prevent load/unload loop on the edge
-
-        let gen = PerlinGenerator::new();
+    /// Adds new chunks as players move closer to them, and removes old chunks once every player
+    /// has moved away from them, following our load_policy.
+    pub fn load_unload_chunks(&mut self, player_positions: &[Point3<f32>]) {
+        self.insert_finished_chunks();
 
         let chunk_size = self.chunk_size.clone();
-        
-        self.chunks.retain(|pos, _| {
-            let chunk_pos = chunkpos_to_center(*pos, chunk_size);
-            let dist = Point3::distance(chunk_pos, player_pos);
-            dist < RETAIN_RADIUS // offset added to prevent load/unload loop on the edge
-        });
-
-        let player_x_in_chunks = (player_pos.x / (self.chunk_size.x as f32)) as i32;
-        let player_y_in_chunks = (player_pos.y / (self.chunk_size.y as f32)) as i32;
-        let player_z_in_chunks = (player_pos.z / (self.chunk_size.z as f32)) as i32;
-        for cx in (player_x_in_chunks-CHUNK_RADIUS)..(player_x_in_chunks+CHUNK_RADIUS+1) {
-            for cy in (player_y_in_chunks-CHUNK_RADIUS)..(player_y_in_chunks+CHUNK_RADIUS+1) {
-                for cz in (player_z_in_chunks-CHUNK_RADIUS)..(player_z_in_chunks+CHUNK_RADIUS+1) {
-                    let chunk_pos = vpos!(cx, cy, cz);
-                    if self.chunks.contains_key(&chunk_pos) {
-                        continue;
-                    }
-
-                    let chunk_world_pos = chunkpos_to_center(vpos!(cx, cy, cz), chunk_size);
-                    let dist = Point3::distance(chunk_world_pos, player_pos);
-                    if dist < CHUNK_DISTANCE {
-                        let chunk_origin = chunkpos_to_block(vpos!(cx, cy, cz), chunk_size);
-                        let mut range = VoxelRange{lower: chunk_origin, 
-                                upper : chunk_origin + vpos!(self.chunk_size.x as i32, self.chunk_size.y as i32, self.chunk_size.z as i32)};
-                        range.validate();
-                        let chunk = gen.generate(range.clone(), 0);
-                        self.chunks.insert(chunk_pos, Arc::new(
-                            ChunkEntry { 
-                                data: RwLock::new(chunk),
-                                state: AtomicUsize::new(CHUNK_STATE_DIRTY),
-                                bounds: range.clone(),
-                            }
-                        ));
-                        //queue.chunks_changed = true;
-                    }
+        let policy = self.load_policy.clone();
+        let player_chunks : Vec<(Point3<f32>, VoxelPos<i32>)> = player_positions.iter()
+            .map(|pos| (*pos, point_to_chunk(*pos, chunk_size))).collect();
+
+        let to_unload : Vec<VoxelPos<i32>> = self.chunks.keys().cloned().filter(|pos| {
+            !player_chunks.iter().any(|(_, center)| policy.should_retain(*center, *pos))
+        }).collect();
+        self.unload_chunks(to_unload);
+
+        let mut wanted : HashMap<VoxelPos<i32>, ChunkRequest> = HashMap::new();
+        for (player_pos, center) in player_chunks.iter() {
+            for chunk_pos in policy.chunks_to_load(*center) {
+                if self.chunks.contains_key(&chunk_pos) {
+                    continue;
+                }
+                let dist = Point3::distance(chunkpos_to_center(chunk_pos, chunk_size), *player_pos);
+                // Prioritize by distance to whichever player is nearest.
+                let request = wanted.entry(chunk_pos).or_insert(ChunkRequest { bounds: self.chunk_range(chunk_pos), distance: dist });
+                if dist < request.distance {
+                    request.distance = dist;
                 }
             }
         }
+        self.request_chunks(wanted);
     }
-    pub fn load_unload_chunks_serverside(&mut self, player_positions: Vec<Point3<f32>>) {
-        const CHUNK_RADIUS: i32 = 2;
-        const CHUNK_DISTANCE: f32 = CHUNK_RADIUS as f32 * 2.0 * 16.0;
-        const RETAIN_RADIUS: f32 = CHUNK_DISTANCE + 4.0; // offset added to prevent load/unload loop on the edge
 
-        let gen = PerlinGenerator::new();
+    pub fn load_unload_chunks_clientside(&mut self, player_pos: Point3<f32>) {
+        self.load_unload_chunks(&[player_pos]);
+    }
 
-        let chunk_size = self.chunk_size.clone();
-        
-        self.chunks.retain(|pos, _| {
-            let mut keep : bool = false;
-            for player_pos in player_positions.iter() {
-                let chunk_pos = chunkpos_to_center(*pos, chunk_size);
-                let dist = Point3::distance(chunk_pos, *player_pos);
-                if dist < RETAIN_RADIUS {
-                    keep = true;
-                }
-            }
-            keep
-        });
-
-        for player_pos_ref in player_positions.iter() {
-            let player_pos = *player_pos_ref;
-            let player_x_in_chunks = (player_pos.x / (self.chunk_size.x as f32)) as i32;
-            let player_y_in_chunks = (player_pos.y / (self.chunk_size.y as f32)) as i32;
-            let player_z_in_chunks = (player_pos.z / (self.chunk_size.z as f32)) as i32;
-            for cx in (player_x_in_chunks-CHUNK_RADIUS)..(player_x_in_chunks+CHUNK_RADIUS+1) {
-                for cy in (player_y_in_chunks-CHUNK_RADIUS)..(player_y_in_chunks+CHUNK_RADIUS+1) {
-                    for cz in (player_z_in_chunks-CHUNK_RADIUS)..(player_z_in_chunks+CHUNK_RADIUS+1) {
-                        let chunk_pos = vpos!(cx, cy, cz);
-                        if self.chunks.contains_key(&chunk_pos) {
-                            continue;
-                        }
+    pub fn load_unload_chunks_serverside(&mut self, player_positions: Vec<Point3<f32>>) {
+        self.load_unload_chunks(&player_positions);
+    }
+}
 
-                        let chunk_world_pos = chunkpos_to_center(vpos!(cx, cy, cz), chunk_size);
-                        let dist = Point3::distance(chunk_world_pos, player_pos);
-                        if dist < CHUNK_DISTANCE {
-                            let chunk_origin = chunkpos_to_block(vpos!(cx, cy, cz), chunk_size);
-                            let mut range = VoxelRange{lower: chunk_origin, 
-                                    upper : chunk_origin + vpos!(self.chunk_size.x as i32, self.chunk_size.y as i32, self.chunk_size.z as i32)};
-                            range.validate();
-                            let chunk = gen.generate(range.clone(), 0);
-                            self.chunks.insert(chunk_pos, Arc::new(
-                                ChunkEntry { 
-                                    data: RwLock::new(chunk),
-                                    state: AtomicUsize::new(CHUNK_STATE_DIRTY),
-                                    bounds: range.clone(),
-                                }
-                            ));
-                            //queue.chunks_changed = true;
-                        }
-                    }
-                }
-            }
+#[test]
+fn test_modified_chunks_survive_unload() {
+    let dir = ::std::env::temp_dir().join(format!("voxelgame_dimension_test_{}", ::std::process::id()));
+    let mut dimension = Dimension::new(0, "Test", Box::new(PerlinGenerator::new(0)), vpos!(16, 16, 16));
+    dimension.set_storage(RegionStore::new(&dir).unwrap());
+    let origin = Point3::new(8.0, 8.0, 8.0);
+    // Chunks are generated in the background, so keep calling the loader until ours shows up.
+    let load_at = |dimension: &mut Dimension, pos: Point3<f32>, chunk_pos: VoxelPos<i32>| {
+        let start = ::std::time::Instant::now();
+        while !dimension.is_chunk_loaded(chunk_pos) {
+            assert!(start.elapsed() < ::std::time::Duration::from_secs(10), "Timed out waiting for chunk {}", chunk_pos);
+            dimension.load_unload_chunks_clientside(pos);
+            ::std::thread::sleep(::std::time::Duration::from_millis(1));
         }
+    };
+
+    load_at(&mut dimension, origin, vpos!(0, 0, 0));
+    let changed = if dimension.get(vpos!(1, 2, 3)).unwrap() == 3 { 2 } else { 3 };
+    dimension.set(vpos!(1, 2, 3), changed).unwrap();
+
+    // Walk far enough away that our chunk gets unloaded, then come back.
+    dimension.load_unload_chunks_clientside(Point3::new(10000.0, 8.0, 8.0));
+    assert!(!dimension.is_chunk_loaded(vpos!(0, 0, 0)));
+    load_at(&mut dimension, origin, vpos!(0, 0, 0));
+    assert_eq!(dimension.get(vpos!(1, 2, 3)).unwrap(), changed);
+    assert!(!dimension.chunks.get(&vpos!(0, 0, 0)).unwrap().modified.load(Ordering::Relaxed));
+
+    ::std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_chunk_events() {
+    let mut dimension = Dimension::new(0, "Test", Box::new(PerlinGenerator::new(0)), vpos!(16, 16, 16));
+    let (events, _) = dimension.chunk_events.subscribe();
+    let range = dimension.chunk_range(vpos!(0, 0, 0));
+    dimension.chunks.insert(vpos!(0, 0, 0), Arc::new(ChunkEntry::new(Chunk::new_solid(16, 16, 16, 0), range)));
+
+    dimension.set(vpos!(1, 2, 3), 1).unwrap();
+    dimension.set(vpos!(4, 0, 3), 1).unwrap();
+    // Setting a block to what it already is isn't a modification.
+    dimension.set(vpos!(15, 15, 15), 0).unwrap();
+    dimension.process_chunk_events();
+    let received : Vec<ChunkEvent> = events.try_iter().collect();
+    assert_eq!(received, vec![ChunkEvent::Modified(vpos!(0, 0, 0), VoxelRange::new(vpos!(1, 0, 3), vpos!(5, 3, 4)))]);
+
+    dimension.unload_chunks(vec![vpos!(0, 0, 0)]);
+    dimension.process_chunk_events();
+    let received : Vec<ChunkEvent> = events.try_iter().collect();
+    assert_eq!(received, vec![ChunkEvent::Unloaded(vpos!(0, 0, 0))]);
+}
+
+#[test]
+fn test_neighbors_remeshed() {
+    let mut dimension = Dimension::new(0, "Test", Box::new(PerlinGenerator::new(0)), vpos!(4, 4, 4));
+    let is_meshed = |dimension: &Dimension, pos: VoxelPos<i32>| dimension.chunk_state(pos) == Some(ChunkState::Meshed);
+    let mesh = |dimension: &Dimension, pos: VoxelPos<i32>| {
+        let pipeline = &dimension.chunks[&pos].pipeline;
+        let revision = pipeline.begin_meshing().unwrap();
+        assert!(pipeline.finish_meshing(revision));
+    };
+    dimension.insert_chunk(vpos!(0, 0, 0), Chunk::new_solid(4, 4, 4, 1));
+    mesh(&dimension, vpos!(0, 0, 0));
+
+    // A new neighbour means a remesh.
+    let mut above = Chunk::new_solid(4, 4, 4, 2);
+    above.set(vpos!(1, 0, 2), 3).unwrap();
+    dimension.insert_chunk(vpos!(0, 1, 0), above);
+    // Not until the loading is done, though.
+    assert!(is_meshed(&dimension, vpos!(0, 0, 0)));
+    dimension.process_chunk_events();
+    assert!(!is_meshed(&dimension, vpos!(0, 0, 0)));
+    mesh(&dimension, vpos!(0, 0, 0));
+    mesh(&dimension, vpos!(0, 1, 0));
+
+    let neighbors = dimension.chunk_neighbors(vpos!(0, 0, 0));
+    assert_eq!(neighbors.get(VoxelAxis::PosiY, vpos!(1, 3, 2)), Some(3));
+    assert_eq!(neighbors.get(VoxelAxis::PosiY, vpos!(0, 3, 0)), Some(2));
+    assert_eq!(neighbors.get(VoxelAxis::NegaY, vpos!(0, 0, 0)), None);
+    assert_eq!(dimension.chunk_neighbors(vpos!(0, 1, 0)).get(VoxelAxis::NegaY, vpos!(3, 0, 3)), Some(1));
+
+    // Changing a block in the middle of a chunk doesn't bother its neighbours, but changing one on the border does.
+    dimension.set(vpos!(1, 5, 2), 1).unwrap();
+    dimension.process_chunk_events();
+    assert!(is_meshed(&dimension, vpos!(0, 0, 0)));
+    mesh(&dimension, vpos!(0, 1, 0));
+    dimension.set(vpos!(1, 4, 2), 1).unwrap();
+    dimension.process_chunk_events();
+    assert!(!is_meshed(&dimension, vpos!(0, 0, 0)));
+    assert_eq!(dimension.chunk_neighbors(vpos!(0, 0, 0)).get(VoxelAxis::PosiY, vpos!(1, 3, 2)), Some(1));
+    mesh(&dimension, vpos!(0, 0, 0));
+
+    // So does losing one.
+    dimension.unload_chunks(vec![vpos!(0, 1, 0)]);
+    dimension.process_chunk_events();
+    assert!(!is_meshed(&dimension, vpos!(0, 0, 0)));
+    assert_eq!(dimension.chunk_neighbors(vpos!(0, 0, 0)).get(VoxelAxis::PosiY, vpos!(1, 3, 2)), None);
+    mesh(&dimension, vpos!(0, 0, 0));
+
+    // A whole wave of neighbours loading at once only means one remesh, and chunks which haven't been meshed yet
+    // aren't touched at all.
+    let revision = dimension.chunks[&vpos!(0, 0, 0)].pipeline.revision();
+    for side in VoxelAxis::iter_all() {
+        dimension.insert_chunk(vpos!(0, 0, 0).get_neighbor(side), Chunk::new_solid(4, 4, 4, 2));
     }
-}
\ No newline at end of file
+    dimension.process_chunk_events();
+    assert_eq!(dimension.chunks[&vpos!(0, 0, 0)].pipeline.revision(), revision + 1);
+    assert!(VoxelAxis::iter_all().all(|side| dimension.chunks[&vpos!(0, 0, 0).get_neighbor(side)].pipeline.revision() == 0));
+}
@@ -0,0 +1,164 @@
+//! Carves caves out of already-generated terrain using 3D noise.
+
+use world::generators::{sub_seed, AIR};
+use world::generators::fractalnoise::FractalNoise;
+use world::generators::pipeline::{GeneratorStage, StageKind, StageContext};
+
+use voxel::voxelmath::*;
+use voxel::voxelstorage::*;
+use world::block::{BlockStateID, Chunk};
+
+/// Mixed into the world seed, so caves don't share noise with the terrain they're carved out of.
+const CAVE_SEED_SALT : u64 = 0x4341_5645;
+
+/// Sub-seed layers for each of our noise fields.
+const TUNNEL_A_LAYER : u64 = 0;
+const TUNNEL_B_LAYER : u64 = 1;
+const CAVERN_LAYER : u64 = 2;
+
+/// How many caves there are, and where.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CaveSettings {
+    /// From 0 (no caves) to 1 (mostly caves). Raises both how wide tunnels are and how much
+    /// of the ground is hollowed out into caverns.
+    pub density: f64,
+    /// The lowest Y a cave can reach.
+    pub min_y: i32,
+    /// The highest Y a cave can reach.
+    pub max_y: i32,
+}
+
+impl Default for CaveSettings {
+    fn default() -> Self {
+        CaveSettings {
+            density: 0.5,
+            min_y: -64,
+            max_y: 16,
+        }
+    }
+}
+
+/// Carves tunnels and caverns out of solid blocks.
+/// Tunnels follow the lines where two 3D noise fields both cross zero, which makes long winding worms.
+/// Caverns are the peaks of a third, lower frequency field.
+/// Whether a voxel is carved only depends on its position in the world, so caves run straight through chunk borders in every direction.
+pub struct CaveCarver {
+    settings: CaveSettings,
+    tunnel_a: FractalNoise,
+    tunnel_b: FractalNoise,
+    caverns: FractalNoise,
+}
+
+impl CaveCarver {
+    pub fn new(seed: u64, settings: CaveSettings) -> CaveCarver {
+        let seed = sub_seed(seed, CAVE_SEED_SALT);
+        CaveCarver {
+            settings: settings,
+            tunnel_a: FractalNoise::new(seed, TUNNEL_A_LAYER, 2, 0.0213),
+            tunnel_b: FractalNoise::new(seed, TUNNEL_B_LAYER, 2, 0.0213),
+            caverns: FractalNoise::new(seed, CAVERN_LAYER, 2, 0.01147),
+        }
+    }
+
+    pub fn settings(&self) -> CaveSettings { self.settings }
+
+    /// Should the voxel at this world position be hollowed out?
+    pub fn is_cave(&self, pos: VoxelPos<i32>) -> bool {
+        let density = self.settings.density;
+        if density <= 0.0 || pos.y < self.settings.min_y || pos.y > self.settings.max_y {
+            return false;
+        }
+        let (x, y, z) = (pos.x as f64, pos.y as f64, pos.z as f64);
+        let tunnel_radius = 0.09 * density;
+        let a = self.tunnel_a.get3(x, y, z);
+        let b = self.tunnel_b.get3(x, y, z);
+        if (a * a) + (b * b) < tunnel_radius * tunnel_radius {
+            return true;
+        }
+        self.caverns.get3(x, y, z) > 0.6 - (0.3 * density)
+    }
+
+    /// Carves caves out of a chunk covering bounds. Only solid blocks are touched.
+    pub fn carve(&self, bounds: VoxelRange<i32>, chunk: &mut Chunk) {
+        if bounds.upper.y <= self.settings.min_y || bounds.lower.y > self.settings.max_y {
+            return;
+        }
+        if chunk.uniform_value() == Some(&AIR) {
+            return;
+        }
+        for pos in bounds {
+            if !self.is_cave(pos) {
+                continue;
+            }
+            let local = pos - bounds.lower;
+            let local = vpos!(local.x as u8, local.y as u8, local.z as u8);
+            if chunk.get(local).unwrap() != AIR {
+                chunk.set(local, AIR).unwrap();
+            }
+        }
+    }
+}
+
+impl GeneratorStage for CaveCarver {
+    fn kind(&self) -> StageKind { StageKind::Carver }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        self.carve(context.bounds, chunk);
+    }
+
+    fn surface(&self, x: i32, z: i32, so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> {
+        match so_far {
+            // A cave opening up at the surface leaves nothing to stand on.
+            Some((height, _)) if self.is_cave(vpos!(x, height, z)) => None,
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+use world::generators::WorldGenerator;
+#[cfg(test)]
+use world::generators::terraingenerator::terrain_pipeline;
+
+#[test]
+fn test_caves_cross_vertical_borders() {
+    let settings = CaveSettings { density: 1.0, min_y: -40, max_y: 40 };
+    let generator = terrain_pipeline(77).with_stage(Box::new(CaveCarver::new(77, settings)));
+    let tall = generator.generate(VoxelRange::new_origin_size(vpos!(0, -16, 0), vpos!(16, 32, 16)), 0);
+    let lower = generator.generate(VoxelRange::new_origin_size(vpos!(0, -16, 0), vpos!(16, 16, 16)), 0);
+    let upper = generator.generate(VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(16, 16, 16)), 0);
+    for pos in tall.get_bounds() {
+        let expected = if pos.y < 16 { lower.get(pos) } else { upper.get(vpos!(pos.x, pos.y - 16, pos.z)) };
+        assert_eq!(tall.get(pos).unwrap(), expected.unwrap());
+    }
+}
+
+#[test]
+fn test_caves_depth_range() {
+    let seed = 3;
+    let settings = CaveSettings { density: 1.0, min_y: -20, max_y: -5 };
+    let plain = terrain_pipeline(seed);
+    let caved = terrain_pipeline(seed).with_stage(Box::new(CaveCarver::new(seed, settings)));
+    let bounds = VoxelRange::new_origin_size(vpos!(-24, -32, 40), vpos!(48, 48, 48));
+    let before = plain.generate(bounds, 0);
+    let after = caved.generate(bounds, 0);
+    let mut carved = 0;
+    for pos in before.get_bounds() {
+        let old = before.get(pos).unwrap();
+        let new = after.get(pos).unwrap();
+        if old != new {
+            let world_y = pos.y as i32 + bounds.lower.y;
+            assert!(world_y >= settings.min_y && world_y <= settings.max_y);
+            assert_eq!(new, AIR);
+            carved += 1;
+        }
+    }
+    assert!(carved > 0);
+
+    // No density, no caves.
+    let none = terrain_pipeline(seed).with_stage(Box::new(CaveCarver::new(seed, CaveSettings { density: 0.0, .. settings })));
+    let after = none.generate(bounds, 0);
+    for pos in before.get_bounds() {
+        assert_eq!(before.get(pos).unwrap(), after.get(pos).unwrap());
+    }
+}
@@ -0,0 +1,385 @@
+//! Decoration: features such as trees, ore veins and boulders, which can be bigger than one chunk.
+//!
+//! Features aren't placed chunk by chunk. Instead, the world is split into columns of cells
+//! DECORATION_CELL_SIZE blocks across, and each cell plans its features from the seed and the
+//! shape of the terrain alone. A plan is a list of block writes, some of which may land outside
+//! the cell. When a chunk generates, it applies the writes of every cell that can reach it, and
+//! plans are kept around so the writes that spill into neighbors are waiting when they generate.
+//! Since plans never look at chunk contents, the result doesn't depend on which chunk generates first.
+
+extern crate parking_lot;
+
+use self::parking_lot::Mutex;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use voxel::voxelmath::*;
+use voxel::voxelstorage::*;
+use world::block::{BlockStateID, Chunk};
+use world::generators::{sub_seed, AIR, STONE, GRASS, LOG, LEAVES, COAL_ORE};
+use world::generators::pipeline::{GeneratorStage, StageKind, StageContext, SurfaceInfo};
+
+/// Width and depth, in blocks, of the cells features are planned in. This doesn't depend on chunk size.
+pub const DECORATION_CELL_SIZE : i32 = 16;
+/// Features can write at most this many blocks outside of their cell, horizontally. Writes any further out are dropped.
+pub const MAX_FEATURE_REACH : i32 = 8;
+/// How many cells' plans we hold onto. A plan that got dropped is planned again if it's needed, with the same result.
+const PLAN_CACHE_SIZE : usize = 1024;
+
+/// What a feature's write is allowed to overwrite.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Replace {
+    /// Anything.
+    Any,
+    /// Only air, so features grow around the terrain instead of through it.
+    Air,
+    /// Only this kind of block.
+    Only(BlockStateID),
+}
+
+/// One block a feature wants to place.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FeatureWrite {
+    pub pos: VoxelPos<i32>,
+    pub block: BlockStateID,
+    pub replace: Replace,
+}
+
+/// Small deterministic random number generator for planning features (SplitMix64).
+/// We don't use rand's generators here because their output isn't promised to stay the same between versions.
+pub struct FeatureRng {
+    state: u64,
+}
+
+impl FeatureRng {
+    pub fn new(seed: u64) -> FeatureRng { FeatureRng { state: seed } }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let value = sub_seed(self.state, 0);
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        value
+    }
+
+    /// A random number from low up to but not including high.
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u64() % ((high - low) as u64)) as i32
+    }
+
+    /// True with the given chance, from 0 to 1.
+    pub fn chance(&mut self, chance: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < chance
+    }
+}
+
+/// Something which can be placed in the world, possibly spilling into neighboring chunks.
+pub trait Feature : Send + Sync {
+    /// Plans this feature's placements in the cell whose lowest corner is at (cell_x, cell_z), adding their writes to writes.
+    /// Must only depend on the arguments, so the same cell always gets the same plan.
+    fn plan(&self, cell_x: i32, cell_z: i32, rng: &mut FeatureRng, surface: &dyn SurfaceInfo, writes: &mut Vec<FeatureWrite>);
+}
+
+/// Trees on grass: a log trunk with a ball of leaves on top.
+pub struct TreeFeature {
+    /// How many places in each cell we try to grow a tree.
+    pub attempts: u32,
+    /// How likely each attempt is to grow a tree, from 0 to 1.
+    pub chance: f64,
+}
+
+impl Feature for TreeFeature {
+    fn plan(&self, cell_x: i32, cell_z: i32, rng: &mut FeatureRng, surface: &dyn SurfaceInfo, writes: &mut Vec<FeatureWrite>) {
+        for _ in 0..self.attempts {
+            let x = cell_x + rng.range(0, DECORATION_CELL_SIZE);
+            let z = cell_z + rng.range(0, DECORATION_CELL_SIZE);
+            let trunk_height = rng.range(4, 7);
+            if !rng.chance(self.chance) {
+                continue;
+            }
+            let ground = match surface.surface_height(x, z) {
+                Some(ground) => ground,
+                None => continue,
+            };
+            if surface.surface_block(x, z) != GRASS {
+                continue;
+            }
+            let top = ground + trunk_height;
+            for dx in -2..3i32 {
+                for dy in -2..2i32 {
+                    for dz in -2..3i32 {
+                        // Round the corners off.
+                        if dx.abs() + dy.abs() + dz.abs() > 3 {
+                            continue;
+                        }
+                        writes.push(FeatureWrite { pos: vpos!(x + dx, top + dy, z + dz), block: LEAVES, replace: Replace::Air });
+                    }
+                }
+            }
+            for y in (ground + 1)..(top + 1) {
+                writes.push(FeatureWrite { pos: vpos!(x, y, z), block: LOG, replace: Replace::Any });
+            }
+        }
+    }
+}
+
+/// Veins of ore winding through stone.
+pub struct OreVeinFeature {
+    pub ore: BlockStateID,
+    pub veins_per_cell: u32,
+    /// How many blocks long each vein is.
+    pub length: u32,
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+impl Feature for OreVeinFeature {
+    fn plan(&self, cell_x: i32, cell_z: i32, rng: &mut FeatureRng, _surface: &dyn SurfaceInfo, writes: &mut Vec<FeatureWrite>) {
+        for _ in 0..self.veins_per_cell {
+            let mut pos = vpos!(cell_x + rng.range(0, DECORATION_CELL_SIZE),
+                                rng.range(self.min_y, self.max_y + 1),
+                                cell_z + rng.range(0, DECORATION_CELL_SIZE));
+            for _ in 0..self.length {
+                writes.push(FeatureWrite { pos: pos, block: self.ore, replace: Replace::Only(STONE) });
+                let direction = match rng.range(0, 6) {
+                    0 => VoxelAxis::PosiX,
+                    1 => VoxelAxis::NegaX,
+                    2 => VoxelAxis::PosiY,
+                    3 => VoxelAxis::NegaY,
+                    4 => VoxelAxis::PosiZ,
+                    _ => VoxelAxis::NegaZ,
+                };
+                pos = pos.get_neighbor(direction);
+            }
+        }
+    }
+}
+
+/// Round lumps of stone lying on the ground.
+pub struct BoulderFeature {
+    /// How likely a cell is to have a boulder, from 0 to 1.
+    pub chance: f64,
+}
+
+impl Feature for BoulderFeature {
+    fn plan(&self, cell_x: i32, cell_z: i32, rng: &mut FeatureRng, surface: &dyn SurfaceInfo, writes: &mut Vec<FeatureWrite>) {
+        let x = cell_x + rng.range(0, DECORATION_CELL_SIZE);
+        let z = cell_z + rng.range(0, DECORATION_CELL_SIZE);
+        let radius = rng.range(1, 3);
+        if !rng.chance(self.chance) {
+            return;
+        }
+        let ground = match surface.surface_height(x, z) {
+            Some(ground) => ground,
+            None => return,
+        };
+        for dx in -radius..(radius + 1) {
+            for dy in -radius..(radius + 1) {
+                for dz in -radius..(radius + 1) {
+                    if (dx * dx) + (dy * dy) + (dz * dz) <= radius * radius {
+                        writes.push(FeatureWrite { pos: vpos!(x + dx, ground + dy, z + dz), block: STONE, replace: Replace::Air });
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Cell plans we've made recently, oldest first.
+struct PlanCache {
+    plans: HashMap<(i32, i32), Arc<Vec<FeatureWrite>>>,
+    order: VecDeque<(i32, i32)>,
+}
+
+/// Places features on top of generated terrain. See the module documentation for how this stays deterministic.
+pub struct Decorator {
+    seed: u64,
+    features: Vec<Box<dyn Feature>>,
+    plans: Mutex<PlanCache>,
+}
+
+/// Which cell a block coordinate is in, along one axis.
+fn cell_of(coord: i32) -> i32 {
+    let cell = coord / DECORATION_CELL_SIZE;
+    if (coord % DECORATION_CELL_SIZE) < 0 { cell - 1 } else { cell }
+}
+
+impl Decorator {
+    pub fn new(seed: u64) -> Decorator {
+        Decorator {
+            seed: seed,
+            features: Vec::new(),
+            plans: Mutex::new(PlanCache { plans: HashMap::new(), order: VecDeque::new() }),
+        }
+    }
+
+    /// Adds a feature. Features are applied in the order they're added.
+    pub fn with_feature(mut self, feature: Box<dyn Feature>) -> Decorator {
+        self.features.push(feature);
+        self
+    }
+
+    /// Our usual set of features: ore, boulders, then trees.
+    pub fn with_default_features(self) -> Decorator {
+        self.with_feature(Box::new(OreVeinFeature { ore: COAL_ORE, veins_per_cell: 3, length: 6, min_y: -64, max_y: 16 }))
+            .with_feature(Box::new(BoulderFeature { chance: 0.1 }))
+            .with_feature(Box::new(TreeFeature { attempts: 2, chance: 0.5 }))
+    }
+
+    /// Plans every feature in one cell.
+    fn plan_cell(&self, cell: (i32, i32), surface: &dyn SurfaceInfo) -> Vec<FeatureWrite> {
+        let cell_x = cell.0 * DECORATION_CELL_SIZE;
+        let cell_z = cell.1 * DECORATION_CELL_SIZE;
+        let cell_seed = ((cell.0 as u32 as u64) << 32) | (cell.1 as u32 as u64);
+        let mut writes = Vec::new();
+        for (index, feature) in self.features.iter().enumerate() {
+            let mut rng = FeatureRng::new(sub_seed(sub_seed(self.seed, index as u64), cell_seed));
+            feature.plan(cell_x, cell_z, &mut rng, surface, &mut writes);
+        }
+        // Anything past our reach could land in a chunk which never looks at this cell.
+        writes.retain(|write| (write.pos.x >= cell_x - MAX_FEATURE_REACH) && (write.pos.x < cell_x + DECORATION_CELL_SIZE + MAX_FEATURE_REACH)
+                        && (write.pos.z >= cell_z - MAX_FEATURE_REACH) && (write.pos.z < cell_z + DECORATION_CELL_SIZE + MAX_FEATURE_REACH));
+        writes
+    }
+
+    /// Gets a cell's plan from the cache, or plans it.
+    fn get_plan(&self, cell: (i32, i32), surface: &dyn SurfaceInfo) -> Arc<Vec<FeatureWrite>> {
+        if let Some(plan) = self.plans.lock().plans.get(&cell) {
+            return plan.clone();
+        }
+        // Plan without holding the lock, so other workers aren't held up. If two of us plan the same cell, we get the same plan.
+        let plan = Arc::new(self.plan_cell(cell, surface));
+        let mut cache = self.plans.lock();
+        if !cache.plans.contains_key(&cell) {
+            cache.plans.insert(cell, plan.clone());
+            cache.order.push_back(cell);
+            while cache.order.len() > PLAN_CACHE_SIZE {
+                let oldest = cache.order.pop_front().unwrap();
+                cache.plans.remove(&oldest);
+            }
+        }
+        plan
+    }
+
+    /// Applies every feature write which lands in this chunk.
+    pub fn decorate(&self, bounds: VoxelRange<i32>, chunk: &mut Chunk, surface: &dyn SurfaceInfo) {
+        if self.features.is_empty() {
+            return;
+        }
+        let low_x = cell_of(bounds.lower.x - MAX_FEATURE_REACH);
+        let high_x = cell_of(bounds.upper.x - 1 + MAX_FEATURE_REACH);
+        let low_z = cell_of(bounds.lower.z - MAX_FEATURE_REACH);
+        let high_z = cell_of(bounds.upper.z - 1 + MAX_FEATURE_REACH);
+        // Always the same order, so overlapping features settle the same way in every chunk.
+        for cell_z in low_z..(high_z + 1) {
+            for cell_x in low_x..(high_x + 1) {
+                let plan = self.get_plan((cell_x, cell_z), surface);
+                for write in plan.iter() {
+                    let local = match bounds.get_local(write.pos) {
+                        Some(local) => vpos!(local.x as u8, local.y as u8, local.z as u8),
+                        None => continue,
+                    };
+                    let current = chunk.get(local).unwrap();
+                    let allowed = match write.replace {
+                        Replace::Any => true,
+                        Replace::Air => current == AIR,
+                        Replace::Only(block) => current == block,
+                    };
+                    if allowed {
+                        chunk.set(local, write.block).unwrap();
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl GeneratorStage for Decorator {
+    fn kind(&self) -> StageKind { StageKind::Decorator }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        self.decorate(context.bounds, chunk, context.surface);
+    }
+}
+
+#[cfg(test)]
+use world::generators::WorldGenerator;
+#[cfg(test)]
+use world::generators::terraingenerator::terrain_pipeline;
+
+/// Flat ground at y = 0 everywhere, for testing.
+#[cfg(test)]
+struct FlatSurface;
+
+#[cfg(test)]
+impl SurfaceInfo for FlatSurface {
+    fn surface_height(&self, _x: i32, _z: i32) -> Option<i32> { Some(0) }
+    fn surface_block(&self, _x: i32, _z: i32) -> BlockStateID { GRASS }
+}
+
+/// Draws a line of stone along X from the far edge of every cell, well into the next one.
+#[cfg(test)]
+struct LineFeature;
+
+#[cfg(test)]
+impl Feature for LineFeature {
+    fn plan(&self, cell_x: i32, cell_z: i32, _rng: &mut FeatureRng, _surface: &dyn SurfaceInfo, writes: &mut Vec<FeatureWrite>) {
+        for x in (cell_x + 12)..(cell_x + 20) {
+            writes.push(FeatureWrite { pos: vpos!(x, 1, cell_z), block: STONE, replace: Replace::Air });
+        }
+        // Past our reach, so it must be dropped.
+        writes.push(FeatureWrite { pos: vpos!(cell_x - MAX_FEATURE_REACH - 1, 2, cell_z), block: STONE, replace: Replace::Air });
+    }
+}
+
+#[test]
+fn test_decoration_spills_into_neighbors() {
+    let decorator = Decorator::new(0).with_feature(Box::new(LineFeature));
+    // The chunk right after a cell border gets the end of the line which started in the previous cell.
+    let bounds = VoxelRange::new_origin_size(vpos!(16, 0, 0), vpos!(8, 8, 8));
+    let mut chunk = Chunk::new_solid(8, 8, 8, AIR);
+    decorator.decorate(bounds, &mut chunk, &FlatSurface);
+    for x in 0..8u8 {
+        let expected = if x < 4 { STONE } else { AIR };
+        assert_eq!(chunk.get(vpos!(x, 1, 0)).unwrap(), expected);
+    }
+    let plan = decorator.plan_cell((0, 0), &FlatSurface);
+    assert_eq!(plan.len(), 8);
+    assert!(plan.iter().all(|write| write.pos.y == 1));
+}
+
+#[test]
+fn test_decoration_order_independent() {
+    use world::generators::chunk_hash;
+
+    let make_generator = || terrain_pipeline(11).with_stage(Box::new(Decorator::new(11).with_default_features()));
+    let size = vpos!(16, 16, 16);
+    let mut positions = Vec::new();
+    for x in -1..2 {
+        for y in -1..2 {
+            for z in -1..2 {
+                positions.push(vpos!(x * 16, y * 16, z * 16));
+            }
+        }
+    }
+    let forward = make_generator();
+    let expected : Vec<u64> = positions.iter().map(|pos| chunk_hash(&forward.generate(VoxelRange::new_origin_size(*pos, size), 0))).collect();
+    // A fresh generator going the other way around gets exactly the same chunks.
+    let backward = make_generator();
+    for (index, pos) in positions.iter().enumerate().rev() {
+        assert_eq!(chunk_hash(&backward.generate(VoxelRange::new_origin_size(*pos, size), 0)), expected[index]);
+    }
+}
+
+#[test]
+fn test_feature_rng() {
+    let mut a = FeatureRng::new(5);
+    let mut b = FeatureRng::new(5);
+    for _ in 0..100 {
+        let value = a.range(-3, 4);
+        assert!(value >= -3 && value < 4);
+        assert_eq!(value, b.range(-3, 4));
+    }
+    assert_eq!(a.range(2, 2), 2);
+}
@@ -0,0 +1,137 @@
+//! Flat and empty world generators, for testing and building.
+
+use std::error::Error;
+
+use world::generators::{WorldGenerator, block_state_for_name, AIR, STONE, DIRT, GRASS};
+use world::generators::pipeline::{GeneratorStage, StageKind, StageContext};
+
+use voxel::voxelmath::*;
+use voxel::voxelarray::*;
+use voxel::compactvoxelarray::CompactVoxelArray;
+use world::block::{BlockStateID, Chunk};
+
+/// The layers a flat world gets when it isn't given any.
+pub const DEFAULT_FLAT_LAYERS : &str = "stone*60,dirt*3,grass";
+
+/// Base stage which stacks the same layers of blocks everywhere, starting at y = 0 and going up.
+/// Everything below and above the layers is air.
+pub struct FlatGenerator {
+    /// Bottom layer first, one entry per block of height.
+    layers: Vec<BlockStateID>,
+}
+
+impl FlatGenerator {
+    /// Creates a `FlatGenerator` from a list of layers, bottom layer first, one entry per block of height.
+    pub fn new(layers: Vec<BlockStateID>) -> FlatGenerator {
+        FlatGenerator { layers: layers }
+    }
+
+    /// Parses a layer list like "stone*60,dirt*3,grass": block names (or state names, like "log[axis=x]")
+    /// from the bottom up, each optionally followed by how many blocks tall that layer is.
+    pub fn parse(layers: &str) -> Result<FlatGenerator, Box<dyn Error>> {
+        let mut result = Vec::new();
+        for layer in layers.split(',') {
+            let layer = layer.trim();
+            let mut parts = layer.splitn(2, '*');
+            let name = parts.next().unwrap().trim();
+            let count = match parts.next() {
+                Some(count) => count.trim().parse::<usize>().map_err(|_| format!("Bad layer height in \"{}\"", layer))?,
+                None => 1,
+            };
+            let block = block_state_for_name(name).ok_or_else(|| format!("Unknown block \"{}\" in flat world layers", name))?;
+            for _ in 0..count {
+                result.push(block);
+            }
+        }
+        if result.is_empty() {
+            return Err("A flat world needs at least one layer".into());
+        }
+        Ok(FlatGenerator::new(result))
+    }
+
+    /// The block at height y.
+    pub fn block_at(&self, y: i32) -> BlockStateID {
+        if y < 0 {
+            return AIR;
+        }
+        match self.layers.get(y as usize) {
+            Some(block) => *block,
+            None => AIR,
+        }
+    }
+}
+
+impl Default for FlatGenerator {
+    fn default() -> Self {
+        FlatGenerator::parse(DEFAULT_FLAT_LAYERS).unwrap()
+    }
+}
+
+impl GeneratorStage for FlatGenerator {
+    fn kind(&self) -> StageKind { StageKind::Base }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        let bounds = context.bounds;
+        let size = bounds.get_size();
+
+        let num_elements = (size.x * size.y * size.z) as usize;
+        let mut data : Vec<BlockStateID> = vec![AIR; num_elements];
+        for y in 0..size.y {
+            let block = self.block_at(bounds.lower.y + y);
+            for x in 0..size.x {
+                for z in 0..size.z {
+                    data[xyz_to_i(x as usize, y as usize, z as usize,
+                                    size.x as usize, size.y as usize, size.z as usize)] = block;
+                }
+            }
+        }
+        *chunk = CompactVoxelArray::load_new(size.x as u8, size.y as u8, size.z as u8, data);
+    }
+
+    fn surface(&self, _x: i32, _z: i32, _so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> {
+        self.layers.iter().rposition(|block| *block != AIR).map(|top| (top as i32, self.layers[top]))
+    }
+}
+
+/// World generator which makes nothing but air.
+pub struct VoidGenerator;
+
+impl WorldGenerator for VoidGenerator {
+    fn generate(&self, bounds: VoxelRange<i32>, _dimension_id: u32) -> Chunk {
+        let size = bounds.get_size();
+        Chunk::new_solid(size.x as u8, size.y as u8, size.z as u8, AIR)
+    }
+}
+
+#[test]
+fn test_flat_layers() {
+    let generator = FlatGenerator::parse("stone*2, dirt ,grass*1").unwrap();
+    assert_eq!(generator.layers, vec![STONE, STONE, DIRT, GRASS]);
+    assert_eq!(generator.block_at(-1), AIR);
+    assert_eq!(generator.block_at(1), STONE);
+    assert_eq!(generator.block_at(3), GRASS);
+    assert_eq!(generator.block_at(4), AIR);
+
+    assert!(FlatGenerator::parse("").is_err());
+    assert!(FlatGenerator::parse("stone*lots").is_err());
+    assert!(FlatGenerator::parse("cheese*3").is_err());
+    assert_eq!(FlatGenerator::default().layers.len(), 64);
+}
+
+#[test]
+fn test_flat_generate() {
+    use voxel::voxelstorage::*;
+    use world::generators::pipeline::GeneratorPipeline;
+
+    let generator = GeneratorPipeline::new(0).with_stage(Box::new(FlatGenerator::parse("stone*20,grass").unwrap()));
+    // Entirely inside the stone.
+    let chunk = generator.generate(VoxelRange::new_origin_size(vpos!(32, 0, -16), vpos!(16, 16, 16)), 0);
+    assert_eq!(chunk.uniform_value(), Some(&STONE));
+    let chunk = generator.generate(VoxelRange::new_origin_size(vpos!(32, 16, -16), vpos!(16, 16, 16)), 0);
+    assert_eq!(chunk.get(vpos!(3, 3, 9)).unwrap(), STONE);
+    assert_eq!(chunk.get(vpos!(3, 4, 9)).unwrap(), GRASS);
+    assert_eq!(chunk.get(vpos!(3, 5, 9)).unwrap(), AIR);
+
+    let chunk = VoidGenerator.generate(VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(16, 16, 16)), 0);
+    assert_eq!(chunk.uniform_value(), Some(&AIR));
+}
@@ -0,0 +1,59 @@
+//! Fractal (multi-octave) noise built out of Perlin noise.
+
+use noise::{NoiseFn, Perlin, Seedable};
+use world::generators::{sub_seed, noise_seed};
+
+/// Several octaves of Perlin noise added together, each twice the frequency and half the amplitude of the last.
+/// Every octave has its own seed, so the octaves don't line up with each other.
+pub struct FractalNoise {
+    octaves: Vec<Perlin>,
+    frequency: f64,
+}
+
+impl FractalNoise {
+    /// Creates fractal noise whose first octave has the given frequency (in cycles per block).
+    /// layer picks this noise's sub-seed, so each FractalNoise in a generator should get a different one.
+    pub fn new(seed: u64, layer: u64, octave_count: usize, frequency: f64) -> FractalNoise {
+        let layer_seed = sub_seed(seed, layer);
+        FractalNoise {
+            octaves: (0..octave_count).map(|octave| Perlin::new().set_seed(noise_seed(layer_seed, octave as u64))).collect(),
+            frequency: frequency,
+        }
+    }
+
+    /// Samples the noise in 2D. Results are roughly between -1 and 1.
+    pub fn get(&self, x: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut amplitude_sum = 0.0;
+        let mut frequency = self.frequency;
+        for octave in self.octaves.iter() {
+            // The half-block offset keeps us from sampling exactly on the noise lattice, where Perlin noise is always 0.
+            total += octave.get([(x + 0.5) * frequency, (z + 0.5) * frequency]) * amplitude;
+            amplitude_sum += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        if amplitude_sum > 0.0 { total / amplitude_sum } else { 0.0 }
+    }
+
+    /// Samples the noise in 3D. Results are roughly between -1 and 1.
+    pub fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut amplitude_sum = 0.0;
+        let mut frequency = self.frequency;
+        for octave in self.octaves.iter() {
+            total += octave.get([(x + 0.5) * frequency, (y + 0.5) * frequency, (z + 0.5) * frequency]) * amplitude;
+            amplitude_sum += amplitude;
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        if amplitude_sum > 0.0 { total / amplitude_sum } else { 0.0 }
+    }
+
+    /// Like get(), but scaled to roughly 0 to 1.
+    pub fn get_normalized(&self, x: f64, z: f64) -> f64 {
+        (self.get(x, z) / 2.0 + 0.5).max(0.0).min(1.0)
+    }
+}
@@ -0,0 +1,135 @@
+//! Looks up world generators by name, so they can be picked on the command line and in world config.
+
+use std::collections::HashMap;
+use std::error::Error;
+
+use world::generators::*;
+use world::generators::pipeline::GeneratorPipeline;
+use world::generators::terraingenerator::terrain_pipeline;
+use world::generators::cavecarver::{CaveCarver, CaveSettings};
+use world::generators::decoration::Decorator;
+use world::generators::flatgenerator::{FlatGenerator, VoidGenerator, DEFAULT_FLAT_LAYERS};
+use world::generators::heightmapgenerator::{HeightmapGenerator, heightmap_pipeline, DEFAULT_MAX_HEIGHT};
+
+/// Builds a generator from a seed and an options string, whose meaning is up to the generator.
+pub type GeneratorFactory = fn(seed: u64, options: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>>;
+
+/// Every kind of world generator we know how to build, by name.
+///
+/// Generators are picked with a spec string: a name, optionally followed by a colon and options, e.g.
+/// "terrain", "perlin:0.003", "flat:stone*60,dirt*3,grass", "void" or "heightmap:maps/island.png,48".
+pub struct GeneratorRegistry {
+    factories: HashMap<String, GeneratorFactory>,
+}
+
+fn no_options(name: &str, options: &str) -> Result<(), Box<dyn Error>> {
+    if options.is_empty() { Ok(()) } else { Err(format!("The {} generator doesn't take any options", name).into()) }
+}
+
+fn make_terrain(seed: u64, options: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+    no_options("terrain", options)?;
+    Ok(Box::new(terrain_pipeline(seed)
+                    .with_stage(Box::new(CaveCarver::new(seed, CaveSettings::default())))
+                    .with_stage(Box::new(Decorator::new(seed).with_default_features()))))
+}
+
+/// Options: the horizontal scale, if not the default.
+fn make_perlin(seed: u64, options: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+    if options.is_empty() {
+        return Ok(Box::new(PerlinGenerator::new(seed)));
+    }
+    let scale = options.parse::<f64>().map_err(|_| format!("Bad perlin generator scale \"{}\"", options))?;
+    Ok(Box::new(PerlinGenerator::with_scale(seed, scale)))
+}
+
+/// Options: the layer list, see FlatGenerator::parse().
+fn make_flat(seed: u64, options: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+    let layers = if options.is_empty() { DEFAULT_FLAT_LAYERS } else { options };
+    Ok(Box::new(GeneratorPipeline::new(seed).with_stage(Box::new(FlatGenerator::parse(layers)?))))
+}
+
+fn make_void(_seed: u64, options: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+    no_options("void", options)?;
+    Ok(Box::new(VoidGenerator))
+}
+
+/// Options: the image's path, optionally followed by a comma and the height of a white pixel.
+fn make_heightmap(seed: u64, options: &str) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+    if options.is_empty() {
+        return Err("The heightmap generator needs an image, e.g. heightmap:map.png".into());
+    }
+    let mut parts = options.rsplitn(2, ',');
+    let last = parts.next().unwrap();
+    let (path, max_height) = match (parts.next(), last.trim().parse::<f64>()) {
+        (Some(path), Ok(max_height)) => (path, max_height),
+        _ => (options, DEFAULT_MAX_HEIGHT),
+    };
+    Ok(Box::new(heightmap_pipeline(seed, HeightmapGenerator::load(path, max_height)?)))
+}
+
+impl GeneratorRegistry {
+    /// A registry with no generators in it.
+    pub fn new() -> GeneratorRegistry {
+        GeneratorRegistry { factories: HashMap::new() }
+    }
+
+    /// Adds a generator, replacing any generator which already had that name.
+    pub fn register(&mut self, name: &str, factory: GeneratorFactory) {
+        self.factories.insert(name.to_owned(), factory);
+    }
+
+    /// Names of every generator we can build, in alphabetical order.
+    pub fn names(&self) -> Vec<String> {
+        let mut names : Vec<String> = self.factories.keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    /// Builds the generator described by spec. See GeneratorRegistry for what a spec looks like.
+    pub fn create(&self, spec: &str, seed: u64) -> Result<Box<dyn WorldGenerator>, Box<dyn Error>> {
+        let mut parts = spec.splitn(2, ':');
+        let name = parts.next().unwrap().trim();
+        let options = parts.next().unwrap_or("").trim();
+        match self.factories.get(name) {
+            Some(factory) => factory(seed, options),
+            None => Err(format!("Unknown world generator \"{}\". Known generators are: {}", name, self.names().join(", ")).into()),
+        }
+    }
+}
+
+impl Default for GeneratorRegistry {
+    /// A registry with all of our built-in generators.
+    fn default() -> Self {
+        let mut registry = GeneratorRegistry::new();
+        registry.register("terrain", make_terrain);
+        registry.register("perlin", make_perlin);
+        registry.register("flat", make_flat);
+        registry.register("void", make_void);
+        registry.register("heightmap", make_heightmap);
+        registry
+    }
+}
+
+#[test]
+fn test_generator_registry() {
+    use voxel::voxelmath::*;
+    use voxel::voxelstorage::*;
+
+    let registry = GeneratorRegistry::default();
+    assert_eq!(registry.names(), vec!["flat", "heightmap", "perlin", "terrain", "void"]);
+    let bounds = VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(4, 4, 4));
+
+    let flat = registry.create("flat: dirt*2,grass", 0).unwrap().generate(bounds, 0);
+    assert_eq!(flat.get(vpos!(0, 1, 0)).unwrap(), DIRT);
+    assert_eq!(flat.get(vpos!(0, 2, 0)).unwrap(), GRASS);
+    assert_eq!(flat.get(vpos!(0, 3, 0)).unwrap(), AIR);
+    assert_eq!(registry.create("void", 0).unwrap().generate(bounds, 0).uniform_value(), Some(&AIR));
+    assert!(registry.create("terrain", 1).is_ok());
+    assert!(registry.create("perlin:0.01", 1).is_ok());
+
+    assert!(registry.create("marble", 0).is_err());
+    assert!(registry.create("void:please", 0).is_err());
+    assert!(registry.create("perlin:big", 0).is_err());
+    assert!(registry.create("heightmap", 0).is_err());
+    assert!(registry.create("heightmap:does/not/exist.png,20", 0).is_err());
+}
@@ -0,0 +1,118 @@
+//! World generator which reads its terrain from a grayscale image.
+
+use std::error::Error;
+use std::path::Path;
+
+use world::generators::{AIR, STONE, DIRT, GRASS};
+use world::generators::pipeline::*;
+
+use voxel::voxelmath::*;
+use voxel::voxelarray::*;
+use voxel::compactvoxelarray::CompactVoxelArray;
+use world::block::{BlockStateID, Chunk};
+
+/// How tall the terrain gets at a white pixel, if we aren't told otherwise.
+pub const DEFAULT_MAX_HEIGHT : f64 = 32.0;
+/// How many blocks of dirt heightmap_pipeline() puts under the grass.
+const DIRT_DEPTH : i32 = 3;
+
+/// Base stage which turns a grayscale image into stone terrain. Pixel (x, y) of the image is the column at
+/// (x, z) in the world, and the brighter it is, the taller the ground: black is y = 0 and white is max_height.
+/// Past the edges of the image, the edge pixels carry on forever.
+pub struct HeightmapGenerator {
+    width: u32,
+    depth: u32,
+    /// One brightness per pixel, row by row.
+    heights: Vec<u8>,
+    max_height: f64,
+}
+
+impl HeightmapGenerator {
+    /// Creates a new `HeightmapGenerator` from a width * depth grid of brightnesses, row by row.
+    pub fn new(width: u32, depth: u32, heights: Vec<u8>, max_height: f64) -> HeightmapGenerator {
+        assert_eq!(heights.len(), (width * depth) as usize);
+        assert!(width > 0 && depth > 0);
+        HeightmapGenerator {
+            width: width,
+            depth: depth,
+            heights: heights,
+            max_height: max_height,
+        }
+    }
+
+    /// Loads a heightmap from an image file. Color images are converted to grayscale.
+    pub fn load<P: AsRef<Path>>(path: P, max_height: f64) -> Result<HeightmapGenerator, Box<dyn Error>> {
+        let image = ::image::open(path.as_ref())?.to_luma();
+        let (width, depth) = image.dimensions();
+        if width == 0 || depth == 0 {
+            return Err(format!("Heightmap {} is empty", path.as_ref().display()).into());
+        }
+        Ok(HeightmapGenerator::new(width, depth, image.into_raw(), max_height))
+    }
+
+    /// Y of the highest solid block in the column at (x, z).
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        let x = x.max(0).min(self.width as i32 - 1) as u32;
+        let z = z.max(0).min(self.depth as i32 - 1) as u32;
+        let brightness = self.heights[(z * self.width + x) as usize] as f64 / 255.0;
+        (brightness * self.max_height).round() as i32
+    }
+}
+
+impl GeneratorStage for HeightmapGenerator {
+    fn kind(&self) -> StageKind { StageKind::Base }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        let bounds = context.bounds;
+        let size = bounds.get_size();
+
+        let num_elements = (size.x * size.y * size.z) as usize;
+        let mut data : Vec<BlockStateID> = vec![AIR; num_elements];
+        for x in 0..size.x {
+            for z in 0..size.z {
+                let height = self.height(bounds.lower.x + x, bounds.lower.z + z);
+                for y in 0..size.y {
+                    if bounds.lower.y + y <= height {
+                        data[xyz_to_i(x as usize, y as usize, z as usize,
+                                        size.x as usize, size.y as usize, size.z as usize)] = STONE;
+                    }
+                }
+            }
+        }
+        *chunk = CompactVoxelArray::load_new(size.x as u8, size.y as u8, size.z as u8, data);
+    }
+
+    fn surface(&self, x: i32, z: i32, _so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> {
+        Some((self.height(x, z), STONE))
+    }
+}
+
+/// A heightmap covered in grass, with a few blocks of dirt under it.
+pub fn heightmap_pipeline(seed: u64, heightmap: HeightmapGenerator) -> GeneratorPipeline {
+    GeneratorPipeline::new(seed)
+        .with_stage(Box::new(heightmap))
+        .with_stage(Box::new(SurfaceLayerStage { top: GRASS, filler: DIRT, filler_depth: DIRT_DEPTH }))
+}
+
+#[test]
+fn test_heightmap() {
+    use voxel::voxelstorage::*;
+    use world::generators::WorldGenerator;
+
+    // 2 by 2: black, white / gray, black
+    let generator = HeightmapGenerator::new(2, 2, vec![0, 255, 128, 0], 10.0);
+    assert_eq!(generator.height(0, 0), 0);
+    assert_eq!(generator.height(1, 0), 10);
+    assert_eq!(generator.height(0, 1), 5);
+    // Off the edges.
+    assert_eq!(generator.height(50, -50), 10);
+    assert_eq!(generator.height(-3, 7), 5);
+
+    let chunk = heightmap_pipeline(0, generator).generate(VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(2, 12, 2)), 0);
+    assert_eq!(chunk.get(vpos!(1, 10, 0)).unwrap(), GRASS);
+    assert_eq!(chunk.get(vpos!(1, 11, 0)).unwrap(), AIR);
+    assert_eq!(chunk.get(vpos!(1, 7, 0)).unwrap(), DIRT);
+    assert_eq!(chunk.get(vpos!(1, 6, 0)).unwrap(), STONE);
+    assert_eq!(chunk.get(vpos!(0, 0, 0)).unwrap(), GRASS);
+    assert_eq!(chunk.get(vpos!(0, 1, 0)).unwrap(), AIR);
+}
@@ -1,15 +1,75 @@
 //! World generator types.
 
 pub mod perlingenerator;
+pub mod terraingenerator;
+pub mod fractalnoise;
+pub mod cavecarver;
+pub mod decoration;
+pub mod flatgenerator;
+pub mod heightmapgenerator;
+pub mod generatorregistry;
+pub mod pipeline;
 
 pub use self::perlingenerator::PerlinGenerator;
+pub use self::terraingenerator::TerrainGenerator;
+pub use self::generatorregistry::GeneratorRegistry;
+pub use self::pipeline::GeneratorPipeline;
 
 use voxel::voxelmath::*;
 
-use world::block::Chunk;
+use world::block::{BlockStateID, Chunk, MASTER_BLOCK_REGISTRY};
 
-/// Trait for world generators.
-pub trait WorldGenerator {
+/// The built-in blocks our generators place, in their default states.
+pub use world::block::{AIR, STONE, DIRT, GRASS, LOG, LEAVES, COAL_ORE};
+
+/// Looks up a block state by its name (see BlockRegistry::state_for_name()), for generator settings.
+pub fn block_state_for_name(name: &str) -> Option<BlockStateID> {
+    MASTER_BLOCK_REGISTRY.read().state_for_name(name)
+}
+
+/// Trait for world generators. Generators are shared between chunk generation threads.
+/// A generator must be built from a world seed, and generate() must only depend on that seed
+/// and its arguments, so a chunk comes out the same no matter when or on which thread it's generated.
+pub trait WorldGenerator : Send + Sync {
     /// Generates a chunk with this generator.
     fn generate(&self, bounds: VoxelRange<i32>, dimension_id: u32) -> Chunk;
+}
+
+/// Derives a seed for one part of a generator (a noise layer, a dimension...) from a parent seed.
+/// Each layer gets a different, well-mixed seed, so layers never end up sharing noise.
+/// This is the SplitMix64 generator's output for step number layer + 1 from the parent seed.
+pub fn sub_seed(seed: u64, layer: u64) -> u64 {
+    let mut z = seed.wrapping_add(layer.wrapping_add(1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Like sub_seed(), but for noise functions which only take a 32-bit seed.
+pub fn noise_seed(seed: u64, layer: u64) -> u32 {
+    (sub_seed(seed, layer) >> 32) as u32
+}
+
+/// Hash of every voxel in a chunk, to check that generation is deterministic.
+/// IDs are hashed as little-endian bytes so the same chunk hashes the same on every machine.
+#[cfg(test)]
+pub fn chunk_hash(chunk: &Chunk) -> u64 {
+    extern crate fnv;
+    use std::hash::Hasher;
+    use voxel::voxelstorage::*;
+
+    let mut hasher = fnv::FnvHasher::default();
+    for pos in chunk.get_bounds() {
+        hasher.write(&chunk.get(pos).unwrap().to_le_bytes());
+    }
+    hasher.finish()
+}
+
+#[test]
+fn test_sub_seed() {
+    // The first output of SplitMix64 seeded with 0, from the reference implementation.
+    assert_eq!(sub_seed(0, 0), 0xE220_A839_7B1D_CDAF);
+    assert_ne!(sub_seed(0, 0), sub_seed(0, 1));
+    assert_ne!(sub_seed(0, 1), sub_seed(1, 1));
+    assert_eq!(sub_seed(12345, 3), sub_seed(12345, 3));
 }
\ No newline at end of file
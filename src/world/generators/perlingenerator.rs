@@ -2,11 +2,12 @@
 
 
 use noise::{NoiseFn, Perlin, Seedable};
-use world::generators::WorldGenerator;
+use world::generators::{WorldGenerator, noise_seed, STONE, DIRT, GRASS};
 
 use voxel::voxelmath::*;
 use voxel::voxelarray::*;
-use world::block::{BlockID, Chunk};
+use voxel::compactvoxelarray::CompactVoxelArray;
+use world::block::{BlockStateID, Chunk};
 
 /// Simple world generator using perlin noise.
 pub struct PerlinGenerator {
@@ -18,18 +19,28 @@ pub struct PerlinGenerator {
 }
 
 
+/// Sub-seed layers for each of our noise functions.
+const HEIGHT_LAYER : u64 = 0;
+const BLOCK_TYPE_LAYER : u64 = 1;
+
+/// Blocks the block type noise picks between.
+const BLOCK_TYPES : [BlockStateID; 3] = [STONE, DIRT, GRASS];
+
 impl PerlinGenerator {
     /// Creates a new `PerlinGenerator`
-    pub fn new() -> PerlinGenerator {
-        let perlin = Perlin::new();
-        perlin.set_seed(1);
+    pub fn new(seed: u64) -> PerlinGenerator {
+        PerlinGenerator::with_scale(seed, 0.008126)
+    }
 
-        let block_type_noise = Perlin::new();
-        perlin.set_seed(50);
+    /// Creates a new `PerlinGenerator` with a different horizontal scale. Smaller scales make wider, gentler hills.
+    pub fn with_scale(seed: u64, scale: f64) -> PerlinGenerator {
+        let perlin = Perlin::new().set_seed(noise_seed(seed, HEIGHT_LAYER));
+
+        let block_type_noise = Perlin::new().set_seed(noise_seed(seed, BLOCK_TYPE_LAYER));
 
         PerlinGenerator {
             perlin,
-            scale: 0.008126,
+            scale,
             offset: 0.26378,
             block_type_noise,
             block_type_scale: 0.063647,
@@ -43,7 +54,7 @@ impl WorldGenerator for PerlinGenerator {
         let size = bounds.get_size();
         
         let num_elements = (size.x * size.y * size.z) as usize;
-        let mut data : Vec<BlockID> = Vec::with_capacity(num_elements);
+        let mut data : Vec<BlockStateID> = Vec::with_capacity(num_elements);
         for _ in 0..num_elements { data.push(0); }
 
         for x in 0..size.x {
@@ -55,7 +66,7 @@ impl WorldGenerator for PerlinGenerator {
                     if (bounds.lower.y + y) as f32 <= height_abs {
                         let block_type_val = self.block_type_noise.get([((bounds.lower.x + x) as f64) * self.block_type_scale, 
                                                                         ((bounds.lower.z + z) as f64) * self.block_type_scale]) / 2.0 + 0.5;
-                        let block_id = ((block_type_val * 3.0) + 1.0) as BlockID;
+                        let block_id = BLOCK_TYPES[((block_type_val * 3.0) as usize).min(BLOCK_TYPES.len() - 1)];
 
                         data[xyz_to_i(x as usize, y as usize, z as usize, 
                                         size.x as usize, size.y as usize, size.z as usize)] = block_id;
@@ -63,6 +74,25 @@ impl WorldGenerator for PerlinGenerator {
                 }
             }
         }
-        VoxelArray::load_new(size.x as u8, size.y as u8, size.z as u8, data)
+        CompactVoxelArray::load_new(size.x as u8, size.y as u8, size.z as u8, data)
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_perlin_deterministic() {
+    use std::sync::Arc;
+    use std::thread;
+    use world::generators::chunk_hash;
+
+    let bounds = VoxelRange::new_origin_size(vpos!(-16, 0, 48), vpos!(16, 16, 16));
+    // Pinned, so worlds generated on another machine or by an older build still line up.
+    let expected = 0x46B2_33A8_28BA_93F5;
+    assert_eq!(chunk_hash(&PerlinGenerator::new(1234).generate(bounds, 0)), expected);
+    // A fresh generator with the same seed, after generating other chunks, on another thread.
+    let generator = Arc::new(PerlinGenerator::new(1234));
+    generator.generate(VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(16, 16, 16)), 0);
+    let hash = thread::spawn(move || chunk_hash(&generator.generate(bounds, 0))).join().unwrap();
+    assert_eq!(hash, expected);
+
+    assert_ne!(chunk_hash(&PerlinGenerator::new(1235).generate(bounds, 0)), expected);
+}
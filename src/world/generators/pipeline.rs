@@ -0,0 +1,247 @@
+//! World generators built out of stages.
+//!
+//! A GeneratorPipeline starts every chunk out as air and hands it to each of its stages in turn:
+//! first whatever lays down the base shape of the land, then stages which replace its surface,
+//! carvers, decorators and finally post-processing. Stages can be mixed freely, so (for example)
+//! the same caves and trees can be put on top of noise terrain or a heightmap.
+
+use voxel::voxelmath::*;
+use voxel::voxelstorage::*;
+use world::block::{BlockStateID, Chunk};
+use world::generators::{WorldGenerator, AIR};
+
+/// What a stage does, which decides when it runs. Stages run in this order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StageKind {
+    /// Lays down the shape of the land, usually as stone.
+    Base,
+    /// Replaces the top of the land with grass, dirt and so on.
+    Surface,
+    /// Hollows out caves and the like.
+    Carver,
+    /// Places trees, ore and other features.
+    Decorator,
+    /// Anything which has to see the finished chunk.
+    PostProcess,
+}
+
+/// What a stage can find out about the terrain so far, one column at a time.
+pub trait SurfaceInfo {
+    /// Y of the highest solid block of the column at (x, z), or None if there's no ground to stand on.
+    fn surface_height(&self, x: i32, z: i32) -> Option<i32>;
+    /// The block at the top of the column at (x, z).
+    fn surface_block(&self, x: i32, z: i32) -> BlockStateID;
+}
+
+/// Everything a stage gets to know about the chunk it's working on.
+pub struct StageContext<'a> {
+    /// The seed of the pipeline this stage is in.
+    pub seed: u64,
+    /// Where the chunk is in the world.
+    pub bounds: VoxelRange<i32>,
+    pub dimension_id: u32,
+    /// The surface of the terrain as it stands after every stage before this one.
+    /// Unlike the chunk, this covers the whole world, so stages can look past the edges of their chunk.
+    pub surface: &'a dyn SurfaceInfo,
+}
+
+/// One step in a GeneratorPipeline. Like a WorldGenerator, a stage must only depend on its own settings and
+/// what it's given, so chunks always come out the same.
+pub trait GeneratorStage : Send + Sync {
+    fn kind(&self) -> StageKind;
+
+    /// Does this stage's work on a chunk.
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk);
+
+    /// Given the height and top block of the column at (x, z) after the stages before this one,
+    /// returns what they are after this stage. By default, stages leave the surface alone.
+    fn surface(&self, _x: i32, _z: i32, so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> { so_far }
+}
+
+/// The surface of the terrain after some of a pipeline's stages.
+struct StagesSurface<'a> {
+    stages: &'a [Box<dyn GeneratorStage>],
+}
+
+impl<'a> StagesSurface<'a> {
+    fn top(&self, x: i32, z: i32) -> Option<(i32, BlockStateID)> {
+        self.stages.iter().fold(None, |so_far, stage| stage.surface(x, z, so_far))
+    }
+}
+
+impl<'a> SurfaceInfo for StagesSurface<'a> {
+    fn surface_height(&self, x: i32, z: i32) -> Option<i32> { self.top(x, z).map(|(height, _)| height) }
+    fn surface_block(&self, x: i32, z: i32) -> BlockStateID { self.top(x, z).map(|(_, block)| block).unwrap_or(AIR) }
+}
+
+/// A world generator made out of stages, run in order of their StageKind.
+pub struct GeneratorPipeline {
+    seed: u64,
+    stages: Vec<Box<dyn GeneratorStage>>,
+}
+
+impl GeneratorPipeline {
+    /// A pipeline with no stages, which generates nothing but air.
+    pub fn new(seed: u64) -> GeneratorPipeline {
+        GeneratorPipeline { seed: seed, stages: Vec::new() }
+    }
+
+    /// Adds a stage. It runs after every stage of an earlier kind, and after any stage of the same kind which was added before it.
+    pub fn with_stage(mut self, stage: Box<dyn GeneratorStage>) -> GeneratorPipeline {
+        let kind = stage.kind();
+        let index = self.stages.iter().position(|existing| existing.kind() > kind).unwrap_or(self.stages.len());
+        self.stages.insert(index, stage);
+        self
+    }
+
+    /// Our stages' kinds, in the order they run.
+    pub fn stage_kinds(&self) -> Vec<StageKind> {
+        self.stages.iter().map(|stage| stage.kind()).collect()
+    }
+}
+
+impl WorldGenerator for GeneratorPipeline {
+    fn generate(&self, bounds: VoxelRange<i32>, dimension_id: u32) -> Chunk {
+        let size = bounds.get_size();
+        let mut chunk = Chunk::new_solid(size.x as u8, size.y as u8, size.z as u8, AIR);
+        for index in 0..self.stages.len() {
+            let surface = StagesSurface { stages: &self.stages[..index] };
+            let context = StageContext { seed: self.seed, bounds: bounds, dimension_id: dimension_id, surface: &surface };
+            self.stages[index].apply(&context, &mut chunk);
+        }
+        chunk
+    }
+}
+
+/// Surface stage which puts one block on top of the land and a few of another underneath it,
+/// like grass over dirt. Only solid blocks are replaced.
+pub struct SurfaceLayerStage {
+    pub top: BlockStateID,
+    pub filler: BlockStateID,
+    /// How many blocks of filler go under the top block.
+    pub filler_depth: i32,
+}
+
+impl GeneratorStage for SurfaceLayerStage {
+    fn kind(&self) -> StageKind { StageKind::Surface }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        let bounds = context.bounds;
+        for x in bounds.lower.x..bounds.upper.x {
+            for z in bounds.lower.z..bounds.upper.z {
+                let height = match context.surface.surface_height(x, z) {
+                    Some(height) => height,
+                    None => continue,
+                };
+                for depth in 0..(self.filler_depth + 1) {
+                    let block = if depth == 0 { self.top } else { self.filler };
+                    replace_solid(bounds, chunk, vpos!(x, height - depth, z), block);
+                }
+            }
+        }
+    }
+
+    fn surface(&self, _x: i32, _z: i32, so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> {
+        so_far.map(|(height, _)| (height, self.top))
+    }
+}
+
+/// Sets the block at a world position to block, if that position is in this chunk and isn't air.
+pub fn replace_solid(bounds: VoxelRange<i32>, chunk: &mut Chunk, pos: VoxelPos<i32>, block: BlockStateID) {
+    if let Some(local) = bounds.get_local(pos) {
+        let local = vpos!(local.x as u8, local.y as u8, local.z as u8);
+        if chunk.get(local).unwrap() != AIR {
+            chunk.set(local, block).unwrap();
+        }
+    }
+}
+
+/// Test stage which fills its chunk with `to` wherever it finds `from`.
+#[cfg(test)]
+struct ReplaceStage {
+    kind: StageKind,
+    from: BlockStateID,
+    to: BlockStateID,
+}
+
+#[cfg(test)]
+impl GeneratorStage for ReplaceStage {
+    fn kind(&self) -> StageKind { self.kind }
+
+    fn apply(&self, _context: &StageContext, chunk: &mut Chunk) {
+        for pos in chunk.get_bounds() {
+            if chunk.get(pos).unwrap() == self.from {
+                chunk.set(pos, self.to).unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_pipeline_stage_order() {
+    let stage = |kind, from, to| Box::new(ReplaceStage { kind: kind, from: from, to: to });
+    // Added out of order. Each stage only does anything if the one before it already ran,
+    // so the chunk ends up as 5 only if they run Base, Surface, Surface, Carver, PostProcess.
+    let pipeline = GeneratorPipeline::new(0)
+        .with_stage(stage(StageKind::PostProcess, 4, 5))
+        .with_stage(stage(StageKind::Surface, 1, 2))
+        .with_stage(stage(StageKind::Carver, 3, 4))
+        .with_stage(stage(StageKind::Base, AIR, 1))
+        .with_stage(stage(StageKind::Surface, 2, 3));
+    assert_eq!(pipeline.stage_kinds(), vec![StageKind::Base, StageKind::Surface, StageKind::Surface,
+                                            StageKind::Carver, StageKind::PostProcess]);
+    let chunk = pipeline.generate(VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(4, 4, 4)), 0);
+    for pos in chunk.get_bounds() {
+        assert_eq!(chunk.get(pos).unwrap(), 5);
+    }
+
+    let empty = GeneratorPipeline::new(0).generate(VoxelRange::new_origin_size(vpos!(0, 0, 0), vpos!(4, 4, 4)), 0);
+    assert_eq!(empty.uniform_value(), Some(&AIR));
+}
+
+/// Test base stage: ground at y = x, and records what it was given.
+#[cfg(test)]
+struct SlopeStage;
+
+#[cfg(test)]
+impl GeneratorStage for SlopeStage {
+    fn kind(&self) -> StageKind { StageKind::Base }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        assert_eq!(context.seed, 1234);
+        assert_eq!(context.dimension_id, 7);
+        // Nothing ran before us.
+        assert_eq!(context.surface.surface_height(0, 0), None);
+        for pos in context.bounds {
+            if pos.y <= pos.x {
+                let local = pos - context.bounds.lower;
+                chunk.set(vpos!(local.x as u8, local.y as u8, local.z as u8), 1).unwrap();
+            }
+        }
+    }
+
+    fn surface(&self, x: i32, _z: i32, _so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> { Some((x, 1)) }
+}
+
+#[test]
+fn test_pipeline_surface() {
+    use world::generators::chunk_hash;
+
+    let make_pipeline = || GeneratorPipeline::new(1234)
+        .with_stage(Box::new(SurfaceLayerStage { top: 3, filler: 2, filler_depth: 2 }))
+        .with_stage(Box::new(SlopeStage));
+    let bounds = VoxelRange::new_origin_size(vpos!(2, 0, 0), vpos!(4, 8, 1));
+    let chunk = make_pipeline().generate(bounds, 7);
+    for x in 0..4u8 {
+        let height = x + 2;
+        assert_eq!(chunk.get(vpos!(x, height, 0)).unwrap(), 3);
+        assert_eq!(chunk.get(vpos!(x, height - 1, 0)).unwrap(), 2);
+        assert_eq!(chunk.get(vpos!(x, height - 2, 0)).unwrap(), 2);
+        assert_eq!(chunk.get(vpos!(x, height + 1, 0)).unwrap(), AIR);
+        if height >= 3 {
+            assert_eq!(chunk.get(vpos!(x, height - 3, 0)).unwrap(), 1);
+        }
+    }
+    // Same pipeline, same chunk.
+    assert_eq!(chunk_hash(&make_pipeline().generate(bounds, 7)), chunk_hash(&chunk));
+}
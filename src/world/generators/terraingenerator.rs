@@ -0,0 +1,293 @@
+//! World generator with fractal terrain and biomes.
+
+use world::generators::{AIR, STONE, DIRT, GRASS};
+use world::generators::fractalnoise::FractalNoise;
+use world::generators::pipeline::*;
+
+use voxel::voxelmath::*;
+use voxel::voxelarray::*;
+use voxel::compactvoxelarray::CompactVoxelArray;
+use world::block::{BlockStateID, Chunk};
+
+/// Sub-seed layers for each of our noise maps.
+const CONTINENT_LAYER : u64 = 0;
+const HILL_LAYER : u64 = 1;
+const DETAIL_LAYER : u64 = 2;
+const TEMPERATURE_LAYER : u64 = 3;
+const HUMIDITY_LAYER : u64 = 4;
+
+/// Height of the terrain where every noise map is at its middle value.
+const BASE_HEIGHT : f64 = 8.0;
+const CONTINENT_AMPLITUDE : f64 = 24.0;
+const HILL_AMPLITUDE : f64 = 12.0;
+const DETAIL_AMPLITUDE : f64 = 2.0;
+/// How much colder it gets for every block above BASE_HEIGHT.
+const TEMPERATURE_LAPSE : f64 = 1.0 / 96.0;
+/// The most dirt any biome has under its surface.
+const MAX_DIRT_DEPTH : i32 = 6;
+
+/// The kind of land a column of terrain is. Picked from the temperature and humidity maps.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Biome {
+    /// Cold: bare stone.
+    Tundra,
+    /// Temperate: grass over a few blocks of dirt.
+    Plains,
+    /// Wet: grass over deep, soft dirt.
+    Marsh,
+    /// Hot and dry: the grass is gone, leaving dirt.
+    Badlands,
+}
+
+impl Biome {
+    /// Picks a biome from a temperature and humidity, both from 0 to 1.
+    pub fn from_climate(temperature: f64, humidity: f64) -> Biome {
+        if temperature < 0.3 {
+            Biome::Tundra
+        } else if humidity > 0.65 {
+            Biome::Marsh
+        } else if temperature > 0.7 && humidity < 0.35 {
+            Biome::Badlands
+        } else {
+            Biome::Plains
+        }
+    }
+
+    /// The block on top of each column.
+    pub fn surface_block(&self) -> BlockStateID {
+        match *self {
+            Biome::Tundra => STONE,
+            Biome::Plains | Biome::Marsh => GRASS,
+            Biome::Badlands => DIRT,
+        }
+    }
+
+    /// How many blocks of dirt are between the surface block and the stone. At most MAX_DIRT_DEPTH.
+    pub fn dirt_depth(&self) -> i32 {
+        match *self {
+            Biome::Tundra => 0,
+            Biome::Plains => 3,
+            Biome::Marsh => 6,
+            Biome::Badlands => 4,
+        }
+    }
+}
+
+/// One column of terrain: where the ground ends, and what it's made of.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TerrainColumn {
+    /// Y of the highest solid block.
+    pub height: i32,
+    pub biome: Biome,
+}
+
+/// Temperature and humidity maps. Shared by the stages which shape the land and pick its biomes,
+/// which each build their own copy from the same seed.
+pub struct Climate {
+    temperature: FractalNoise,
+    humidity: FractalNoise,
+}
+
+impl Climate {
+    pub fn new(seed: u64) -> Climate {
+        Climate {
+            temperature: FractalNoise::new(seed, TEMPERATURE_LAYER, 3, 0.002917),
+            humidity: FractalNoise::new(seed, HUMIDITY_LAYER, 3, 0.003391),
+        }
+    }
+
+    /// Humidity of the column at (x, z), from 0 to 1.
+    pub fn humidity(&self, x: i32, z: i32) -> f64 {
+        self.humidity.get_normalized(x as f64, z as f64)
+    }
+
+    /// Which biome the column at (x, z) is, given where its ground is. High ground is colder.
+    pub fn biome(&self, x: i32, z: i32, height: i32) -> Biome {
+        let temperature = self.temperature.get_normalized(x as f64, z as f64) - (height as f64 - BASE_HEIGHT) * TEMPERATURE_LAPSE;
+        Biome::from_climate(temperature, self.humidity(x, z))
+    }
+}
+
+/// Base stage which layers fractal noise for continents, hills and small detail, filling everything
+/// below the ground with stone. Every column only depends on its own X and Z, so chunks always line up with their neighbors.
+/// Use terrain_pipeline() to get this with biomes on top.
+pub struct TerrainGenerator {
+    continents: FractalNoise,
+    hills: FractalNoise,
+    detail: FractalNoise,
+    climate: Climate,
+}
+
+impl TerrainGenerator {
+    /// Creates a new `TerrainGenerator`
+    pub fn new(seed: u64) -> TerrainGenerator {
+        TerrainGenerator {
+            continents: FractalNoise::new(seed, CONTINENT_LAYER, 4, 0.001953),
+            hills: FractalNoise::new(seed, HILL_LAYER, 4, 0.008126),
+            detail: FractalNoise::new(seed, DETAIL_LAYER, 2, 0.0631),
+            climate: Climate::new(seed),
+        }
+    }
+
+    /// Y of the highest solid block in the column at (x, z), in world coordinates.
+    pub fn height(&self, x: i32, z: i32) -> i32 {
+        // Wet land is flat land. This changes smoothly with humidity, so biome borders never turn into cliffs.
+        let hilliness = 1.0 - (self.climate.humidity(x, z) * 0.8);
+        let (x, z) = (x as f64, z as f64);
+        let height = BASE_HEIGHT
+            + self.continents.get(x, z) * CONTINENT_AMPLITUDE
+            + self.hills.get(x, z) * HILL_AMPLITUDE * hilliness
+            + self.detail.get(x, z) * DETAIL_AMPLITUDE;
+        height.floor() as i32
+    }
+
+    /// Works out the terrain at the column (x, z), in world coordinates.
+    pub fn column(&self, x: i32, z: i32) -> TerrainColumn {
+        let height = self.height(x, z);
+        TerrainColumn {
+            height: height,
+            biome: self.climate.biome(x, z, height),
+        }
+    }
+}
+
+impl GeneratorStage for TerrainGenerator {
+    fn kind(&self) -> StageKind { StageKind::Base }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        let bounds = context.bounds;
+        let size = bounds.get_size();
+
+        let num_elements = (size.x * size.y * size.z) as usize;
+        let mut data : Vec<BlockStateID> = vec![AIR; num_elements];
+
+        for x in 0..size.x {
+            for z in 0..size.z {
+                let height = self.height(bounds.lower.x + x, bounds.lower.z + z);
+                for y in 0..size.y {
+                    if bounds.lower.y + y <= height {
+                        data[xyz_to_i(x as usize, y as usize, z as usize,
+                                        size.x as usize, size.y as usize, size.z as usize)] = STONE;
+                    }
+                }
+            }
+        }
+        *chunk = CompactVoxelArray::load_new(size.x as u8, size.y as u8, size.z as u8, data);
+    }
+
+    fn surface(&self, x: i32, z: i32, _so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> {
+        Some((self.height(x, z), STONE))
+    }
+}
+
+/// Surface stage which covers the land in whatever its biome calls for.
+pub struct BiomeSurfaceStage {
+    climate: Climate,
+}
+
+impl BiomeSurfaceStage {
+    /// seed must be the same one the TerrainGenerator was given, so we agree on the climate.
+    pub fn new(seed: u64) -> BiomeSurfaceStage {
+        BiomeSurfaceStage { climate: Climate::new(seed) }
+    }
+}
+
+impl GeneratorStage for BiomeSurfaceStage {
+    fn kind(&self) -> StageKind { StageKind::Surface }
+
+    fn apply(&self, context: &StageContext, chunk: &mut Chunk) {
+        let bounds = context.bounds;
+        for x in bounds.lower.x..bounds.upper.x {
+            for z in bounds.lower.z..bounds.upper.z {
+                let height = match context.surface.surface_height(x, z) {
+                    Some(height) => height,
+                    None => continue,
+                };
+                // Skip columns whose surface layers are nowhere near this chunk.
+                if height < bounds.lower.y || height - MAX_DIRT_DEPTH >= bounds.upper.y {
+                    continue;
+                }
+                let biome = self.climate.biome(x, z, height);
+                replace_solid(bounds, chunk, vpos!(x, height, z), biome.surface_block());
+                for depth in 1..(biome.dirt_depth() + 1) {
+                    replace_solid(bounds, chunk, vpos!(x, height - depth, z), DIRT);
+                }
+            }
+        }
+    }
+
+    fn surface(&self, x: i32, z: i32, so_far: Option<(i32, BlockStateID)>) -> Option<(i32, BlockStateID)> {
+        so_far.map(|(height, _)| (height, self.climate.biome(x, z, height).surface_block()))
+    }
+}
+
+/// Terrain with biomes: a TerrainGenerator and a BiomeSurfaceStage. Add caves, decorations and so on to taste.
+pub fn terrain_pipeline(seed: u64) -> GeneratorPipeline {
+    GeneratorPipeline::new(seed)
+        .with_stage(Box::new(TerrainGenerator::new(seed)))
+        .with_stage(Box::new(BiomeSurfaceStage::new(seed)))
+}
+
+#[cfg(test)]
+use world::generators::WorldGenerator;
+
+#[test]
+fn test_terrain_seams() {
+    use voxel::voxelstorage::*;
+
+    let generator = terrain_pipeline(99);
+    let size = vpos!(16, 16, 16);
+    let left = generator.generate(VoxelRange::new_origin_size(vpos!(-16, -8, 0), size), 0);
+    let right = generator.generate(VoxelRange::new_origin_size(vpos!(0, -8, 0), size), 0);
+    // A chunk straddling the border between the other two must agree with both of them.
+    let middle = generator.generate(VoxelRange::new_origin_size(vpos!(-8, -8, 0), size), 0);
+    for x in 0..16u8 {
+        for y in 0..16u8 {
+            for z in 0..16u8 {
+                let expected = if x < 8 { left.get(vpos!(x + 8, y, z)) } else { right.get(vpos!(x - 8, y, z)) };
+                assert_eq!(middle.get(vpos!(x, y, z)).unwrap(), expected.unwrap());
+            }
+        }
+    }
+}
+
+#[test]
+fn test_terrain_layers() {
+    use voxel::voxelstorage::*;
+
+    let terrain = TerrainGenerator::new(5);
+    let generator = terrain_pipeline(5);
+    for &(x, z) in [(0, 0), (100, -37), (-2000, 1500), (4321, 8765)].iter() {
+        let column = terrain.column(x, z);
+        let bounds = VoxelRange::new_origin_size(vpos!(x, column.height - 10, z), vpos!(1, 12, 1));
+        let chunk = generator.generate(bounds, 0);
+        // bounds.lower.y + 10 is the surface.
+        let block_at_depth = |depth: i32| chunk.get(vpos!(0, (10 - depth) as u8, 0)).unwrap();
+        assert_eq!(block_at_depth(-1), AIR);
+        assert_eq!(block_at_depth(0), column.biome.surface_block());
+        for depth in 1..(column.biome.dirt_depth() + 1) {
+            assert_eq!(block_at_depth(depth), DIRT);
+        }
+        assert_eq!(block_at_depth(column.biome.dirt_depth() + 1), STONE);
+    }
+}
+
+#[test]
+fn test_biome_from_climate() {
+    assert_eq!(Biome::from_climate(0.1, 0.9), Biome::Tundra);
+    assert_eq!(Biome::from_climate(0.5, 0.9), Biome::Marsh);
+    assert_eq!(Biome::from_climate(0.9, 0.1), Biome::Badlands);
+    assert_eq!(Biome::from_climate(0.5, 0.5), Biome::Plains);
+}
+
+#[test]
+fn test_terrain_deterministic() {
+    use world::generators::chunk_hash;
+
+    let bounds = VoxelRange::new_origin_size(vpos!(320, -16, -48), vpos!(32, 32, 32));
+    // Pinned, so a change to any stage (or to how seeds are split between them) that alters worlds shows up here.
+    let expected = 0xCDA9_25C3_80E2_EAD5;
+    assert_eq!(chunk_hash(&terrain_pipeline(42).generate(bounds, 0)), expected);
+    assert_ne!(chunk_hash(&terrain_pipeline(43).generate(bounds, 0)), expected);
+}
+
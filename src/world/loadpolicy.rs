@@ -0,0 +1,146 @@
+//! Which chunks a dimension keeps loaded around its players.
+
+use voxel::voxelmath::*;
+
+/// The shape of the area loaded around each player.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LoadShape {
+    /// A box, horizontal_radius chunks out in x and z and vertical_radius chunks up and down.
+    Cube,
+    /// An ellipsoid, horizontal_radius wide and vertical_radius tall.
+    Sphere,
+    /// A vertical cylinder, horizontal_radius wide and extending vertical_radius chunks up and down.
+    Cylinder,
+}
+
+/// Decides which chunks are loaded around a player. All distances are in chunks, measured
+/// from the chunk the player is standing in, so this works with any chunk size.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChunkLoadPolicy {
+    pub horizontal_radius: u32,
+    pub vertical_radius: u32,
+    pub shape: LoadShape,
+    /// Chunks stay loaded until they are this many chunks beyond the load radius, so walking
+    /// back and forth over a chunk boundary doesn't load and unload the same chunks over and over.
+    pub retain_margin: u32,
+}
+
+impl Default for ChunkLoadPolicy {
+    fn default() -> Self {
+        ChunkLoadPolicy {
+            horizontal_radius: 2,
+            vertical_radius: 2,
+            shape: LoadShape::Cube,
+            retain_margin: 1,
+        }
+    }
+}
+
+/// (offset / radius)^2, treating a radius of 0 as "only offset 0 fits."
+fn axis_term(offset: i32, radius: u32) -> f32 {
+    if radius == 0 {
+        if offset == 0 { 0.0 } else { ::std::f32::INFINITY }
+    } else {
+        let ratio = offset as f32 / radius as f32;
+        ratio * ratio
+    }
+}
+
+impl ChunkLoadPolicy {
+    /// Is a chunk this far away from the player's chunk inside our shape, grown by extra chunks in every direction?
+    fn contains(&self, offset: VoxelPos<i32>, extra: u32) -> bool {
+        let horizontal = self.horizontal_radius + extra;
+        let vertical = self.vertical_radius + extra;
+        let horizontal_term = axis_term(offset.x, horizontal) + axis_term(offset.z, horizontal);
+        match self.shape {
+            LoadShape::Cube => (offset.x.abs() as u32 <= horizontal) && (offset.z.abs() as u32 <= horizontal)
+                                && (offset.y.abs() as u32 <= vertical),
+            LoadShape::Sphere => horizontal_term + axis_term(offset.y, vertical) <= 1.0,
+            LoadShape::Cylinder => (horizontal_term <= 1.0) && (offset.y.abs() as u32 <= vertical),
+        }
+    }
+
+    /// Should a chunk be loaded for a player standing in center_chunk?
+    pub fn should_load(&self, center_chunk: VoxelPos<i32>, chunk: VoxelPos<i32>) -> bool {
+        self.contains(chunk - center_chunk, 0)
+    }
+
+    /// Should an already-loaded chunk be kept around for a player standing in center_chunk?
+    pub fn should_retain(&self, center_chunk: VoxelPos<i32>, chunk: VoxelPos<i32>) -> bool {
+        self.contains(chunk - center_chunk, self.retain_margin)
+    }
+
+    /// Every chunk which should be loaded for a player standing in center_chunk.
+    pub fn chunks_to_load(&self, center_chunk: VoxelPos<i32>) -> Vec<VoxelPos<i32>> {
+        let h = self.horizontal_radius as i32;
+        let v = self.vertical_radius as i32;
+        let mut result = Vec::new();
+        for x in -h..(h + 1) {
+            for y in -v..(v + 1) {
+                for z in -h..(h + 1) {
+                    let offset = vpos!(x, y, z);
+                    if self.contains(offset, 0) {
+                        result.push(center_chunk + offset);
+                    }
+                }
+            }
+        }
+        result
+    }
+}
+
+#[test]
+fn test_load_policy_sphere() {
+    let policy = ChunkLoadPolicy { horizontal_radius: 1, vertical_radius: 1, shape: LoadShape::Sphere, retain_margin: 1 };
+    let center = vpos!(10, -3, 4);
+    let mut loaded = policy.chunks_to_load(center);
+    loaded.sort_by_key(|pos| (pos.x, pos.y, pos.z));
+    // The center and its six face neighbors.
+    assert_eq!(loaded, vec![vpos!(9, -3, 4), vpos!(10, -4, 4), vpos!(10, -3, 3), vpos!(10, -3, 4),
+                            vpos!(10, -3, 5), vpos!(10, -2, 4), vpos!(11, -3, 4)]);
+    assert!(!policy.should_load(center, vpos!(11, -2, 4)));
+    // ...but diagonal neighbors are inside the retain margin.
+    assert!(policy.should_retain(center, vpos!(11, -2, 4)));
+    assert!(policy.should_retain(center, vpos!(12, -3, 4)));
+    assert!(!policy.should_retain(center, vpos!(13, -3, 4)));
+}
+
+#[test]
+fn test_load_policy_cylinder() {
+    let policy = ChunkLoadPolicy { horizontal_radius: 2, vertical_radius: 1, shape: LoadShape::Cylinder, retain_margin: 0 };
+    let center = vpos!(0, 0, 0);
+    let loaded = policy.chunks_to_load(center);
+    // 13 columns fit in a circle of radius 2, each 3 chunks tall.
+    assert_eq!(loaded.len(), 13 * 3);
+    assert!(loaded.contains(&vpos!(2, 1, 0)));
+    assert!(loaded.contains(&vpos!(-1, -1, -1)));
+    assert!(!loaded.contains(&vpos!(2, 0, 1)));
+    assert!(!loaded.contains(&vpos!(0, 2, 0)));
+    for pos in loaded.iter() {
+        assert!(policy.should_retain(center, *pos));
+    }
+    assert!(!policy.should_retain(center, vpos!(0, -2, 0)));
+}
+
+#[test]
+fn test_load_policy_zero_radius() {
+    let policy = ChunkLoadPolicy { horizontal_radius: 0, vertical_radius: 3, shape: LoadShape::Sphere, retain_margin: 0 };
+    let mut loaded = policy.chunks_to_load(vpos!(0, 0, 0));
+    loaded.sort_by_key(|pos| pos.y);
+    assert_eq!(loaded, vec![vpos!(0, -3, 0), vpos!(0, -2, 0), vpos!(0, -1, 0), vpos!(0, 0, 0),
+                            vpos!(0, 1, 0), vpos!(0, 2, 0), vpos!(0, 3, 0)]);
+}
+
+#[test]
+fn test_load_policy_default_cube() {
+    let policy = ChunkLoadPolicy::default();
+    let center = vpos!(0, 0, 0);
+    let loaded = policy.chunks_to_load(center);
+    // Every chunk up to 2 away on each axis, like the loader had before there were policies.
+    assert_eq!(loaded.len(), 5 * 5 * 5);
+    assert!(loaded.contains(&vpos!(2, 2, 2)));
+    assert!(loaded.contains(&vpos!(-2, -2, 2)));
+    assert!(!loaded.contains(&vpos!(3, 0, 0)));
+    assert!(policy.should_retain(center, vpos!(3, -3, 3)));
+    assert!(!policy.should_retain(center, vpos!(0, 4, 0)));
+}
@@ -3,7 +3,12 @@
 pub mod generators;
 
 pub mod dimension;
+pub mod chunkgen;
+pub mod loadpolicy;
+pub mod chunkpipeline;
 pub mod block;
+pub mod blockmodel;
+pub mod storage;
 
-pub use self::block::{BlockID, BlockName};
+pub use self::block::{BlockName, BlockStateID};
 pub use self::dimension::Dimension;
@@ -0,0 +1,493 @@
+//! On-disk world persistence.
+//!
+//! A world directory looks like this:
+//!
+//! ```text
+//! world/
+//!     world.json          WorldInfo - seed.
+//!     blocks.json         BlockTable - which block state each ID in our saved chunks stands for.
+//!     dim0/
+//!         dimension.json  DimensionInfo - name, chunk size, generator.
+//!         r.0.0.0.vxr     One region file per REGION_SIZE^3 chunks.
+//!         r.-1.0.0.vxr
+//! ```
+//!
+//! A region file is a small header, a table of (offset, length) pairs with one entry per
+//! chunk in the region, and then the chunks themselves in the format written by
+//! VoxelStorageIOAble. A length of 0 means that chunk has never been saved.
+//!
+//! Chunks are saved with the world's own block state IDs rather than our BlockRegistry's, which can change
+//! whenever blocks are added to or removed from the game. A saved ID always means the same block state.
+
+extern crate serde_json;
+
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::io::{Read, Write, Seek, SeekFrom, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+
+use serde::{Serialize, Deserialize};
+
+use voxel::voxelmath::*;
+use voxel::voxelstorage::{VoxelError, VoxelStorageIOAble};
+use voxel::voxelio::PayloadReader;
+use world::block::{Chunk, BlockStateID, BlockName, BlockRegistry};
+
+/// Width of a region, in chunks, along each axis.
+pub const REGION_SIZE : i32 = 8;
+const CHUNKS_PER_REGION : usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+const REGION_MAGIC : [u8; 4] = *b"VXRG";
+const REGION_FORMAT_VERSION : u16 = 1;
+/// Magic, version, and then an offset and a length (both u32) per chunk.
+const REGION_HEADER_LENGTH : usize = 4 + 2 + (CHUNKS_PER_REGION * 8);
+
+const WORLD_INFO_FILE : &str = "world.json";
+const DIMENSION_INFO_FILE : &str = "dimension.json";
+const BLOCK_TABLE_FILE : &str = "blocks.json";
+
+/// Settings which have to stay the same for the whole lifetime of a world.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldInfo {
+    pub seed : u64,
+}
+
+/// Settings which have to stay the same for the whole lifetime of one of a world's dimensions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DimensionInfo {
+    pub name : String,
+    pub chunk_size : VoxelSize<u32>,
+    /// Which generator makes this dimension's terrain, as a GeneratorRegistry spec. Empty means the dimension's default.
+    #[serde(default)]
+    pub generator : String,
+}
+
+fn default_placeholder() -> BlockName { BlockName::from("missing") }
+
+/// Which block state (by name, see BlockRegistry::state_name()) each ID in a world's saved chunks stands for.
+/// IDs are only ever added to this, never changed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockTable {
+    /// What blocks which don't exist anymore turn into when they're loaded. Can be edited.
+    #[serde(default = "default_placeholder")]
+    pub placeholder : BlockName,
+    pub ids : HashMap<BlockName, BlockStateID>,
+}
+
+impl BlockTable {
+    /// A table which saves every block state in blocks with the ID it has there.
+    pub fn new(blocks: &BlockRegistry) -> BlockTable {
+        BlockTable { placeholder : default_placeholder(), ids : blocks.all_mappings() }
+    }
+}
+
+/// Translates between the IDs a world's chunks are saved with and the IDs our BlockRegistry uses.
+#[derive(Clone, Debug)]
+pub struct BlockIdMap {
+    /// Current ID for each saved ID.
+    to_current : Vec<BlockStateID>,
+    /// Saved ID for each current ID. None for IDs which aren't states any more.
+    to_saved : Vec<Option<BlockStateID>>,
+    /// What saved IDs we have no block for become.
+    placeholder : BlockStateID,
+}
+
+impl BlockIdMap {
+    /// Builds the map for a world's table, first giving any of our block states which aren't in it yet new saved IDs.
+    /// Returns whether the table changed. Saved states whose block has lost the property values they had load as
+    /// that block's default state, and ones whose block is gone entirely load as the table's placeholder.
+    pub fn new(table: &mut BlockTable, blocks: &BlockRegistry) -> Result<(BlockIdMap, bool), Box<dyn Error>> {
+        let placeholder = blocks.state_for_name(&table.placeholder)
+            .ok_or_else(|| format!("The placeholder for missing blocks, {}, isn't a block", table.placeholder))?;
+        let mut changed = false;
+        let mut current : Vec<(BlockName, BlockStateID)> = blocks.all_mappings().into_iter().collect();
+        current.sort_by_key(|(_, id)| *id);
+        for (name, _) in current.iter() {
+            if !table.ids.contains_key(name) {
+                let next_id = table.ids.values().max().map_or(0, |id| id + 1);
+                table.ids.insert(name.clone(), next_id);
+                changed = true;
+            }
+        }
+
+        let saved_count = table.ids.values().max().map_or(0, |id| id + 1) as usize;
+        let mut to_current = vec![placeholder; saved_count];
+        let mut to_saved = vec![None; current.last().map_or(0, |(_, id)| *id as usize + 1)];
+        for (name, saved_id) in table.ids.iter() {
+            match blocks.state_for_name(name) {
+                Some(id) => {
+                    to_current[*saved_id as usize] = id;
+                    to_saved[id as usize] = Some(*saved_id);
+                },
+                None => {
+                    let block = name.split('[').next().and_then(|block| blocks.id_for_name(block));
+                    match block {
+                        Some(block) => to_current[*saved_id as usize] = blocks.default_state(block),
+                        None => warn!("Block {} isn't around anymore. It will be loaded as {}.", name, table.placeholder),
+                    }
+                },
+            }
+        }
+        Ok((BlockIdMap { to_current : to_current, to_saved : to_saved, placeholder : placeholder }, changed))
+    }
+
+    pub fn to_current(&self, saved_id: BlockStateID) -> BlockStateID {
+        self.to_current.get(saved_id as usize).cloned().unwrap_or(self.placeholder)
+    }
+
+    pub fn to_saved(&self, id: BlockStateID) -> BlockStateID {
+        // Every state we know about is in the table, so anything else can only be garbage.
+        self.to_saved.get(id as usize).cloned().unwrap_or(None)
+            .unwrap_or_else(|| self.to_saved[self.placeholder as usize].unwrap())
+    }
+
+    /// Does every block state have the same ID on disk as in memory?
+    fn is_identity(&self) -> bool {
+        self.to_current.iter().enumerate().all(|(saved_id, id)| saved_id as BlockStateID == *id)
+            && self.to_saved.iter().enumerate().all(|(id, saved_id)| saved_id.map_or(true, |saved_id| id as BlockStateID == saved_id))
+    }
+}
+
+/// Reads a JSON file if it exists, otherwise writes new_value to it. Returns whichever one we ended up with.
+fn read_or_create_json<T>(path: &Path, new_value: T) -> Result<T, Box<dyn Error>> where T : Serialize + for<'de> Deserialize<'de> {
+    if path.exists() {
+        return Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?);
+    }
+    write_json(path, &new_value)?;
+    Ok(new_value)
+}
+
+/// Writes value to a JSON file. Like region files, it's written to a temporary file which then replaces
+/// the real one, so a crash partway through leaves the old file rather than a cut-off one.
+fn write_json<T : Serialize>(path: &Path, value: &T) -> Result<(), Box<dyn Error>> {
+    let temp_path = path.with_extension("json.tmp");
+    {
+        let mut file = BufWriter::new(File::create(&temp_path)?);
+        serde_json::to_writer_pretty(&mut file, value)?;
+        file.flush()?;
+    }
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// A world directory on disk.
+pub struct WorldStorage {
+    path : PathBuf,
+    pub info : WorldInfo,
+    block_ids : BlockIdMap,
+}
+
+impl WorldStorage {
+    /// Opens the world at path, or creates a new one there with the provided WorldInfo if there isn't one yet.
+    /// When opening an existing world, new_info is ignored in favor of what's on disk.
+    /// Chunks are loaded with (and saved from) the IDs blocks has for them.
+    pub fn open_or_create<P: AsRef<Path>>(path: P, new_info: WorldInfo, blocks: &BlockRegistry) -> Result<WorldStorage, Box<dyn Error>> {
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path)?;
+        let info = read_or_create_json(&path.join(WORLD_INFO_FILE), new_info)?;
+        // Worlds from before we saved block tables used the IDs blocks had back then, which are still the built-in blocks' IDs.
+        let table_path = path.join(BLOCK_TABLE_FILE);
+        let mut table = read_or_create_json(&table_path, BlockTable::new(blocks))?;
+        let (block_ids, changed) = BlockIdMap::new(&mut table, blocks)?;
+        if changed {
+            write_json(&table_path, &table)?;
+        }
+        info!("Opened world at {} (seed {})", path.display(), info.seed);
+        Ok(WorldStorage { path : path, info : info, block_ids : block_ids })
+    }
+
+    /// Gets the region store holding the chunks of a given dimension, and that dimension's settings.
+    /// As with worlds, new_info is only used if this dimension hasn't been saved before.
+    pub fn open_dimension(&self, dimension_id: u32, new_info: DimensionInfo) -> Result<(RegionStore, DimensionInfo), Box<dyn Error>> {
+        let store = RegionStore::new(self.path.join(format!("dim{}", dimension_id)))?.with_block_ids(self.block_ids.clone());
+        let info = read_or_create_json(&store.path.join(DIMENSION_INFO_FILE), new_info)?;
+        Ok((store, info))
+    }
+}
+
+fn floor_div(a : i32, b : i32) -> i32 {
+    let d = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) { d - 1 } else { d }
+}
+
+/// Which region a chunk lives in.
+pub fn chunk_to_region(chunk_pos : VoxelPos<i32>) -> VoxelPos<i32> {
+    vpos!(floor_div(chunk_pos.x, REGION_SIZE), floor_div(chunk_pos.y, REGION_SIZE), floor_div(chunk_pos.z, REGION_SIZE))
+}
+
+/// Index of a chunk's entry in its region's table.
+fn index_in_region(chunk_pos : VoxelPos<i32>) -> usize {
+    let region = chunk_to_region(chunk_pos);
+    let local = chunk_pos - vpos!(region.x * REGION_SIZE, region.y * REGION_SIZE, region.z * REGION_SIZE);
+    (local.x + (local.y * REGION_SIZE) + (local.z * REGION_SIZE * REGION_SIZE)) as usize
+}
+
+fn io_error(error : std::io::Error) -> VoxelError { VoxelError::Other(Box::new(error)) }
+
+/// A directory of region files, keyed by chunk position.
+pub struct RegionStore {
+    path : PathBuf,
+    /// How to translate block IDs when loading and saving. None saves them as they are.
+    block_ids : Option<BlockIdMap>,
+}
+
+impl RegionStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<RegionStore, Box<dyn Error>> {
+        fs::create_dir_all(path.as_ref())?;
+        Ok(RegionStore { path : path.as_ref().to_path_buf(), block_ids : None })
+    }
+
+    /// Translates block IDs with block_ids when loading and saving.
+    pub fn with_block_ids(mut self, block_ids: BlockIdMap) -> RegionStore {
+        self.block_ids = if block_ids.is_identity() { None } else { Some(block_ids) };
+        self
+    }
+
+    fn region_path(&self, region : VoxelPos<i32>) -> PathBuf {
+        self.path.join(format!("r.{}.{}.{}.vxr", region.x, region.y, region.z))
+    }
+
+    /// Reads and validates a region's offset table.
+    fn read_table<R: Read>(reader : &mut R) -> Result<Vec<(u32, u32)>, VoxelError> {
+        let mut header = vec![0u8; REGION_HEADER_LENGTH];
+        reader.read_exact(&mut header).map_err(|e| VoxelError::CorruptData(format!("Could not read region header: {}", e)))?;
+        let mut header_reader = PayloadReader::new(&header);
+        if header_reader.read_slice(4)? != REGION_MAGIC {
+            return Err(VoxelError::CorruptData("Region file has the wrong magic number.".to_owned()));
+        }
+        let version = header_reader.read_u16()?;
+        if version != REGION_FORMAT_VERSION {
+            return Err(VoxelError::CorruptData(format!("Unsupported region format version {}, expected {}.", version, REGION_FORMAT_VERSION)));
+        }
+        let mut table = Vec::with_capacity(CHUNKS_PER_REGION);
+        for _ in 0..CHUNKS_PER_REGION {
+            table.push((header_reader.read_u32()?, header_reader.read_u32()?));
+        }
+        Ok(table)
+    }
+
+    /// Loads a chunk from disk. Returns Ok(None) if this chunk has never been saved.
+    pub fn load_chunk(&self, chunk_pos : VoxelPos<i32>) -> Result<Option<Chunk>, VoxelError> {
+        let path = self.region_path(chunk_to_region(chunk_pos));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let mut file = BufReader::new(File::open(&path).map_err(io_error)?);
+        let table = RegionStore::read_table(&mut file)?;
+        let (offset, length) = table[index_in_region(chunk_pos)];
+        if length == 0 {
+            return Ok(None);
+        }
+        file.seek(SeekFrom::Start(offset as u64)).map_err(io_error)?;
+        let mut chunk_reader = file.take(length as u64);
+        let mut chunk = Chunk::load(&mut chunk_reader)?;
+        if let Some(ref block_ids) = self.block_ids {
+            chunk.map_values(|saved_id| block_ids.to_current(*saved_id));
+        }
+        Ok(Some(chunk))
+    }
+
+    /// Saves a batch of chunks, rewriting each region file they touch once.
+    pub fn save_chunks(&self, chunks : Vec<(VoxelPos<i32>, &Chunk)>) -> Result<(), VoxelError> {
+        let mut by_region : HashMap<VoxelPos<i32>, Vec<(usize, Vec<u8>)>> = HashMap::new();
+        for (chunk_pos, chunk) in chunks {
+            let mut bytes = Vec::new();
+            match self.block_ids {
+                Some(ref block_ids) => {
+                    let mut saved = chunk.clone();
+                    saved.map_values(|id| block_ids.to_saved(*id));
+                    saved.save(&mut bytes)?;
+                },
+                None => { chunk.save(&mut bytes)?; },
+            }
+            by_region.entry(chunk_to_region(chunk_pos)).or_insert(Vec::new()).push((index_in_region(chunk_pos), bytes));
+        }
+        for (region, new_entries) in by_region {
+            self.write_region(region, new_entries)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_chunk(&self, chunk_pos : VoxelPos<i32>, chunk : &Chunk) -> Result<(), VoxelError> {
+        self.save_chunks(vec![(chunk_pos, chunk)])
+    }
+
+    /// Rewrites a region file with some of its entries replaced. Writes to a temporary file
+    /// first so a crash halfway through can't take the rest of the region down with it.
+    fn write_region(&self, region : VoxelPos<i32>, new_entries : Vec<(usize, Vec<u8>)>) -> Result<(), VoxelError> {
+        let path = self.region_path(region);
+        let mut entries : Vec<Option<Vec<u8>>> = vec![None; CHUNKS_PER_REGION];
+        if path.exists() {
+            let mut file = BufReader::new(File::open(&path).map_err(io_error)?);
+            let table = RegionStore::read_table(&mut file)?;
+            let mut rest = Vec::new();
+            file.read_to_end(&mut rest).map_err(io_error)?;
+            for (i, (offset, length)) in table.iter().enumerate() {
+                if *length == 0 { continue; }
+                let start = (*offset as usize).checked_sub(REGION_HEADER_LENGTH);
+                match start {
+                    Some(start) if start + (*length as usize) <= rest.len() => {
+                        entries[i] = Some(rest[start .. start + (*length as usize)].to_vec());
+                    },
+                    _ => return Err(VoxelError::CorruptData(format!("Region file {} has a chunk entry pointing outside of the file.", path.display()))),
+                }
+            }
+        }
+        for (i, bytes) in new_entries {
+            entries[i] = Some(bytes);
+        }
+
+        let mut table_bytes = Vec::with_capacity(REGION_HEADER_LENGTH);
+        table_bytes.extend_from_slice(&REGION_MAGIC);
+        table_bytes.extend_from_slice(&REGION_FORMAT_VERSION.to_le_bytes());
+        let mut offset = REGION_HEADER_LENGTH as u32;
+        for entry in entries.iter() {
+            let length = match entry {
+                Some(bytes) => bytes.len() as u32,
+                None => 0,
+            };
+            table_bytes.extend_from_slice(&(if length == 0 { 0 } else { offset }).to_le_bytes());
+            table_bytes.extend_from_slice(&length.to_le_bytes());
+            offset += length;
+        }
+
+        let temp_path = path.with_extension("vxr.tmp");
+        {
+            let mut file = BufWriter::new(File::create(&temp_path).map_err(io_error)?);
+            file.write_all(&table_bytes).map_err(io_error)?;
+            for entry in entries.iter() {
+                if let Some(bytes) = entry {
+                    file.write_all(bytes).map_err(io_error)?;
+                }
+            }
+            file.flush().map_err(io_error)?;
+        }
+        fs::rename(&temp_path, &path).map_err(io_error)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_region_index() {
+    assert_eq!(chunk_to_region(vpos!(0, 7, -1)), vpos!(0, 0, -1));
+    assert_eq!(chunk_to_region(vpos!(-8, -9, 8)), vpos!(-1, -2, 1));
+    assert_eq!(index_in_region(vpos!(0, 0, 0)), 0);
+    assert_eq!(index_in_region(vpos!(-1, 0, 0)), 7);
+    assert_eq!(index_in_region(vpos!(7, 7, 7)), CHUNKS_PER_REGION - 1);
+}
+
+#[cfg(test)]
+use voxel::voxelstorage::VoxelStorage;
+
+#[test]
+fn test_region_round_trip() {
+    let dir = std::env::temp_dir().join(format!("voxelgame_region_test_{}", std::process::id()));
+    let store = RegionStore::new(&dir).unwrap();
+    let mut chunk_a = Chunk::new_solid(16, 16, 16, 1);
+    chunk_a.set(vpos!(1, 2, 3), 2).unwrap();
+    let chunk_b = Chunk::new_solid(16, 16, 16, 3);
+
+    // Two chunks in the same region, one in a different one, saved in separate batches.
+    store.save_chunks(vec![(vpos!(0, 0, 0), &chunk_a), (vpos!(-1, 0, 0), &chunk_b)]).unwrap();
+    store.save_chunk(vpos!(1, 0, 0), &chunk_b).unwrap();
+    assert!(store.load_chunk(vpos!(2, 0, 0)).unwrap().is_none());
+    assert!(store.load_chunk(vpos!(0, 50, 0)).unwrap().is_none());
+
+    let loaded_a = store.load_chunk(vpos!(0, 0, 0)).unwrap().unwrap();
+    assert_eq!(loaded_a.get(vpos!(1, 2, 3)).unwrap(), 2);
+    assert_eq!(loaded_a.get(vpos!(3, 2, 1)).unwrap(), 1);
+    assert_eq!(store.load_chunk(vpos!(1, 0, 0)).unwrap().unwrap().uniform_value(), Some(&3));
+    assert_eq!(store.load_chunk(vpos!(-1, 0, 0)).unwrap().unwrap().uniform_value(), Some(&3));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_world_info_persists() {
+    let dir = std::env::temp_dir().join(format!("voxelgame_world_test_{}", std::process::id()));
+    let world = WorldStorage::open_or_create(&dir, WorldInfo { seed : 7 }, &BlockRegistry::new()).unwrap();
+    let overworld = DimensionInfo { name : "Overworld".to_owned(), chunk_size : vpos!(16, 16, 16), generator : "flat:stone*4".to_owned() };
+    world.open_dimension(0, overworld.clone()).unwrap();
+
+    // Reopening keeps what was saved, whatever we ask for.
+    let world = WorldStorage::open_or_create(&dir, WorldInfo { seed : 8 }, &BlockRegistry::new()).unwrap();
+    assert_eq!(world.info.seed, 7);
+    let (_, info) = world.open_dimension(0, DimensionInfo { name : "Other".to_owned(), chunk_size : vpos!(8, 8, 8), generator : "void".to_owned() }).unwrap();
+    assert_eq!(info, overworld);
+
+    // Dimensions saved before we recorded their generator get the default one.
+    fs::create_dir_all(dir.join("dim1")).unwrap();
+    fs::write(dir.join("dim1").join(DIMENSION_INFO_FILE), r#"{"name":"Old","chunk_size":{"x":16,"y":16,"z":16}}"#).unwrap();
+    let (_, info) = world.open_dimension(1, overworld.clone()).unwrap();
+    assert_eq!(info.name, "Old");
+    assert_eq!(info.generator, "");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_block_ids_remapped() {
+    use world::block::{BlockDef, STONE, MISSING};
+
+    let dir = std::env::temp_dir().join(format!("voxelgame_block_table_test_{}", std::process::id()));
+    let dimension = DimensionInfo { name : "Overworld".to_owned(), chunk_size : vpos!(4, 4, 4), generator : "void".to_owned() };
+    let mut before = BlockRegistry::new();
+    let glass = before.define(BlockDef::new("glass"));
+    let sand = before.define(BlockDef::new("sand"));
+    let mut lamp = BlockDef::new("lamp");
+    lamp.properties.insert("lit".to_owned(), vec!["false".to_owned(), "true".to_owned()]);
+    before.define(lamp);
+    let world = WorldStorage::open_or_create(&dir, WorldInfo { seed : 1 }, &before).unwrap();
+    let (store, _) = world.open_dimension(0, dimension.clone()).unwrap();
+    let mut chunk = Chunk::new_solid(4, 4, 4, STONE);
+    chunk.set(vpos!(0, 0, 0), glass).unwrap();
+    chunk.set(vpos!(1, 0, 0), sand).unwrap();
+    chunk.set(vpos!(0, 1, 0), before.state_for_name("lamp[lit=true]").unwrap()).unwrap();
+    store.save_chunk(vpos!(0, 0, 0), &chunk).unwrap();
+
+    // Sand is gone, lamps can't be lit any more, and the new blocks land where glass used to be.
+    let mut after = BlockRegistry::new();
+    let marble = after.define(BlockDef::new("marble"));
+    let lamp = after.define(BlockDef::new("lamp"));
+    let glass = after.define(BlockDef::new("glass"));
+    assert!(marble != glass);
+    let world = WorldStorage::open_or_create(&dir, WorldInfo { seed : 1 }, &after).unwrap();
+    let (store, _) = world.open_dimension(0, dimension.clone()).unwrap();
+    let loaded = store.load_chunk(vpos!(0, 0, 0)).unwrap().unwrap();
+    assert_eq!(loaded.get(vpos!(0, 0, 0)).unwrap(), glass);
+    assert_eq!(loaded.get(vpos!(1, 0, 0)).unwrap(), MISSING);
+    assert_eq!(loaded.get(vpos!(2, 0, 0)).unwrap(), STONE);
+    assert_eq!(loaded.get(vpos!(0, 1, 0)).unwrap(), after.default_state(lamp));
+    // The table got the new blocks added to it, and the temporary file it was written to is gone.
+    let table : BlockTable = serde_json::from_reader(File::open(dir.join(BLOCK_TABLE_FILE)).unwrap()).unwrap();
+    assert!(table.ids.contains_key(&BlockName::from("marble")));
+    assert!(!dir.join("blocks.json.tmp").exists());
+
+    // The placeholder can be changed in the table, but it has to be a real block.
+    let table_path = dir.join(BLOCK_TABLE_FILE);
+    let mut table : BlockTable = serde_json::from_reader(File::open(&table_path).unwrap()).unwrap();
+    table.placeholder = BlockName::from("dirt");
+    serde_json::to_writer(File::create(&table_path).unwrap(), &table).unwrap();
+    let world = WorldStorage::open_or_create(&dir, WorldInfo { seed : 1 }, &after).unwrap();
+    let (store, _) = world.open_dimension(0, dimension.clone()).unwrap();
+    assert_eq!(store.load_chunk(vpos!(0, 0, 0)).unwrap().unwrap().get(vpos!(1, 0, 0)).unwrap(), after.state_for_name("dirt").unwrap());
+    table.placeholder = BlockName::from("cheese");
+    serde_json::to_writer(File::create(&table_path).unwrap(), &table).unwrap();
+    assert!(WorldStorage::open_or_create(&dir, WorldInfo { seed : 1 }, &after).is_err());
+    table.placeholder = BlockName::from("missing");
+    serde_json::to_writer(File::create(&table_path).unwrap(), &table).unwrap();
+
+    // Marble saved under this registry still reads back as marble under the first one, now that it has a saved ID too.
+    let mut chunk = loaded.clone();
+    chunk.set(vpos!(3, 0, 0), marble).unwrap();
+    store.save_chunk(vpos!(0, 0, 0), &chunk).unwrap();
+    before.define(BlockDef::new("marble"));
+    let world = WorldStorage::open_or_create(&dir, WorldInfo { seed : 1 }, &before).unwrap();
+    let (store, _) = world.open_dimension(0, dimension.clone()).unwrap();
+    let loaded = store.load_chunk(vpos!(0, 0, 0)).unwrap().unwrap();
+    assert_eq!(loaded.get(vpos!(0, 0, 0)).unwrap(), before.state_for_name("glass").unwrap());
+    assert_eq!(loaded.get(vpos!(3, 0, 0)).unwrap(), before.state_for_name("marble").unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}